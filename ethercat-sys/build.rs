@@ -58,12 +58,15 @@ fn main() {
                 let mut numparts = parts[2].split("(");
                 let access = match numparts.next().unwrap() {
                     "EC_IO" => match name {
-                        "SEND" | "SEND_EXT" => "arg",
+                        "SEND" | "SEND_EXT" | "MASTER_DEBUG" => "arg",
                         x if x.starts_with("DOMAIN_") => "arg",
                         _ => "none",
                     },
                     "EC_IOR" => "read",
-                    "EC_IOW" => "write",
+                    "EC_IOW" => match name {
+                        "SELECT_REF_CLOCK" => "arg",
+                        _ => "write",
+                    },
                     "EC_IOWR" => "readwrite",
                     _ => unreachable!("invalid IO macro found"),
                 };
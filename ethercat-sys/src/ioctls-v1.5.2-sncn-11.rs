@@ -7,7 +7,7 @@ ioctl!(readwrite  SLAVE_SYNC_PDO_ENTRY with EC, 0x05; ec_ioctl_slave_sync_pdo_en
 ioctl!(readwrite  DOMAIN               with EC, 0x06; ec_ioctl_domain_t);
 ioctl!(readwrite  DOMAIN_FMMU          with EC, 0x07; ec_ioctl_domain_fmmu_t);
 ioctl!(readwrite  DOMAIN_DATA          with EC, 0x08; ec_ioctl_domain_data_t);
-ioctl!(none       MASTER_DEBUG         with EC, 0x09);
+ioctl!(arg        MASTER_DEBUG         with EC, 0x09);
 ioctl!(none       MASTER_RESCAN        with EC, 0x0a);
 ioctl!(write      SLAVE_STATE          with EC, 0x0b; ec_ioctl_slave_state_t);
 ioctl!(readwrite  SLAVE_SDO            with EC, 0x0c; ec_ioctl_slave_sdo_t);
@@ -33,7 +33,7 @@ ioctl!(write      SLAVE_DICT_UPLOAD    with EC, 0x7f; ec_ioctl_slave_dict_upload
 ioctl!(none       REQUEST              with EC, 0x1f);
 ioctl!(none       CREATE_DOMAIN        with EC, 0x20);
 ioctl!(readwrite  CREATE_SLAVE_CONFIG  with EC, 0x21; ec_ioctl_config_t);
-ioctl!(write      SELECT_REF_CLOCK     with EC, 0x22; u32);
+ioctl!(arg        SELECT_REF_CLOCK     with EC, 0x22; u32);
 ioctl!(read       ACTIVATE             with EC, 0x23; ec_ioctl_master_activate_t);
 ioctl!(none       DEACTIVATE           with EC, 0x24);
 ioctl!(arg        SEND                 with EC, 0x25);
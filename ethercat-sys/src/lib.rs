@@ -14,11 +14,29 @@ include!("bindings-v1.5-334c34cfd2e5.rs");
 #[cfg(all(feature = "sncn", feature = "pregenerated-bindings"))]
 include!("bindings-v1.5.2-sncn-11.rs");
 
-use ioctl_sys::{io, ioc, ioctl, ior, iorw, iow};
+use ioctl_sys::{io, ioc, ior, iorw, iow};
+use std::{marker::PhantomData, os::raw::c_ulong};
 
 pub mod ioctl {
     use super::EC_IOCTL_TYPE as EC;
     use super::*;
+    use std::os::raw::c_int;
+
+    macro_rules! ioctl {
+        // The argument is passed by value, but the code contains its size.
+        (arg $name:ident with $ioty:expr, $nr:expr; $ty:ty) => {
+            /// # Safety
+            ///
+            /// `fd` must be an open EtherCAT master device.
+            pub unsafe fn $name(fd: c_int, arg: c_ulong) -> c_int {
+                let code = iow!($ioty, $nr, ::std::mem::size_of::<$ty>());
+                ioctl_sys::ioctl(fd, code as c_ulong, arg)
+            }
+        };
+        ($($rest:tt)*) => {
+            ioctl_sys::ioctl!($($rest)*);
+        };
+    }
 
     #[cfg(not(feature = "pregenerated-bindings"))]
     include!(concat!(env!("OUT_DIR"), "/ioctls.rs"));
@@ -29,3 +47,148 @@ pub mod ioctl {
     #[cfg(all(feature = "sncn", feature = "pregenerated-bindings"))]
     include!("ioctls-v1.5.2-sncn-11.rs");
 }
+
+/// Typed request codes of the ioctls in `ioctl`.
+///
+/// These allow dispatching the requests to something other than the
+/// character device, e.g. a simulation.
+pub mod request {
+    use super::EC_IOCTL_TYPE as EC;
+    use super::*;
+    use std::os::raw::c_ulong;
+
+    macro_rules! ioctl {
+        (none $name:ident with $ioty:expr, $nr:expr) => {
+            pub const $name: Request<()> = Request::new(stringify!($name), io!($ioty, $nr));
+        };
+        (arg $name:ident with $ioty:expr, $nr:expr) => {
+            pub const $name: Request<c_ulong> =
+                Request::by_value(stringify!($name), io!($ioty, $nr));
+        };
+        (arg $name:ident with $ioty:expr, $nr:expr; $ty:ty) => {
+            pub const $name: Request<c_ulong> = Request::by_value(
+                stringify!($name),
+                iow!($ioty, $nr, ::std::mem::size_of::<$ty>()),
+            );
+        };
+        (read $name:ident with $ioty:expr, $nr:expr; $ty:ty) => {
+            pub const $name: Request<*mut $ty> = Request::new(
+                stringify!($name),
+                ior!($ioty, $nr, ::std::mem::size_of::<$ty>()),
+            );
+        };
+        (write $name:ident with $ioty:expr, $nr:expr; $ty:ty) => {
+            pub const $name: Request<*const $ty> = Request::new(
+                stringify!($name),
+                iow!($ioty, $nr, ::std::mem::size_of::<$ty>()),
+            );
+        };
+        (readwrite $name:ident with $ioty:expr, $nr:expr; $ty:ty) => {
+            pub const $name: Request<*mut $ty> = Request::new(
+                stringify!($name),
+                iorw!($ioty, $nr, ::std::mem::size_of::<$ty>()),
+            );
+        };
+    }
+
+    #[cfg(not(feature = "pregenerated-bindings"))]
+    include!(concat!(env!("OUT_DIR"), "/ioctls.rs"));
+
+    #[cfg(all(not(feature = "sncn"), feature = "pregenerated-bindings"))]
+    include!("ioctls-v1.5-334c34cfd2e5.rs");
+
+    #[cfg(all(feature = "sncn", feature = "pregenerated-bindings"))]
+    include!("ioctls-v1.5.2-sncn-11.rs");
+}
+
+/// An ioctl request code together with the type of its argument.
+pub struct Request<A> {
+    raw: RawRequest,
+    _arg: PhantomData<fn(A)>,
+}
+
+impl<A> Request<A> {
+    const fn new(name: &'static str, code: u32) -> Self {
+        Self {
+            raw: RawRequest {
+                name,
+                code: code as c_ulong,
+                by_value: false,
+            },
+            _arg: PhantomData,
+        }
+    }
+
+    const fn by_value(name: &'static str, code: u32) -> Self {
+        Self {
+            raw: RawRequest {
+                name,
+                code: code as c_ulong,
+                by_value: true,
+            },
+            _arg: PhantomData,
+        }
+    }
+
+    pub const fn raw(&self) -> RawRequest {
+        self.raw
+    }
+}
+
+impl<A> Clone for Request<A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A> Copy for Request<A> {}
+
+/// An untyped ioctl request code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawRequest {
+    /// Name of the request, without the `EC_IOCTL_` prefix.
+    pub name: &'static str,
+    pub code: c_ulong,
+    /// Whether the argument is passed by value instead of as a pointer.
+    pub by_value: bool,
+}
+
+impl RawRequest {
+    /// Size of the argument struct, or 0 if the argument is passed by value.
+    pub fn arg_size(&self) -> usize {
+        if self.by_value {
+            0
+        } else {
+            ioctl_sys::ioc_size(self.code as u32) as usize
+        }
+    }
+}
+
+/// Conversion of an ioctl argument into its raw representation.
+pub trait IoctlArg {
+    fn into_raw(self) -> c_ulong;
+}
+
+impl IoctlArg for () {
+    fn into_raw(self) -> c_ulong {
+        0
+    }
+}
+
+impl IoctlArg for c_ulong {
+    fn into_raw(self) -> c_ulong {
+        self
+    }
+}
+
+impl<T> IoctlArg for *mut T {
+    fn into_raw(self) -> c_ulong {
+        self as c_ulong
+    }
+}
+
+impl<T> IoctlArg for *const T {
+    fn into_raw(self) -> c_ulong {
+        self as c_ulong
+    }
+}
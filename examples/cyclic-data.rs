@@ -1,7 +1,7 @@
 use ethercat::{
-    AlState, DomainIdx as DomainIndex, Idx, Master, MasterAccess, Offset, PdoCfg, PdoEntryIdx,
-    PdoEntryIdx as PdoEntryIndex, PdoEntryInfo, PdoEntryPos, PdoIdx, SlaveAddr, SlaveId, SlavePos,
-    SmCfg, SubIdx,
+    AlState, Configuring, DomainIdx as DomainIndex, Idx, Master, MasterAccess, Offset, PdoCfg,
+    PdoEntryIdx, PdoEntryIdx as PdoEntryIndex, PdoEntryInfo, PdoEntryPos, PdoIdx, SlaveAddr,
+    SlaveId, SlavePos, SmCfg, SubIdx,
 };
 use ethercat_esi::EtherCatInfo;
 use std::{
@@ -31,7 +31,7 @@ pub fn main() -> Result<(), io::Error> {
     let mut esi_xml_string = String::new();
     esi_file.read_to_string(&mut esi_xml_string)?;
     let esi = EtherCatInfo::from_xml_str(&esi_xml_string)?;
-    let (mut master, domain_idx, offsets) = init_master(&esi, 0_u32)?;
+    let (master, domain_idx, offsets) = init_master(&esi, 0_u32)?;
     for (s, o) in &offsets {
         log::info!("PDO offsets of Slave {}:", u16::from(*s));
         for (pdo, (bit_len, offset)) in o {
@@ -45,7 +45,7 @@ pub fn main() -> Result<(), io::Error> {
         }
     }
     let cycle_time = Duration::from_micros(50_000);
-    master.activate()?;
+    let mut master = master.activate()?;
 
     loop {
         master.receive()?;
@@ -69,23 +69,21 @@ pub fn init_master(
     idx: u32,
 ) -> Result<
     (
-        Master,
+        Master<Configuring>,
         DomainIndex,
         HashMap<SlavePos, HashMap<PdoEntryIndex, (BitLen, Offset)>>,
     ),
     io::Error,
 > {
-    let mut master = Master::open(idx, MasterAccess::ReadWrite)?;
+    let master = Master::open(idx, MasterAccess::ReadWrite)?;
     log::debug!("Reserve master");
-    master.reserve()?;
-    log::debug!("Create domain");
-    let domain_idx = master.create_domain()?;
-    let mut offsets: HashMap<SlavePos, HashMap<PdoEntryIndex, (u8, Offset)>> = HashMap::new();
+    let mut master = master.reserve()?;
+    let mut configs = vec![];
 
     for (dev_nr, dev) in esi.description.devices.iter().enumerate() {
         let slave_pos = SlavePos::from(dev_nr as u16);
         log::debug!("Request PreOp state for {:?}", slave_pos);
-        master.request_state(slave_pos, AlState::Preop)?;
+        master.request_state(slave_pos, AlState::PreOp)?;
         let slave_info = master.get_slave_info(slave_pos)?;
         log::info!("Found device {}:{:?}", dev.name, slave_info);
         let slave_addr = SlaveAddr::ByPos(dev_nr as u16);
@@ -93,8 +91,7 @@ pub fn init_master(
             vendor_id: esi.vendor.id,
             product_code: dev.product_code,
         };
-        let mut config = master.configure_slave(slave_addr, slave_id)?;
-        let mut entry_offsets: HashMap<PdoEntryIndex, (u8, Offset)> = HashMap::new();
+        let config = master.configure_slave(slave_addr, slave_id)?;
 
         let rx_pdos: Vec<PdoCfg> = dev
             .rx_pdo
@@ -143,14 +140,22 @@ pub fn init_master(
         let output = SmCfg::output(2.into());
         let input = SmCfg::input(3.into());
 
-        config.config_sm_pdos(output, &rx_pdos)?;
-        config.config_sm_pdos(input, &tx_pdos)?;
+        config.config_sm_pdos(&master, output, &rx_pdos)?;
+        config.config_sm_pdos(&master, input, &tx_pdos)?;
+        configs.push((slave_pos, config, rx_pdos, tx_pdos));
+    }
+
+    log::debug!("Create domain");
+    let domain_idx = master.create_domain()?;
+    let mut offsets: HashMap<SlavePos, HashMap<PdoEntryIndex, (u8, Offset)>> = HashMap::new();
 
+    for (slave_pos, config, rx_pdos, tx_pdos) in configs {
+        let mut entry_offsets: HashMap<PdoEntryIndex, (u8, Offset)> = HashMap::new();
         for pdo in &rx_pdos {
             // Positions of RX PDO
             log::debug!("Positions of RX PDO 0x{:X}:", u16::from(pdo.idx));
             for entry in &pdo.entries {
-                let offset = config.register_pdo_entry(entry.entry_idx, domain_idx)?;
+                let offset = config.register_pdo_entry(&master, entry.entry_idx, domain_idx)?;
                 entry_offsets.insert(entry.entry_idx, (entry.bit_len, offset));
             }
         }
@@ -158,7 +163,7 @@ pub fn init_master(
             // Positions of TX PDO
             log::debug!("Positions of TX PDO 0x{:X}:", u16::from(pdo.idx));
             for entry in &pdo.entries {
-                let offset = config.register_pdo_entry(entry.entry_idx, domain_idx)?;
+                let offset = config.register_pdo_entry(&master, entry.entry_idx, domain_idx)?;
                 entry_offsets.insert(entry.entry_idx, (entry.bit_len, offset));
             }
         }
@@ -0,0 +1,147 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Backends executing the requests of a `Master`.
+//!
+//! All interaction of `Master`, `SlaveConfig` and `Domain` with the
+//! EtherCAT master is expressed as ioctl requests of the IgH master (see
+//! `ethercat_sys::request`). By default they are sent to the kernel module
+//! via `/dev/EtherCATn`, but any other `Backend` can be plugged in with
+//! `Master::with_backend`.
+
+use crate::{ec, Error, MasterAccess, MasterIdx};
+use std::{
+    cell::UnsafeCell,
+    fs::{File, OpenOptions},
+    io,
+    os::{
+        raw::{c_int, c_ulong},
+        unix::io::AsRawFd,
+    },
+    sync::Arc,
+};
+
+/// Executes ioctl requests on behalf of a `Master`.
+pub trait Backend: Send + Sync {
+    /// Execute a request and return its non-negative result.
+    ///
+    /// # Safety
+    ///
+    /// `arg` is the raw argument of the request as defined by the IgH
+    /// master: either a plain value, or a pointer to a valid instance of
+    /// the request's argument struct. Pointers within that struct (e.g.
+    /// data buffers) are valid for the sizes given in the struct.
+    unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int>;
+
+    /// Map the process data memory after the master has been activated.
+    fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>>;
+
+    /// Take the details of the request that just failed, if the backend
+    /// knows more about the failure than its OS error code.
+    fn take_error(&self) -> Option<Error> {
+        None
+    }
+}
+
+/// Process data memory of all domains, shared between `Master` and its
+/// backend.
+///
+/// The backend may only access the memory while executing a request,
+/// e.g. to exchange the process data on `RECEIVE` and `SEND`.
+pub trait ProcessDataMemory: Send + Sync {
+    fn as_mut_slice(&mut self) -> &mut [u8];
+}
+
+impl ProcessDataMemory for memmap::MmapMut {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl ProcessDataMemory for Vec<u8> {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// Process data memory that a backend hands out to the `Master` while
+/// keeping access to it, e.g. to exchange the process data itself.
+pub(crate) struct SharedMemory {
+    memory: UnsafeCell<Box<dyn ProcessDataMemory>>,
+    len: usize,
+}
+
+// The backend only accesses the memory while executing a request of the
+// owning master, which holds no reference to it at that time.
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    pub fn new(mut memory: Box<dyn ProcessDataMemory>) -> Arc<Self> {
+        let len = memory.as_mut_slice().len();
+        Arc::new(Self {
+            memory: UnsafeCell::new(memory),
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// # Safety
+    ///
+    /// Must only be called while executing a request of the owning master.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn data(&self) -> &mut [u8] {
+        (*self.memory.get()).as_mut_slice()
+    }
+
+    /// The memory as handed out to the master.
+    pub fn handle(self: &Arc<Self>) -> Box<dyn ProcessDataMemory> {
+        Box::new(SharedHandle(self.clone()))
+    }
+}
+
+struct SharedHandle(Arc<SharedMemory>);
+
+impl ProcessDataMemory for SharedHandle {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { self.0.data() }
+    }
+}
+
+/// The default backend, using the character device of the kernel module.
+#[derive(Debug)]
+pub struct DeviceBackend {
+    file: File,
+}
+
+impl DeviceBackend {
+    pub fn open(idx: MasterIdx, access: MasterAccess) -> io::Result<Self> {
+        let devpath = format!("/dev/EtherCAT{}", idx);
+        log::debug!("Open EtherCAT Master {}", devpath);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(access == MasterAccess::ReadWrite)
+            .open(&devpath)?;
+        Ok(Self { file })
+    }
+}
+
+impl Backend for DeviceBackend {
+    unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        // The request type of `ioctl` is `c_ulong` on glibc, but `c_int` on musl.
+        let res = libc::ioctl(self.file.as_raw_fd(), request.code as _, arg);
+        if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(res)
+        }
+    }
+
+    fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>> {
+        let map = unsafe { memmap::MmapOptions::new().len(size).map_mut(&self.file)? };
+        Ok(Box::new(map))
+    }
+}
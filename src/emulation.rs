@@ -0,0 +1,1385 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Emulation of the IgH master in userspace.
+//!
+//! `Bus` implements the requests of the kernel module on top of a
+//! `Network` of slaves, which is either simulated (`sim`) or reached over a
+//! network interface (`packet`). `Connection` is the equivalent of an open
+//! file descriptor of the master device.
+
+use crate::{
+    backend::{ProcessDataMemory, SharedMemory},
+    ec,
+    sdo::SdoAbortCode,
+    types::*,
+};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt, io,
+    os::raw::{c_char, c_int, c_ulong},
+    ptr, slice,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Description of a slave behind the emulated master.
+#[derive(Debug, Clone)]
+pub(crate) struct SlaveDesc {
+    pub id: SlaveId,
+    pub rev: SlaveRev,
+    pub alias: u16,
+    pub name: String,
+    /// Sync managers with their default PDO assignment and mapping.
+    pub syncs: Vec<SyncDesc>,
+    /// The object dictionary, as far as it is known.
+    pub objects: BTreeMap<(u16, u8), Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SyncDesc {
+    pub cfg: SmCfg,
+    pub pdos: Vec<PdoCfg>,
+}
+
+impl SlaveDesc {
+    pub(crate) fn new(id: SlaveId) -> Self {
+        Self {
+            id,
+            rev: SlaveRev::new(0, 0),
+            alias: 0,
+            name: String::new(),
+            syncs: vec![],
+            objects: BTreeMap::new(),
+        }
+    }
+
+    /// Add or replace a sync manager, keeping them ordered by index.
+    pub(crate) fn set_sync(&mut self, cfg: SmCfg, pdos: Vec<PdoCfg>) {
+        self.syncs.retain(|s| s.cfg.idx != cfg.idx);
+        self.syncs.push(SyncDesc { cfg, pdos });
+        self.syncs.sort_by_key(|s| u8::from(s.cfg.idx));
+    }
+
+    fn sync(&self, idx: u8) -> Option<&SyncDesc> {
+        self.syncs.iter().find(|s| u8::from(s.cfg.idx) == idx)
+    }
+
+    /// The default PDO assignment and mapping of a sync manager.
+    #[cfg_attr(not(feature = "packet"), allow(dead_code))]
+    pub(crate) fn default_pdos(&self, idx: u8) -> &[PdoCfg] {
+        self.sync(idx).map_or(&[], |s| &s.pdos)
+    }
+
+    fn sync_count(&self) -> u8 {
+        self.syncs
+            .last()
+            .map(|s| u8::from(s.cfg.idx) + 1)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn sdo_indices(&self) -> Vec<u16> {
+        let mut indices: Vec<_> = self.objects.keys().map(|(idx, _)| *idx).collect();
+        indices.dedup();
+        indices
+    }
+}
+
+/// A connection to the emulated master, shared by the backends of the
+/// simulation and of real networks.
+pub(crate) struct Connection<N: Network> {
+    bus: Arc<Mutex<Bus<N>>>,
+    id: usize,
+}
+
+impl<N: Network> Connection<N> {
+    pub(crate) fn new(bus: &Arc<Mutex<Bus<N>>>) -> Self {
+        let mut guard = lock(bus);
+        let id = guard.next_context;
+        guard.next_context += 1;
+        Self {
+            bus: bus.clone(),
+            id,
+        }
+    }
+
+    pub(crate) unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        lock(&self.bus).ioctl(self.id, request, arg)
+    }
+
+    pub(crate) fn take_error(&self) -> Option<Error> {
+        let mut bus = lock(&self.bus);
+        if bus.owner == Some(self.id) {
+            bus.failure.take()
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>> {
+        let bus = lock(&self.bus);
+        match &bus.memory {
+            Some(memory) if bus.owner == Some(self.id) && memory.len() == size => {
+                Ok(memory.handle())
+            }
+            _ => Err(errno(libc::EINVAL)),
+        }
+    }
+}
+
+impl<N: Network> Drop for Connection<N> {
+    fn drop(&mut self) {
+        let mut bus = lock(&self.bus);
+        if bus.owner == Some(self.id) {
+            bus.deactivate();
+            bus.owner = None;
+        }
+    }
+}
+
+pub(crate) fn lock<N: Network>(bus: &Mutex<Bus<N>>) -> MutexGuard<'_, Bus<N>> {
+    bus.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The next state on the way from `current` to `target`.
+///
+/// States are raised one at a time and lowered directly, Boot is entered
+/// and left via Init.
+pub(crate) fn next_al_state(current: AlState, target: AlState) -> AlState {
+    use AlState::*;
+    if current == target {
+        return current;
+    }
+    match (current, target) {
+        (Init, Boot) => Boot,
+        (_, Boot) | (Boot, _) => Init,
+        (Init, _) => PreOp,
+        (PreOp, SafeOp) | (PreOp, Op) => SafeOp,
+        (SafeOp, Op) => Op,
+        _ => target,
+    }
+}
+
+/// The slaves behind the emulated master.
+///
+/// Implemented by the slaves of a `Simulation` and by real slaves reached
+/// over a network interface.
+pub(crate) trait Network: Send + 'static {
+    fn slave_count(&self) -> usize;
+
+    /// The description of a slave, e.g. as read from its SII.
+    fn desc(&self, slave: usize) -> &SlaveDesc;
+
+    fn has_mailbox(&self, slave: usize) -> bool {
+        !self.desc(slave).objects.is_empty()
+    }
+
+    fn al_state(&self, slave: usize) -> AlState;
+
+    /// Request an AL state of a slave.
+    ///
+    /// With `wait`, return once the state is reached, otherwise it is
+    /// approached in `receive`.
+    fn request_state(&mut self, slave: usize, state: AlState, wait: bool) -> io::Result<()>;
+
+    fn sdo_upload(&mut self, slave: usize, index: u16, subindex: u8) -> SdoResult<Vec<u8>>;
+
+    fn sdo_download(
+        &mut self,
+        slave: usize,
+        index: u16,
+        subindex: u8,
+        complete_access: bool,
+        data: &[u8],
+    ) -> SdoResult<()>;
+
+    /// Set up the process data sync managers of a slave, or release them
+    /// if `syncs` is empty.
+    fn configure(&mut self, slave: usize, syncs: &[SyncLayout]) -> ConfigResult<()>;
+
+    /// Send the process data of a domain to its logical address.
+    fn send(&mut self, domain: usize, address: usize, data: &[u8]) -> io::Result<()>;
+
+    /// Collect the answers to the sent process data.
+    fn receive(&mut self) -> io::Result<Vec<Answer>>;
+}
+
+pub(crate) type SdoResult<T> = std::result::Result<T, SdoError>;
+
+/// Failure of an SDO transfer.
+#[derive(Debug)]
+pub(crate) enum SdoError {
+    Abort(SdoAbortCode),
+    Io(io::Error),
+}
+
+impl From<SdoAbortCode> for SdoError {
+    fn from(abort: SdoAbortCode) -> Self {
+        SdoError::Abort(abort)
+    }
+}
+
+impl From<io::Error> for SdoError {
+    fn from(err: io::Error) -> Self {
+        SdoError::Io(err)
+    }
+}
+
+pub(crate) type ConfigResult<T> = std::result::Result<T, ConfigError>;
+
+/// Failure to set up the process data of a slave.
+#[derive(Debug)]
+pub(crate) enum ConfigError {
+    /// The slave refused an SDO of the PDO assignment or mapping.
+    #[cfg_attr(not(feature = "packet"), allow(dead_code))]
+    SdoAbort {
+        idx: SdoIdx,
+        code: SdoAbortCode,
+    },
+    Io(io::Error),
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::SdoAbort { idx, code } => write!(
+                f,
+                "SDO 0x{:04X}:{} aborted: {}",
+                u16::from(idx.idx),
+                u8::from(idx.sub_idx),
+                code
+            ),
+            ConfigError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+/// A process data sync manager of a slave as configured by the application.
+#[cfg_attr(not(feature = "packet"), allow(dead_code))]
+pub(crate) struct SyncLayout {
+    pub index: u8,
+    pub dir: ec::ec_direction_t,
+    pub watchdog_mode: ec::ec_watchdog_mode_t,
+    /// The PDOs, if the assignment or mapping differs from the default.
+    pub pdos: Option<Vec<PdoCfg>>,
+    pub size: usize,
+    /// The logical addresses the sync manager is mapped to.
+    pub addresses: Vec<usize>,
+}
+
+/// The answer to the sent process data of a domain.
+pub(crate) struct Answer {
+    pub domain: usize,
+    pub working_counter: u16,
+    /// The domain data with the inputs filled in.
+    pub data: Vec<u8>,
+}
+
+struct Config {
+    alias: u16,
+    position: u16,
+    id: SlaveId,
+    syncs: BTreeMap<u8, SyncConfig>,
+    mappings: BTreeMap<u16, Vec<PdoEntryInfo>>,
+    sdos: Vec<StartupSdo>,
+    watchdog_divider: u16,
+    watchdog_intervals: u16,
+    dc_assign_activate: u16,
+    dc_sync: [ec::ec_sync_signal_t; 2],
+}
+
+#[derive(Default)]
+struct SyncConfig {
+    dir: ec::ec_direction_t,
+    watchdog_mode: ec::ec_watchdog_mode_t,
+    /// The PDO assignment, or `None` to use the slave's default.
+    pdos: Option<Vec<u16>>,
+}
+
+struct StartupSdo {
+    index: u16,
+    subindex: u8,
+    complete_access: bool,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Domain {
+    fmmus: Vec<Fmmu>,
+    size: usize,
+    /// Offset in the process data memory, assigned on activation.
+    offset: usize,
+    queued: bool,
+    received_wc: u16,
+    working_counter: u16,
+    /// The process data as last seen on the bus.
+    image: Vec<u8>,
+}
+
+struct Fmmu {
+    config: usize,
+    sync: u8,
+    dir: ec::ec_direction_t,
+    offset: usize,
+    size: usize,
+}
+
+impl Domain {
+    /// Configs with their contribution to the working counter: 2 for
+    /// outputs and 1 for inputs.
+    fn wc_parts(&self) -> BTreeMap<usize, (bool, bool)> {
+        let mut parts = BTreeMap::new();
+        for fmmu in &self.fmmus {
+            let (out, inp) = parts.entry(fmmu.config).or_insert((false, false));
+            *out |= fmmu.dir == ec::EC_DIR_OUTPUT;
+            *inp |= fmmu.dir == ec::EC_DIR_INPUT;
+        }
+        parts
+    }
+
+    fn expected_wc(&self) -> u16 {
+        self.wc_parts()
+            .values()
+            .map(|(out, inp)| 2 * *out as u16 + *inp as u16)
+            .sum()
+    }
+
+    fn wc_state(&self) -> ec::ec_wc_state_t {
+        if self.working_counter == 0 {
+            ec::EC_WC_ZERO
+        } else if self.working_counter == self.expected_wc() {
+            ec::EC_WC_COMPLETE
+        } else {
+            ec::EC_WC_INCOMPLETE
+        }
+    }
+}
+
+#[derive(Default)]
+struct FrameCounters {
+    tx_count: u64,
+    rx_count: u64,
+    tx_bytes: u64,
+    rx_bytes: u64,
+}
+
+/// The emulated master, with the state of the application interface.
+pub(crate) struct Bus<N> {
+    pub network: N,
+    /// The connection which reserved the master.
+    owner: Option<usize>,
+    next_context: usize,
+    active: bool,
+    configs: Vec<Config>,
+    domains: Vec<Domain>,
+    memory: Option<Arc<SharedMemory>>,
+    ref_clock: Option<usize>,
+    app_time: u64,
+    stats: FrameCounters,
+    /// The details of a failed activation, for the owner to take.
+    failure: Option<Error>,
+}
+
+fn errno(code: i32) -> io::Error {
+    io::Error::from_raw_os_error(code)
+}
+
+pub(crate) fn sdo_key(idx: SdoIdx) -> (u16, u8) {
+    (u16::from(idx.idx), u8::from(idx.sub_idx))
+}
+
+/// # Safety
+///
+/// `arg` must point to a valid `T`.
+unsafe fn arg_mut<'a, T>(arg: c_ulong) -> &'a mut T {
+    &mut *(arg as *mut T)
+}
+
+/// # Safety
+///
+/// `arg` must point to a valid `T`.
+unsafe fn arg_ref<T: Copy>(arg: c_ulong) -> T {
+    ptr::read(arg as *const T)
+}
+
+fn write_c_string(target: &mut [c_char], s: &str) {
+    let len = s.len().min(target.len().saturating_sub(1));
+    for (t, b) in target.iter_mut().zip(&s.as_bytes()[..len]) {
+        *t = *b as c_char;
+    }
+    if let Some(t) = target.get_mut(len) {
+        *t = 0;
+    }
+}
+
+/// The requests handled by the simulation, as constants to match on.
+mod req {
+    use crate::ec;
+
+    macro_rules! requests {
+        ($($name:ident)*) => {
+            $(pub const $name: ec::RawRequest = ec::request::$name.raw();)*
+        };
+    }
+
+    requests! {
+        ACTIVATE APP_TIME CONFIG CONFIG_PDO CONFIG_PDO_ENTRY CONFIG_SDO CREATE_DOMAIN
+        CREATE_SLAVE_CONFIG DEACTIVATE DOMAIN DOMAIN_DATA DOMAIN_FMMU DOMAIN_OFFSET DOMAIN_PROCESS
+        DOMAIN_QUEUE DOMAIN_SIZE DOMAIN_STATE MASTER MASTER_DEBUG MASTER_LINK_STATE MASTER_RESCAN
+        MASTER_STATE MODULE RECEIVE REF_CLOCK_TIME REQUEST RESET SC_ADD_ENTRY SC_ADD_PDO
+        SC_CLEAR_ENTRIES SC_CLEAR_PDOS SC_DC SC_REG_PDO_ENTRY SC_REG_PDO_POS SC_SDO SC_STATE
+        SC_SYNC SC_WATCHDOG SELECT_REF_CLOCK SEND SET_SEND_INTERVAL SLAVE SLAVE_SDO
+        SLAVE_SDO_DOWNLOAD SLAVE_SDO_ENTRY SLAVE_SDO_UPLOAD SLAVE_STATE SLAVE_SYNC SLAVE_SYNC_PDO
+        SLAVE_SYNC_PDO_ENTRY SYNC_MON_PROCESS SYNC_MON_QUEUE SYNC_REF SYNC_REF_TO SYNC_SLAVES
+    }
+}
+
+/// Whether a request needs the master to be reserved.
+fn is_app_request(request: ec::RawRequest) -> bool {
+    matches!(
+        request,
+        req::CREATE_DOMAIN
+            | req::CREATE_SLAVE_CONFIG
+            | req::SELECT_REF_CLOCK
+            | req::ACTIVATE
+            | req::DEACTIVATE
+            | req::SEND
+            | req::RECEIVE
+            | req::CONFIG
+            | req::CONFIG_PDO
+            | req::CONFIG_PDO_ENTRY
+            | req::CONFIG_SDO
+            | req::DOMAIN
+            | req::DOMAIN_DATA
+            | req::DOMAIN_FMMU
+            | req::DOMAIN_OFFSET
+            | req::DOMAIN_PROCESS
+            | req::DOMAIN_QUEUE
+            | req::DOMAIN_SIZE
+            | req::DOMAIN_STATE
+            | req::SC_ADD_ENTRY
+            | req::SC_ADD_PDO
+            | req::SC_CLEAR_ENTRIES
+            | req::SC_CLEAR_PDOS
+            | req::SC_DC
+            | req::SC_REG_PDO_ENTRY
+            | req::SC_REG_PDO_POS
+            | req::SC_SDO
+            | req::SC_STATE
+            | req::SC_SYNC
+            | req::SC_WATCHDOG
+    )
+}
+
+/// The CoE data type of an object, guessed from its size.
+fn data_type(size: usize) -> u16 {
+    match size {
+        1 => 0x0005,
+        2 => 0x0006,
+        4 => 0x0007,
+        8 => 0x001B,
+        _ => 0x000A,
+    }
+}
+
+impl<N: Network> Bus<N> {
+    pub(crate) fn new(network: N) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Bus {
+            network,
+            owner: None,
+            next_context: 0,
+            active: false,
+            configs: vec![],
+            domains: vec![],
+            memory: None,
+            ref_clock: None,
+            app_time: 0,
+            stats: FrameCounters::default(),
+            failure: None,
+        }))
+    }
+
+    unsafe fn ioctl(
+        &mut self,
+        ctx: usize,
+        request: ec::RawRequest,
+        arg: c_ulong,
+    ) -> io::Result<c_int> {
+        match request {
+            req::MODULE => {
+                let data = arg_mut::<ec::ec_ioctl_module_t>(arg);
+                data.ioctl_version_magic = ec::EC_IOCTL_VERSION_MAGIC;
+                data.master_count = 1;
+            }
+            req::MASTER => self.master_info(arg_mut(arg)),
+            req::MASTER_STATE => {
+                let data = arg_mut::<ec::ec_master_state_t>(arg);
+                data.slaves_responding = self.network.slave_count() as _;
+                data.set_al_states(self.al_states());
+                data.set_link_up(1);
+            }
+            req::MASTER_LINK_STATE => {
+                let data = arg_ref::<ec::ec_ioctl_link_state_t>(arg);
+                if data.dev_idx != 0 {
+                    return Err(errno(libc::EINVAL));
+                }
+                let state = &mut *data.state;
+                state.slaves_responding = self.network.slave_count() as _;
+                state.set_al_states(self.al_states());
+                state.set_link_up(1);
+            }
+            req::MASTER_DEBUG
+            | req::MASTER_RESCAN
+            | req::RESET
+            | req::SET_SEND_INTERVAL
+            | req::SYNC_REF
+            | req::SYNC_REF_TO
+            | req::SYNC_SLAVES
+            | req::SYNC_MON_QUEUE => {}
+            req::APP_TIME => self.app_time = arg_ref::<u64>(arg),
+            req::REF_CLOCK_TIME => *arg_mut::<u32>(arg) = self.app_time as u32,
+            req::SYNC_MON_PROCESS => *arg_mut::<u32>(arg) = 0,
+            req::SLAVE => self.slave_info(arg_mut(arg))?,
+            req::SLAVE_STATE => {
+                let data = arg_ref::<ec::ec_ioctl_slave_state_t>(arg);
+                let state = AlState::try_from(data.al_state).map_err(|_| errno(libc::EINVAL))?;
+                let slave = self.slave(data.slave_position)?;
+                self.network.request_state(slave, state, !self.active)?;
+            }
+            req::SLAVE_SYNC => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sync_t>(arg);
+                let desc = self.network.desc(self.slave(data.slave_position)?);
+                if data.sync_index >= desc.sync_count() as u32 {
+                    return Err(errno(libc::EINVAL));
+                }
+                let sync = desc.sync(data.sync_index as u8);
+                data.physical_start_address = 0x1000 + 0x80 * data.sync_index as u16;
+                data.default_size = sync.map(|s| sm_size(&s.pdos) as u16).unwrap_or_default();
+                data.control_register = match sync.map(|s| s.cfg.direction) {
+                    Some(SyncDirection::Output) => 0x64,
+                    _ => 0x20,
+                };
+                data.enable = sync.is_some() as u8;
+                data.pdo_count = sync.map(|s| s.pdos.len() as u8).unwrap_or_default();
+            }
+            req::SLAVE_SYNC_PDO => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sync_pdo_t>(arg);
+                let pdo = self
+                    .network
+                    .desc(self.slave(data.slave_position)?)
+                    .sync(data.sync_index as u8)
+                    .and_then(|s| s.pdos.get(data.pdo_pos as usize))
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                data.index = u16::from(pdo.idx);
+                data.entry_count = pdo.entries.len() as u8;
+                write_c_string(&mut data.name, "");
+            }
+            req::SLAVE_SYNC_PDO_ENTRY => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sync_pdo_entry_t>(arg);
+                let entry = self
+                    .network
+                    .desc(self.slave(data.slave_position)?)
+                    .sync(data.sync_index as u8)
+                    .and_then(|s| s.pdos.get(data.pdo_pos as usize))
+                    .and_then(|p| p.entries.get(data.entry_pos as usize))
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                data.index = u16::from(entry.entry_idx.idx);
+                data.subindex = u8::from(entry.entry_idx.sub_idx);
+                data.bit_length = entry.bit_len;
+                write_c_string(&mut data.name, &entry.name);
+            }
+            req::SLAVE_SDO => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sdo_t>(arg);
+                let desc = self.network.desc(self.slave(data.slave_position)?);
+                let index = *desc
+                    .sdo_indices()
+                    .get(data.sdo_position as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                data.sdo_index = index;
+                data.max_subindex = desc
+                    .objects
+                    .range((index, 0)..=(index, u8::MAX))
+                    .map(|((_, sub), _)| *sub)
+                    .max()
+                    .unwrap_or_default();
+                write_c_string(&mut data.name, "");
+            }
+            req::SLAVE_SDO_ENTRY => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sdo_entry_t>(arg);
+                let desc = self.network.desc(self.slave(data.slave_position)?);
+                let index = if data.sdo_spec <= 0 {
+                    *desc
+                        .sdo_indices()
+                        .get(-data.sdo_spec as usize)
+                        .ok_or_else(|| errno(libc::EINVAL))?
+                } else {
+                    data.sdo_spec as u16
+                };
+                let value = desc
+                    .objects
+                    .get(&(index, data.sdo_entry_subindex))
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                data.data_type = data_type(value.len());
+                data.bit_length = (value.len() * 8) as u16;
+                data.read_access = [1; 3];
+                data.write_access = [1; 3];
+                write_c_string(&mut data.description, "");
+            }
+            req::SLAVE_SDO_UPLOAD => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sdo_upload_t>(arg);
+                let slave = self.slave(data.slave_position)?;
+                let value = self
+                    .network
+                    .sdo_upload(slave, data.sdo_index, data.sdo_entry_subindex);
+                let value = value.map_err(|err| sdo_errno(err, &mut data.abort_code))?;
+                if value.len() > data.target_size as usize {
+                    return Err(errno(libc::EOVERFLOW));
+                }
+                ptr::copy_nonoverlapping(value.as_ptr(), data.target, value.len());
+                data.data_size = value.len() as _;
+            }
+            req::SLAVE_SDO_DOWNLOAD => {
+                let data = arg_mut::<ec::ec_ioctl_slave_sdo_download_t>(arg);
+                let value = slice::from_raw_parts(data.data, data.data_size as usize);
+                let slave = self.slave(data.slave_position)?;
+                let res = self.network.sdo_download(
+                    slave,
+                    data.sdo_index,
+                    data.sdo_entry_subindex,
+                    data.complete_access != 0,
+                    value,
+                );
+                res.map_err(|err| sdo_errno(err, &mut data.abort_code))?;
+            }
+            req::REQUEST => {
+                if self.owner.is_some() {
+                    return Err(errno(libc::EBUSY));
+                }
+                self.owner = Some(ctx);
+            }
+            _ => return self.app_ioctl(ctx, request, arg),
+        }
+        Ok(0)
+    }
+
+    /// Requests of the application which reserved the master.
+    unsafe fn app_ioctl(
+        &mut self,
+        ctx: usize,
+        request: ec::RawRequest,
+        arg: c_ulong,
+    ) -> io::Result<c_int> {
+        let read_only = matches!(
+            request,
+            req::CONFIG
+                | req::CONFIG_PDO
+                | req::CONFIG_PDO_ENTRY
+                | req::CONFIG_SDO
+                | req::DOMAIN
+                | req::DOMAIN_FMMU
+                | req::DOMAIN_DATA
+        );
+        if self.owner != Some(ctx) && !read_only && is_app_request(request) {
+            return Err(errno(libc::EPERM));
+        }
+        match request {
+            req::CREATE_DOMAIN => {
+                self.check_idle()?;
+                self.domains.push(Domain::default());
+                return Ok(self.domains.len() as c_int - 1);
+            }
+            req::CREATE_SLAVE_CONFIG => {
+                self.check_idle()?;
+                let data = arg_mut::<ec::ec_ioctl_config_t>(arg);
+                let id = SlaveId::new(data.vendor_id, data.product_code);
+                let existing = self
+                    .configs
+                    .iter()
+                    .position(|c| c.alias == data.alias && c.position == data.position);
+                data.config_index = match existing {
+                    Some(i) if self.configs[i].id == id => i as u32,
+                    Some(_) => return Err(errno(libc::ENOENT)),
+                    None => {
+                        self.configs.push(Config {
+                            alias: data.alias,
+                            position: data.position,
+                            id,
+                            syncs: BTreeMap::new(),
+                            mappings: BTreeMap::new(),
+                            sdos: vec![],
+                            watchdog_divider: 0,
+                            watchdog_intervals: 0,
+                            dc_assign_activate: 0,
+                            dc_sync: Default::default(),
+                        });
+                        self.configs.len() as u32 - 1
+                    }
+                };
+            }
+            req::SC_SYNC => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_config_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                for (i, sync) in data.syncs.iter().enumerate() {
+                    if sync.config_this != 0 {
+                        let cfg = config.syncs.entry(i as u8).or_default();
+                        cfg.dir = sync.dir;
+                        cfg.watchdog_mode = sync.watchdog_mode;
+                    }
+                }
+            }
+            req::SC_WATCHDOG => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_config_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                config.watchdog_divider = data.watchdog_divider;
+                config.watchdog_intervals = data.watchdog_intervals;
+            }
+            req::SC_DC => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_config_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                config.dc_assign_activate = data.dc_assign_activate;
+                config.dc_sync = data.dc_sync;
+            }
+            req::SC_CLEAR_PDOS => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_config_pdo_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                config.syncs.entry(data.sync_index).or_default().pdos = Some(vec![]);
+            }
+            req::SC_ADD_PDO => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_config_pdo_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                let sync = config.syncs.entry(data.sync_index).or_default();
+                sync.pdos.get_or_insert_with(Vec::new).push(data.index);
+            }
+            req::SC_CLEAR_ENTRIES => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_config_pdo_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                config.mappings.insert(data.index, vec![]);
+            }
+            req::SC_ADD_ENTRY => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_add_pdo_entry_t>(arg);
+                let config = self.config_mut(data.config_index)?;
+                let assigned = config
+                    .syncs
+                    .values()
+                    .filter_map(|s| s.pdos.as_ref())
+                    .any(|pdos| pdos.contains(&data.pdo_index));
+                if !assigned {
+                    return Err(errno(libc::ENOENT));
+                }
+                let entries = config.mappings.entry(data.pdo_index).or_default();
+                entries.push(PdoEntryInfo {
+                    pos: PdoEntryPos::from(entries.len() as u8),
+                    entry_idx: PdoEntryIdx::new(data.entry_index, data.entry_subindex),
+                    bit_len: data.entry_bit_length,
+                    name: String::new(),
+                });
+            }
+            req::SC_SDO => {
+                self.check_idle()?;
+                let data = arg_ref::<ec::ec_ioctl_sc_sdo_t>(arg);
+                let value = slice::from_raw_parts(data.data, data.size as usize);
+                let config = self.config_mut(data.config_index)?;
+                config.sdos.push(StartupSdo {
+                    index: data.index,
+                    subindex: data.subindex,
+                    complete_access: data.complete_access != 0,
+                    data: value.to_vec(),
+                });
+            }
+            req::SC_REG_PDO_ENTRY => {
+                self.check_idle()?;
+                let data = arg_mut::<ec::ec_ioctl_reg_pdo_entry_t>(arg);
+                let config = data.config_index as usize;
+                self.config(data.config_index)?;
+                let (sync, bit) = (0..ec::EC_MAX_SYNC_MANAGERS as u8)
+                    .find_map(|sync| {
+                        let entries = self
+                            .config_pdos(config, sync)
+                            .into_iter()
+                            .flat_map(|p| p.entries);
+                        let mut bit = 0;
+                        for entry in entries {
+                            if entry.entry_idx
+                                == PdoEntryIdx::new(data.entry_index, data.entry_subindex)
+                            {
+                                return Some((sync, bit));
+                            }
+                            bit += entry.bit_len as usize;
+                        }
+                        None
+                    })
+                    .ok_or_else(|| errno(libc::ENOENT))?;
+                let offset = self.register_fmmu(config, sync, data.domain_index)?;
+                data.bit_position = (bit % 8) as _;
+                return Ok((offset + bit / 8) as c_int);
+            }
+            req::SC_REG_PDO_POS => {
+                self.check_idle()?;
+                let data = arg_mut::<ec::ec_ioctl_reg_pdo_pos_t>(arg);
+                let config = data.config_index as usize;
+                self.config(data.config_index)?;
+                let pdos = self.config_pdos(config, data.sync_index as u8);
+                let pdo = pdos
+                    .get(data.pdo_pos as usize)
+                    .ok_or_else(|| errno(libc::ENOENT))?;
+                if data.entry_pos as usize >= pdo.entries.len() {
+                    return Err(errno(libc::ENOENT));
+                }
+                let bit: usize = pdos[..data.pdo_pos as usize]
+                    .iter()
+                    .flat_map(|p| &p.entries)
+                    .chain(&pdo.entries[..data.entry_pos as usize])
+                    .map(|e| e.bit_len as usize)
+                    .sum();
+                let offset =
+                    self.register_fmmu(config, data.sync_index as u8, data.domain_index)?;
+                data.bit_position = (bit % 8) as _;
+                return Ok((offset + bit / 8) as c_int);
+            }
+            req::SELECT_REF_CLOCK => {
+                self.check_idle()?;
+                self.config(arg as u32)?;
+                self.ref_clock = Some(arg as usize);
+            }
+            req::CONFIG => {
+                let data = arg_mut::<ec::ec_ioctl_config_t>(arg);
+                let idx = data.config_index as usize;
+                let config = self.config(data.config_index)?;
+                data.alias = config.alias;
+                data.position = config.position;
+                data.vendor_id = config.id.vendor_id;
+                data.product_code = config.id.product_code;
+                for (i, sync) in data.syncs.iter_mut().enumerate() {
+                    sync.dir = self.config_sync_dir(idx, i as u8);
+                    sync.watchdog_mode = config
+                        .syncs
+                        .get(&(i as u8))
+                        .map(|s| s.watchdog_mode)
+                        .unwrap_or_default();
+                    sync.pdo_count = self.config_pdos(idx, i as u8).len() as u32;
+                }
+                data.watchdog_divider = config.watchdog_divider;
+                data.watchdog_intervals = config.watchdog_intervals;
+                data.sdo_count = config.sdos.len() as u32;
+                data.idn_count = 0;
+                data.slave_position = self.attached_slave(idx).map_or(-1, |pos| pos as i32);
+                data.dc_assign_activate = config.dc_assign_activate;
+                data.dc_sync = config.dc_sync;
+            }
+            req::CONFIG_PDO => {
+                let data = arg_mut::<ec::ec_ioctl_config_pdo_t>(arg);
+                self.config(data.config_index)?;
+                let pdos = self.config_pdos(data.config_index as usize, data.sync_index);
+                let pdo = pdos
+                    .get(data.pdo_pos as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                data.index = u16::from(pdo.idx);
+                data.entry_count = pdo.entries.len() as u8;
+                write_c_string(&mut data.name, "");
+            }
+            req::CONFIG_PDO_ENTRY => {
+                let data = arg_mut::<ec::ec_ioctl_config_pdo_entry_t>(arg);
+                self.config(data.config_index)?;
+                let pdos = self.config_pdos(data.config_index as usize, data.sync_index);
+                let entry = pdos
+                    .get(data.pdo_pos as usize)
+                    .and_then(|p| p.entries.get(data.entry_pos as usize))
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                data.index = u16::from(entry.entry_idx.idx);
+                data.subindex = u8::from(entry.entry_idx.sub_idx);
+                data.bit_length = entry.bit_len;
+                write_c_string(&mut data.name, &entry.name);
+            }
+            req::CONFIG_SDO => {
+                let data = arg_mut::<ec::ec_ioctl_config_sdo_t>(arg);
+                let sdo = self
+                    .config(data.config_index)?
+                    .sdos
+                    .get(data.sdo_pos as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                let size = sdo.data.len().min(data.data.len());
+                data.index = sdo.index;
+                data.subindex = sdo.subindex;
+                data.size = size as _;
+                data.data[..size].copy_from_slice(&sdo.data[..size]);
+                data.complete_access = sdo.complete_access as u8;
+            }
+            req::SC_STATE => {
+                let data = arg_ref::<ec::ec_ioctl_sc_state_t>(arg);
+                self.config(data.config_index)?;
+                let slave = self.attached_slave(data.config_index as usize);
+                let al_state = slave.map(|pos| self.network.al_state(pos));
+                let state = &mut *data.state;
+                state.set_online(slave.is_some() as _);
+                state.set_operational((al_state == Some(AlState::Op)) as _);
+                state.set_al_state(al_state.map_or(0, |s| s as u8 as _));
+            }
+            req::DOMAIN => {
+                let data = arg_mut::<ec::ec_ioctl_domain_t>(arg);
+                let domain = self.domain(data.index)?;
+                data.data_size = domain.size as u32;
+                data.logical_base_address = domain.offset as u32;
+                data.working_counter = [domain.working_counter];
+                data.expected_working_counter = domain.expected_wc();
+                data.fmmu_count = domain.fmmus.len() as u32;
+            }
+            req::DOMAIN_FMMU => {
+                let data = arg_mut::<ec::ec_ioctl_domain_fmmu_t>(arg);
+                let domain = self.domain(data.domain_index)?;
+                let fmmu = domain
+                    .fmmus
+                    .get(data.fmmu_index as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                let config = &self.configs[fmmu.config];
+                data.slave_config_alias = config.alias;
+                data.slave_config_position = config.position;
+                data.sync_index = fmmu.sync;
+                data.dir = fmmu.dir;
+                data.logical_address = (domain.offset + fmmu.offset) as u32;
+                data.data_size = fmmu.size as u32;
+            }
+            req::DOMAIN_DATA => {
+                let data = arg_ref::<ec::ec_ioctl_domain_data_t>(arg);
+                let domain = self.domain(data.domain_index)?;
+                if data.data_size as usize != domain.size {
+                    return Err(errno(libc::EFAULT));
+                }
+                let mut image = domain.image.clone();
+                image.resize(domain.size, 0);
+                ptr::copy_nonoverlapping(image.as_ptr(), data.target, image.len());
+            }
+            req::DOMAIN_SIZE => return Ok(self.domain(arg as u32)?.size as c_int),
+            req::DOMAIN_OFFSET => return Ok(self.domain(arg as u32)?.offset as c_int),
+            req::DOMAIN_STATE => {
+                let data = arg_ref::<ec::ec_ioctl_domain_state_t>(arg);
+                let domain = self.domain(data.domain_index)?;
+                let state = &mut *data.state;
+                state.working_counter = domain.working_counter as _;
+                state.wc_state = domain.wc_state();
+                state.redundancy_active = 0;
+            }
+            req::DOMAIN_PROCESS => {
+                self.check_active()?;
+                let domain = self.domain_mut(arg as u32)?;
+                domain.working_counter = domain.received_wc;
+            }
+            req::DOMAIN_QUEUE => {
+                self.check_active()?;
+                self.domain_mut(arg as u32)?.queued = true;
+            }
+            req::ACTIVATE => {
+                let data = arg_mut::<ec::ec_ioctl_master_activate_t>(arg);
+                if !self.active {
+                    self.activate()?;
+                }
+                data.process_data_size = self.memory.as_ref().map_or(0, |m| m.len()) as _;
+            }
+            req::DEACTIVATE => self.deactivate(),
+            req::SEND => {
+                self.check_active()?;
+                let sent = self.send()?;
+                if arg != 0 {
+                    *arg_mut::<usize>(arg) = sent;
+                }
+            }
+            req::RECEIVE => {
+                self.check_active()?;
+                self.receive()?;
+            }
+            _ => {
+                log::debug!("Simulation does not support {}", request.name);
+                return Err(errno(libc::ENOTTY));
+            }
+        }
+        Ok(0)
+    }
+
+    fn check_idle(&self) -> io::Result<()> {
+        if self.active {
+            Err(errno(libc::EPERM))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_active(&self) -> io::Result<()> {
+        if self.active {
+            Ok(())
+        } else {
+            Err(errno(libc::EPERM))
+        }
+    }
+
+    fn al_states(&self) -> u32 {
+        (0..self.network.slave_count()).fold(0, |states, pos| {
+            states | self.network.al_state(pos) as u8 as u32
+        })
+    }
+
+    fn master_info(&self, data: &mut ec::ec_ioctl_master_t) {
+        data.slave_count = self.network.slave_count() as u32;
+        data.config_count = self.configs.len() as u32;
+        data.domain_count = self.domains.len() as u32;
+        data.eoe_handler_count = 0;
+        data.phase = if self.owner.is_some() { 2 } else { 1 };
+        data.active = self.active as u8;
+        data.scan_busy = 0;
+        data.num_devices = 1;
+        let device = &mut data.devices[0];
+        device.address = [0x02, 0, 0, 0, 0, 0x01];
+        device.attached = 1;
+        device.link_state = 1;
+        device.tx_count = self.stats.tx_count;
+        device.rx_count = self.stats.rx_count;
+        device.tx_bytes = self.stats.tx_bytes;
+        device.rx_bytes = self.stats.rx_bytes;
+        data.tx_count = self.stats.tx_count;
+        data.rx_count = self.stats.rx_count;
+        data.tx_bytes = self.stats.tx_bytes;
+        data.rx_bytes = self.stats.rx_bytes;
+        data.app_time = self.app_time;
+        data.ref_clock = self
+            .ref_clock
+            .and_then(|config| self.attached_slave(config))
+            .map_or(0xFFFF, |pos| pos as u16);
+    }
+
+    fn slave_info(&self, data: &mut ec::ec_ioctl_slave_t) -> io::Result<()> {
+        let slave = self.slave(data.position)?;
+        let desc = self.network.desc(slave);
+        data.vendor_id = desc.id.vendor_id;
+        data.product_code = desc.id.product_code;
+        data.revision_number = desc.rev.revision_number;
+        data.serial_number = desc.rev.serial_number;
+        data.alias = desc.alias;
+        if self.network.has_mailbox(slave) {
+            data.std_rx_mailbox_offset = 0x1000;
+            data.std_rx_mailbox_size = 0x80;
+            data.std_tx_mailbox_offset = 0x1080;
+            data.std_tx_mailbox_size = 0x80;
+            data.mailbox_protocols = MailboxProtocols::COE.bits();
+        }
+        data.has_general_category = 1;
+        data.ports[0].desc = ec::EC_PORT_EBUS;
+        data.ports[0].link.link_up = 1;
+        data.ports[0].link.signal_detected = 1;
+        data.dc_supported = 1;
+        data.has_dc_system_time = 1;
+        data.dc_range = ec::EC_DC_32;
+        data.al_state = self.network.al_state(slave) as u8;
+        data.error_flag = 0;
+        data.sync_count = desc.sync_count();
+        data.sdo_count = desc.sdo_indices().len() as u16;
+        write_c_string(&mut data.name, &desc.name);
+        Ok(())
+    }
+
+    /// Check the position of a slave.
+    fn slave(&self, position: u16) -> io::Result<usize> {
+        if (position as usize) < self.network.slave_count() {
+            Ok(position as usize)
+        } else {
+            Err(errno(libc::EINVAL))
+        }
+    }
+
+    fn config(&self, idx: u32) -> io::Result<&Config> {
+        self.configs
+            .get(idx as usize)
+            .ok_or_else(|| errno(libc::EINVAL))
+    }
+
+    fn config_mut(&mut self, idx: u32) -> io::Result<&mut Config> {
+        self.configs
+            .get_mut(idx as usize)
+            .ok_or_else(|| errno(libc::EINVAL))
+    }
+
+    fn domain(&self, idx: u32) -> io::Result<&Domain> {
+        self.domains
+            .get(idx as usize)
+            .ok_or_else(|| errno(libc::EINVAL))
+    }
+
+    fn domain_mut(&mut self, idx: u32) -> io::Result<&mut Domain> {
+        self.domains
+            .get_mut(idx as usize)
+            .ok_or_else(|| errno(libc::EINVAL))
+    }
+
+    /// The slave a config is attached to: the slave at its address with
+    /// the expected identity.
+    fn attached_slave(&self, config: usize) -> Option<usize> {
+        let config = self.configs.get(config)?;
+        let base = if config.alias == 0 {
+            0
+        } else {
+            (0..self.network.slave_count())
+                .position(|pos| self.network.desc(pos).alias == config.alias)?
+        };
+        let pos = base + config.position as usize;
+        Some(pos).filter(|&pos| {
+            pos < self.network.slave_count() && self.network.desc(pos).id == config.id
+        })
+    }
+
+    /// The PDOs of a config's sync manager, falling back to the default
+    /// assignment and mapping of the attached slave.
+    fn config_pdos(&self, config: usize, sync: u8) -> Vec<PdoCfg> {
+        let slave_sync = self
+            .attached_slave(config)
+            .and_then(|pos| self.network.desc(pos).sync(sync));
+        let config = &self.configs[config];
+        let assigned = match config.syncs.get(&sync).and_then(|s| s.pdos.clone()) {
+            Some(pdos) => pdos,
+            None => slave_sync
+                .map(|s| s.pdos.iter().map(|p| u16::from(p.idx)).collect())
+                .unwrap_or_default(),
+        };
+        assigned
+            .into_iter()
+            .map(|idx| PdoCfg {
+                idx: PdoIdx::from(idx),
+                entries: config
+                    .mappings
+                    .get(&idx)
+                    .cloned()
+                    .or_else(|| {
+                        slave_sync?
+                            .pdos
+                            .iter()
+                            .find(|p| u16::from(p.idx) == idx)
+                            .map(|p| p.entries.clone())
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    fn config_sync_dir(&self, config: usize, sync: u8) -> ec::ec_direction_t {
+        match self.configs[config].syncs.get(&sync) {
+            Some(s) if s.dir != ec::EC_DIR_INVALID => s.dir,
+            _ => self
+                .attached_slave(config)
+                .and_then(|pos| self.network.desc(pos).sync(sync))
+                .map_or(ec::EC_DIR_INVALID, |s| s.cfg.direction as u32),
+        }
+    }
+
+    /// Map a config's sync manager into a domain and return its offset.
+    fn register_fmmu(&mut self, config: usize, sync: u8, domain: u32) -> io::Result<usize> {
+        let dir = self.config_sync_dir(config, sync);
+        if dir == ec::EC_DIR_INVALID {
+            return Err(errno(libc::EINVAL));
+        }
+        let size = sm_size(&self.config_pdos(config, sync));
+        let domain = self.domain_mut(domain)?;
+        if let Some(fmmu) = domain
+            .fmmus
+            .iter()
+            .find(|f| f.config == config && f.sync == sync)
+        {
+            return Ok(fmmu.offset);
+        }
+        let offset = domain.size;
+        domain.fmmus.push(Fmmu {
+            config,
+            sync,
+            dir,
+            offset,
+            size,
+        });
+        domain.size += size;
+        Ok(offset)
+    }
+
+    /// The process data sync managers of a config, with the logical
+    /// addresses they are mapped to.
+    fn sync_layouts(&self, config: usize) -> Vec<SyncLayout> {
+        let cfg = &self.configs[config];
+        (0..ec::EC_MAX_SYNC_MANAGERS as u8)
+            .filter_map(|sync| {
+                let addresses: Vec<_> = self
+                    .domains
+                    .iter()
+                    .flat_map(|d| {
+                        d.fmmus
+                            .iter()
+                            .filter(|f| f.config == config && f.sync == sync)
+                            .map(move |f| d.offset + f.offset)
+                    })
+                    .collect();
+                let sync_cfg = cfg.syncs.get(&sync);
+                let pdos = self.config_pdos(config, sync);
+                let custom = sync_cfg.is_some_and(|s| s.pdos.is_some())
+                    || pdos
+                        .iter()
+                        .any(|p| cfg.mappings.contains_key(&u16::from(p.idx)));
+                if addresses.is_empty() && !custom {
+                    return None;
+                }
+                Some(SyncLayout {
+                    index: sync,
+                    dir: self.config_sync_dir(config, sync),
+                    watchdog_mode: sync_cfg.map(|s| s.watchdog_mode).unwrap_or_default(),
+                    size: sm_size(&pdos),
+                    pdos: if custom { Some(pdos) } else { None },
+                    addresses,
+                })
+            })
+            .collect()
+    }
+
+    fn activate(&mut self) -> io::Result<()> {
+        let mut offset = 0;
+        for domain in &mut self.domains {
+            domain.offset = offset;
+            domain.image = vec![0; domain.size];
+            offset += domain.size;
+        }
+        self.memory = Some(SharedMemory::new(Box::new(vec![0; offset])));
+        for config in 0..self.configs.len() {
+            if let Some(pos) = self.attached_slave(config) {
+                for sdo in &self.configs[config].sdos {
+                    let res = self.network.sdo_download(
+                        pos,
+                        sdo.index,
+                        sdo.subindex,
+                        sdo.complete_access,
+                        &sdo.data,
+                    );
+                    if let Err(err) = res {
+                        log::warn!(
+                            "Startup SDO 0x{:04X}:{} of slave {} failed: {:?}",
+                            sdo.index,
+                            sdo.subindex,
+                            pos,
+                            err
+                        );
+                    }
+                }
+                let syncs = self.sync_layouts(config);
+                self.network
+                    .configure(pos, &syncs)
+                    .map_err(|err| match err {
+                        ConfigError::SdoAbort { idx, code } => {
+                            self.failure = Some(Error::ConfigSdoAbort {
+                                slave: SlavePos::from(pos as u16),
+                                idx,
+                                code,
+                            });
+                            errno(libc::EIO)
+                        }
+                        ConfigError::Io(err) => err,
+                    })?;
+                self.network.request_state(pos, AlState::Op, false)?;
+            }
+        }
+        self.active = true;
+        Ok(())
+    }
+
+    fn deactivate(&mut self) {
+        self.active = false;
+        self.memory = None;
+        self.configs.clear();
+        self.domains.clear();
+        self.ref_clock = None;
+        for pos in 0..self.network.slave_count() {
+            let res = self
+                .network
+                .request_state(pos, AlState::PreOp, true)
+                .map_err(ConfigError::from)
+                .and_then(|_| self.network.configure(pos, &[]));
+            if let Err(err) = res {
+                log::warn!("Failed to reset slave {}: {}", pos, err);
+            }
+        }
+    }
+
+    /// Send the process data of all queued domains to the slaves.
+    fn send(&mut self) -> io::Result<usize> {
+        let memory = match &self.memory {
+            Some(memory) => memory.clone(),
+            None => return Ok(0),
+        };
+        // Safety: called while executing a request of the owning master.
+        let memory = unsafe { memory.data() };
+        let mut sent = 0;
+        for (i, domain) in self.domains.iter_mut().enumerate() {
+            if !domain.queued {
+                continue;
+            }
+            let data = &memory[domain.offset..domain.offset + domain.size];
+            domain.image.copy_from_slice(data);
+            domain.queued = false;
+            self.network.send(i, domain.offset, data)?;
+            sent += domain.size;
+        }
+        self.stats.tx_count += 1;
+        self.stats.tx_bytes += sent as u64;
+        Ok(sent)
+    }
+
+    /// Store the inputs of the answers to the last sent process data.
+    fn receive(&mut self) -> io::Result<()> {
+        let answers = self.network.receive()?;
+        let memory = match &self.memory {
+            Some(memory) => memory.clone(),
+            None => return Ok(()),
+        };
+        // Safety: called while executing a request of the owning master.
+        let memory = unsafe { memory.data() };
+        for answer in answers {
+            let domain = match self.domains.get_mut(answer.domain) {
+                Some(domain) if domain.size == answer.data.len() => domain,
+                _ => continue,
+            };
+            for fmmu in domain.fmmus.iter().filter(|f| f.dir == ec::EC_DIR_INPUT) {
+                let range = fmmu.offset..fmmu.offset + fmmu.size;
+                let input = &answer.data[range.clone()];
+                let start = domain.offset + fmmu.offset;
+                memory[start..start + fmmu.size].copy_from_slice(input);
+                domain.image[range].copy_from_slice(input);
+            }
+            domain.received_wc = answer.working_counter;
+            self.stats.rx_count += 1;
+            self.stats.rx_bytes += domain.size as u64;
+        }
+        Ok(())
+    }
+}
+
+/// The errno of a failed SDO transfer, storing the abort code.
+fn sdo_errno(err: SdoError, abort_code: &mut u32) -> io::Error {
+    match err {
+        SdoError::Abort(abort) => {
+            *abort_code = abort.code();
+            errno(libc::EIO)
+        }
+        SdoError::Io(err) => err,
+    }
+}
+
+/// Size in bytes of the process data of a sync manager.
+fn sm_size(pdos: &[PdoCfg]) -> usize {
+    let bits: usize = pdos
+        .iter()
+        .flat_map(|p| &p.entries)
+        .map(|e| e.bit_len as usize)
+        .sum();
+    bits.div_ceil(8)
+}
@@ -0,0 +1,146 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! File access over EtherCAT (FoE)
+
+use std::fmt;
+use thiserror::Error;
+
+/// Error code sent by a slave to abort an FoE transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoeErrorCode {
+    NotDefined,
+    NotFound,
+    AccessDenied,
+    DiskFull,
+    Illegal,
+    PacketNumberWrong,
+    AlreadyExists,
+    NoUser,
+    BootstrapOnly,
+    NotBootstrap,
+    NoRights,
+    ProgramError,
+    Other(u32),
+}
+
+impl From<u32> for FoeErrorCode {
+    fn from(code: u32) -> Self {
+        use FoeErrorCode::*;
+        match code {
+            0x8000 => NotDefined,
+            0x8001 => NotFound,
+            0x8002 => AccessDenied,
+            0x8003 => DiskFull,
+            0x8004 => Illegal,
+            0x8005 => PacketNumberWrong,
+            0x8006 => AlreadyExists,
+            0x8007 => NoUser,
+            0x8008 => BootstrapOnly,
+            0x8009 => NotBootstrap,
+            0x800A => NoRights,
+            0x800B => ProgramError,
+            x => Other(x),
+        }
+    }
+}
+
+impl fmt::Display for FoeErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use FoeErrorCode::*;
+        match self {
+            NotDefined => write!(f, "not defined"),
+            NotFound => write!(f, "file not found"),
+            AccessDenied => write!(f, "access denied"),
+            DiskFull => write!(f, "disk full"),
+            Illegal => write!(f, "illegal operation"),
+            PacketNumberWrong => write!(f, "wrong packet number"),
+            AlreadyExists => write!(f, "file already exists"),
+            NoUser => write!(f, "no user"),
+            BootstrapOnly => write!(f, "only allowed in Bootstrap state"),
+            NotBootstrap => write!(f, "not allowed in Bootstrap state"),
+            NoRights => write!(f, "no rights"),
+            ProgramError => write!(f, "program error"),
+            Other(x) => write!(f, "error code 0x{:04X}", x),
+        }
+    }
+}
+
+/// A failed FoE transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum FoeError {
+    #[error("FoE working counter error")]
+    WorkingCounter,
+    #[error("FoE receive error")]
+    Receive,
+    #[error("FoE protocol error")]
+    Protocol,
+    #[error("FoE no data error")]
+    NoData,
+    #[error("FoE packet number error")]
+    PacketNumber,
+    #[error("FoE transfer aborted by slave: {0}")]
+    Opcode(FoeErrorCode),
+    #[error("FoE timeout")]
+    Timeout,
+    #[error("FoE error sending received data")]
+    SendRxData,
+    #[error("FoE error acknowledging received data")]
+    RxDataAck,
+    #[error("FoE acknowledge error")]
+    Ack,
+    #[error("FoE error fetching data from mailbox")]
+    MailboxFetch,
+    #[error("FoE no data while reading")]
+    ReadNoData,
+    #[error("FoE mailbox protocol error")]
+    MailboxProtocol,
+    #[error("Unknown FoE result {0}")]
+    Unknown(u32),
+    #[error("FoE file name is too long")]
+    FileNameTooLong,
+    #[error("FoE passwords are not supported by the kernel module")]
+    PasswordNotSupported,
+}
+
+impl FoeError {
+    /// Decode the `result` and `error_code` fields reported by the master.
+    ///
+    /// Returns `None` if the result does not indicate an error.
+    pub(crate) fn from_result(result: u32, error_code: u32) -> Option<Self> {
+        use FoeError::*;
+        Some(match result {
+            // busy, ready, idle
+            0..=2 => return None,
+            3 => WorkingCounter,
+            4 => Receive,
+            5 => Protocol,
+            6 => NoData,
+            7 => PacketNumber,
+            8 => Opcode(FoeErrorCode::from(error_code)),
+            9 => Timeout,
+            10 => SendRxData,
+            11 => RxDataAck,
+            12 => Ack,
+            13 => MailboxFetch,
+            14 => ReadNoData,
+            15 => MailboxProtocol,
+            x => Unknown(x),
+        })
+    }
+}
+
+#[test]
+fn test_foe_error_from_result() {
+    assert_eq!(FoeError::from_result(1, 0), None);
+    assert_eq!(FoeError::from_result(9, 0), Some(FoeError::Timeout));
+    assert_eq!(
+        FoeError::from_result(8, 0x8001),
+        Some(FoeError::Opcode(FoeErrorCode::NotFound))
+    );
+    assert_eq!(
+        FoeError::from_result(8, 0x1234),
+        Some(FoeError::Opcode(FoeErrorCode::Other(0x1234)))
+    );
+    assert_eq!(FoeError::from_result(99, 0), Some(FoeError::Unknown(99)));
+}
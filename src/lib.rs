@@ -3,10 +3,29 @@
 
 use ethercat_sys as ec;
 
+pub mod backend;
+mod emulation;
+mod foe;
 mod master;
+#[cfg(feature = "packet")]
+pub mod packet;
+pub mod record;
+pub mod register;
+mod sdo;
+pub mod sii;
+pub mod sim;
+mod soe;
+#[cfg(test)]
+mod testing;
 mod types;
 
 pub use self::{
-    master::{Domain, Master, MasterAccess, SlaveConfig},
+    foe::{FoeError, FoeErrorCode},
+    master::{
+        Active, Configuring, Domain, Idle, Master, MasterAccess, Phase, PhaseError, RegRequest,
+        Reserved, SdoRequest, SlaveConfig, VoeHandler,
+    },
+    sdo::SdoAbortCode,
+    soe::{Idn, ParseIdnError, SoeError},
     types::*,
 };
@@ -1,34 +1,151 @@
 // Part of ethercat-rs. Copyright 2018-2020 by the authors.
 // This work is dual-licensed under Apache 2.0 and MIT terms.
 
-use crate::{ec, types::*};
+use crate::{
+    backend::{Backend, DeviceBackend, ProcessDataMemory},
+    ec,
+    foe::FoeError,
+    register::Register,
+    sdo::SdoAbortCode,
+    sii::SiiMailbox,
+    soe::{Idn, SoeError},
+    types::*,
+};
 use num_traits::cast::FromPrimitive;
 use std::{
     collections::HashMap,
     convert::TryFrom,
-    ffi::CStr,
-    fs::{File, OpenOptions},
-    io,
-    os::{raw::c_ulong, unix::io::AsRawFd},
+    fmt, io,
+    marker::PhantomData,
+    os::raw::{c_char, c_int, c_ulong},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
 };
 
 macro_rules! ioctl {
-    ($m:expr, $f:expr) => { ioctl!($m, $f,) };
-    ($m:expr, $f:expr, $($arg:tt)*) => {{
-        let res = unsafe { $f($m.file.as_raw_fd(), $($arg)*) };
-        if res < 0 { Err(Error::Io(io::Error::last_os_error())) } else { Ok(res) }
-    }}
+    ($m:expr, $req:expr; $target:expr) => { unsafe { $m.ioctl($req, (), $target) } };
+    ($m:expr, $req:expr, $arg:expr; $target:expr) => { unsafe { $m.ioctl($req, $arg, $target) } };
+    ($m:expr, $req:expr) => { ioctl!($m, $req; ErrorTarget::Master) };
+    ($m:expr, $req:expr, $arg:expr) => { ioctl!($m, $req, $arg; ErrorTarget::Master) };
 }
 
 /// An EtherCAT master.
-pub struct Master {
-    file: File,
-    map: Option<memmap::MmapMut>,
+///
+/// The type parameter tracks the phase of the master, so that operations
+/// are only available when the master is able to perform them:
+///
+/// * `Idle`: opened, but not reserved for an application; the bus can
+///   be inspected and slaves can be accessed via their mailbox.
+/// * `Configuring`: reserved with `reserve`; domains and slave
+///   configurations can be created.
+/// * `Active`: activated with `activate`; process data is exchanged
+///   with `send` and `receive`.
+///
+/// If changing the phase fails, the master is handed back in a `PhaseError`.
+///
+/// Using an operation in the wrong phase fails to compile:
+///
+/// ```compile_fail
+/// # use ethercat::{Master, MasterAccess};
+/// let mut master = Master::open(0, MasterAccess::ReadWrite)?;
+/// let domain = master.create_domain()?; // the master is not reserved
+/// # Ok::<(), ethercat::Error>(())
+/// ```
+pub struct Master<P = Idle> {
+    backend: Box<dyn Backend>,
+    map: Option<Box<dyn ProcessDataMemory>>,
     domains: HashMap<DomainIdx, DomainDataPlacement>,
+    /// Distinguishes the masters of the process, for handles to check
+    /// that they are used with the master that created them.
+    id: usize,
+    /// The number of times the slave configurations were discarded, for
+    /// handles to check that they were created since.
+    generation: u32,
+    phase: PhantomData<P>,
+}
+
+static NEXT_MASTER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The master and configuration generation a handle was created in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Origin {
+    master: usize,
+    generation: u32,
+}
+
+/// Phase of a `Master`.
+pub trait Phase: private::Sealed {}
+
+/// Phases in which the master is reserved by the application.
+pub trait Reserved: Phase {}
+
+/// Phase of a master which is not reserved by an application.
+pub enum Idle {}
+
+/// Phase of a reserved master which is not activated yet.
+pub enum Configuring {}
+
+/// Phase of an activated master.
+pub enum Active {}
+
+impl Phase for Idle {}
+impl Phase for Configuring {}
+impl Phase for Active {}
+impl Reserved for Configuring {}
+impl Reserved for Active {}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for super::Idle {}
+    impl Sealed for super::Configuring {}
+    impl Sealed for super::Active {}
+}
+
+/// A failed change of the phase of a `Master`.
+///
+/// Hands back the master in the phase it was in before, so that the
+/// application can retry or continue in that phase.
+pub struct PhaseError<P> {
+    pub master: Box<Master<P>>,
+    pub error: Error,
+}
+
+impl<P> fmt::Debug for PhaseError<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PhaseError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> fmt::Display for PhaseError<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<P> std::error::Error for PhaseError<P> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.error)
+    }
+}
+
+impl<P> From<PhaseError<P>> for Error {
+    fn from(e: PhaseError<P>) -> Self {
+        e.error
+    }
+}
+
+impl<P> From<PhaseError<P>> for io::Error {
+    fn from(e: PhaseError<P>) -> Self {
+        e.error.into()
+    }
 }
 
 pub struct Domain<'m> {
-    master: &'m Master,
+    master: &'m Master<Active>,
     idx: DomainIdx,
 }
 
@@ -38,21 +155,24 @@ pub enum MasterAccess {
     ReadWrite,
 }
 
-impl Master {
+impl Master<Idle> {
     pub fn open(idx: MasterIdx, access: MasterAccess) -> Result<Self> {
-        let devpath = format!("/dev/EtherCAT{}", idx);
-        log::debug!("Open EtherCAT Master {}", devpath);
-        let file = OpenOptions::new()
-            .read(true)
-            .write(access == MasterAccess::ReadWrite)
-            .open(&devpath)?;
+        Self::with_backend(DeviceBackend::open(idx, access)?)
+    }
+
+    /// Create a master that sends all requests to the given backend
+    /// instead of the kernel module.
+    pub fn with_backend<B: Backend + 'static>(backend: B) -> Result<Self> {
         let mut module_info = ec::ec_ioctl_module_t::default();
         let master = Master {
-            file,
+            backend: Box::new(backend),
             map: None,
             domains: HashMap::new(),
+            id: NEXT_MASTER_ID.fetch_add(1, Ordering::Relaxed),
+            generation: 0,
+            phase: PhantomData,
         };
-        ioctl!(master, ec::ioctl::MODULE, &mut module_info)?;
+        ioctl!(master, ec::request::MODULE, &mut module_info)?;
         if module_info.ioctl_version_magic != ec::EC_IOCTL_VERSION_MAGIC {
             return Err(Error::KernelModule(
                 ec::EC_IOCTL_VERSION_MAGIC,
@@ -65,91 +185,171 @@ impl Master {
     pub fn master_count() -> Result<usize> {
         let master = Self::open(0, MasterAccess::ReadOnly)?;
         let mut module_info = ec::ec_ioctl_module_t::default();
-        ioctl!(master, ec::ioctl::MODULE, &mut module_info)?;
+        ioctl!(master, ec::request::MODULE, &mut module_info)?;
         Ok(module_info.master_count as usize)
     }
 
-    pub fn reserve(&self) -> Result<()> {
+    /// Reserve the master for this application, to configure it.
+    pub fn reserve(self) -> std::result::Result<Master<Configuring>, PhaseError<Idle>> {
         log::debug!("Reserve EtherCAT Master");
-        ioctl!(self, ec::ioctl::REQUEST)?;
-        Ok(())
+        match ioctl!(self, ec::request::REQUEST) {
+            Ok(_) => Ok(self.into_phase()),
+            Err(error) => Err(PhaseError {
+                master: Box::new(self),
+                error,
+            }),
+        }
     }
+}
 
-    pub fn create_domain(&self) -> Result<DomainIdx> {
-        Ok((ioctl!(self, ec::ioctl::CREATE_DOMAIN)? as usize).into())
+impl<P: Phase> Master<P> {
+    fn into_phase<Q>(self) -> Master<Q> {
+        Master {
+            backend: self.backend,
+            map: self.map,
+            domains: self.domains,
+            id: self.id,
+            generation: self.generation,
+            phase: PhantomData,
+        }
     }
 
-    pub const fn domain(&self, idx: DomainIdx) -> Domain {
-        Domain::new(idx, self)
+    fn origin(&self) -> Origin {
+        Origin {
+            master: self.id,
+            generation: self.generation,
+        }
     }
 
-    pub fn domain_data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
-        let p = self
-            .domain_data_placement(idx)
-            .map_err(|_| Error::NoDomain)?;
-        let data = self.map.as_mut().ok_or_else(|| Error::NotActivated)?;
-        Ok(&mut data[p.offset..p.offset + p.size])
+    /// Check that a handle of the given slave configuration belongs to
+    /// this master and its current configuration.
+    fn check_handle(&self, origin: Origin, config: SlaveConfigIdx) -> Result<()> {
+        if origin == self.origin() {
+            Ok(())
+        } else {
+            Err(Error::StaleHandle(ErrorTarget::Config(config)))
+        }
     }
 
-    fn domain_data_placement(&mut self, idx: DomainIdx) -> Result<DomainDataPlacement> {
-        Ok(match self.domains.get(&idx) {
-            None => {
-                let d_idx =
-                    c_ulong::try_from(idx).map_err(|_| Error::DomainIdx(usize::from(idx)))?;
-                let offset = ioctl!(self, ec::ioctl::DOMAIN_OFFSET, d_idx)? as usize;
-                let size = ioctl!(self, ec::ioctl::DOMAIN_SIZE, d_idx)? as usize;
-                let meta_data = DomainDataPlacement { offset, size };
-                self.domains.insert(idx, meta_data);
-                meta_data
-            }
-            Some(d) => *d,
+    /// Execute a request via the backend.
+    ///
+    /// # Safety
+    ///
+    /// Pointers in `arg` must be valid as required by `Backend::ioctl`.
+    unsafe fn ioctl<A: ec::IoctlArg>(
+        &self,
+        request: ec::Request<A>,
+        arg: A,
+        target: ErrorTarget,
+    ) -> Result<c_int> {
+        let request = request.raw();
+        self.backend.ioctl(request, arg.into_raw()).map_err(|e| {
+            self.backend
+                .take_error()
+                .unwrap_or_else(|| Error::from_ioctl(request.name, target, e))
         })
     }
 
-    pub fn activate(&mut self) -> Result<()> {
-        log::debug!("Activate EtherCAT Master");
-        let mut data = ec::ec_ioctl_master_activate_t::default();
-        ioctl!(self, ec::ioctl::ACTIVATE, &mut data)?;
-
-        self.map = unsafe {
-            memmap::MmapOptions::new()
-                .len(data.process_data_size as usize)
-                .map_mut(&self.file)
-                .map(Some)?
-        };
-        self.map.as_mut().ok_or_else(|| Error::NotActivated)?[0] = 0;
-        Ok(())
-    }
-
-    pub fn deactivate(&mut self) -> Result<()> {
-        log::debug!("Deactivate EtherCAT Master");
-        ioctl!(self, ec::ioctl::DEACTIVATE)?;
-        self.domains.clear();
-        self.map = None;
-        Ok(())
+    /// Trigger a rescan of the bus.
+    ///
+    /// The scan is performed asynchronously by the master,
+    /// use `wait_for_scan` to wait until it is finished.
+    pub fn rescan(&mut self) -> Result<()> {
+        log::debug!("Rescan EtherCAT bus");
+        ioctl!(self, ec::request::MASTER_RESCAN).map(|_| ())
     }
 
-    pub fn set_send_interval(&mut self, interval_us: usize) -> Result<()> {
-        ioctl!(self, ec::ioctl::SET_SEND_INTERVAL, &interval_us).map(|_| ())
+    /// Wait until the bus scan is finished and the topology has settled.
+    ///
+    /// Since a scan triggered by `rescan` does not start immediately, the
+    /// scan has to be idle and the slave count unchanged for a short
+    /// period before the topology is considered settled.
+    pub fn wait_for_scan(&self, timeout: Duration) -> Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+        const SETTLED_POLLS: usize = 5;
+        let start = Instant::now();
+        let mut idle_polls = 0;
+        let mut slave_count = None;
+        loop {
+            let info = self.get_info()?;
+            if info.scan_busy || slave_count != Some(info.slave_count) {
+                idle_polls = 0;
+            } else {
+                idle_polls += 1;
+                if idle_polls >= SETTLED_POLLS {
+                    return Ok(());
+                }
+            }
+            slave_count = Some(info.slave_count);
+            if start.elapsed() >= timeout {
+                return Err(Error::ScanTimeout);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
     }
 
-    pub fn send(&mut self) -> Result<usize> {
-        let mut sent = 0;
-        ioctl!(self, ec::ioctl::SEND, &mut sent as *mut _ as c_ulong)?;
-        Ok(sent)
+    /// Set the debug level of the master (0 = off, 1 = messages, 2 = frame dumps).
+    pub fn set_debug_level(&mut self, level: u32) -> Result<()> {
+        ioctl!(self, ec::request::MASTER_DEBUG, c_ulong::from(level)).map(|_| ())
     }
 
-    pub fn receive(&mut self) -> Result<()> {
-        ioctl!(self, ec::ioctl::RECEIVE).map(|_| ())
+    pub fn domain_info(&self, idx: DomainIdx) -> Result<DomainInfo> {
+        let index = u32::try_from(idx).map_err(|_| Error::DomainIdx(usize::from(idx)))?;
+        let mut data = ec::ec_ioctl_domain_t {
+            index,
+            ..Default::default()
+        };
+        ioctl!(self, ec::request::DOMAIN, &mut data; ErrorTarget::Domain(idx))?;
+        let fmmus = (0..data.fmmu_count)
+            .map(|fmmu_index| {
+                let mut fmmu = ec::ec_ioctl_domain_fmmu_t {
+                    domain_index: index,
+                    fmmu_index,
+                    ..Default::default()
+                };
+                ioctl!(self, ec::request::DOMAIN_FMMU, &mut fmmu; ErrorTarget::Domain(idx))?;
+                Ok(DomainFmmuInfo {
+                    slave_config_alias: fmmu.slave_config_alias,
+                    slave_config_position: fmmu.slave_config_position,
+                    sync_index: SmIdx::from(fmmu.sync_index),
+                    direction: SyncDirection::try_from(fmmu.dir)
+                        .map_err(|_| Error::InvalidSyncDirection(fmmu.dir))?,
+                    logical_address: fmmu.logical_address,
+                    data_size: fmmu.data_size as usize,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(DomainInfo {
+            logical_base_address: data.logical_base_address,
+            data_size: data.data_size as usize,
+            working_counters: data.working_counter.to_vec(),
+            expected_working_counter: data.expected_working_counter,
+            fmmus,
+        })
     }
 
-    pub fn reset(&mut self) -> Result<()> {
-        ioctl!(self, ec::ioctl::RESET).map(|_| ())
+    /// Copy the current process data of a domain.
+    ///
+    /// In contrast to `domain_data`, this works with `MasterAccess::ReadOnly`
+    /// and without activating the master, e.g. to monitor the process data
+    /// of another application.
+    pub fn copy_domain_data(&self, idx: DomainIdx) -> Result<Vec<u8>> {
+        let domain_index = u32::try_from(idx).map_err(|_| Error::DomainIdx(usize::from(idx)))?;
+        let data_size = self.domain_info(idx)?.data_size;
+        let mut target = vec![0; data_size];
+        // The kernel rejects the request if the size does not match exactly.
+        let mut data = ec::ec_ioctl_domain_data_t {
+            domain_index,
+            data_size: data_size as u32,
+            target: target.as_mut_ptr(),
+        };
+        ioctl!(self, ec::request::DOMAIN_DATA, &mut data; ErrorTarget::Domain(idx))?;
+        Ok(target)
     }
 
     pub fn state(&self) -> Result<MasterState> {
         let mut data = ec::ec_master_state_t::default();
-        ioctl!(self, ec::ioctl::MASTER_STATE, &mut data)?;
+        ioctl!(self, ec::request::MASTER_STATE, &mut data)?;
         Ok(MasterState {
             slaves_responding: data.slaves_responding,
             al_states: data.al_states() as u8,
@@ -163,7 +363,7 @@ impl Master {
             dev_idx,
             state: &mut state,
         };
-        ioctl!(self, ec::ioctl::MASTER_LINK_STATE, &mut data)?;
+        ioctl!(self, ec::request::MASTER_LINK_STATE, &mut data)?;
         Ok(MasterState {
             slaves_responding: state.slaves_responding,
             al_states: state.al_states() as u8,
@@ -173,136 +373,141 @@ impl Master {
 
     pub fn get_info(&self) -> Result<MasterInfo> {
         let mut data = ec::ec_ioctl_master_t::default();
-        ioctl!(self, ec::ioctl::MASTER, &mut data)?;
-        let ec::ec_ioctl_master_t {
-            slave_count,
-            devices,
-            scan_busy,
-            app_time,
-            ..
-        } = data;
-        let first_device = devices.get(0).ok_or_else(|| Error::NoDevices)?;
-        let link_up = first_device.link_state != 0;
-        let scan_busy = scan_busy != 0;
-        Ok(MasterInfo {
-            slave_count,
-            link_up,
-            scan_busy,
-            app_time,
-        })
+        ioctl!(self, ec::request::MASTER, &mut data)?;
+        decode_master_info(&data)
     }
 
     pub fn get_slave_info(&self, position: SlavePos) -> Result<SlaveInfo> {
         let mut data = ec::ec_ioctl_slave_t::default();
         data.position = u16::from(position);
-        ioctl!(self, ec::ioctl::SLAVE, &mut data)?;
-        let mut ports = [SlavePortInfo::default(); ec::EC_MAX_PORTS as usize];
-        for (i, port) in ports.iter_mut().enumerate().take(ec::EC_MAX_PORTS as usize) {
-            port.desc = match data.ports[i].desc {
-                ec::EC_PORT_NOT_IMPLEMENTED => SlavePortType::NotImplemented,
-                ec::EC_PORT_NOT_CONFIGURED => SlavePortType::NotConfigured,
-                ec::EC_PORT_EBUS => SlavePortType::EBus,
-                ec::EC_PORT_MII => SlavePortType::MII,
-                x => panic!("invalid port type {}", x),
-            };
-            port.link = SlavePortLink {
-                link_up: data.ports[i].link.link_up != 0,
-                loop_closed: data.ports[i].link.loop_closed != 0,
-                signal_detected: data.ports[i].link.signal_detected != 0,
-            };
-            port.receive_time = data.ports[i].receive_time;
-            port.next_slave = data.ports[i].next_slave;
-            port.delay_to_next_dc = data.ports[i].delay_to_next_dc;
-        }
-        Ok(SlaveInfo {
-            name: unsafe {
-                CStr::from_ptr(data.name.as_ptr())
-                    .to_string_lossy()
-                    .into_owned()
-            },
-            ring_pos: data.position,
-            id: SlaveId {
-                vendor_id: data.vendor_id,
-                product_code: data.product_code,
-            },
-            rev: SlaveRev {
-                revision_number: data.revision_number,
-                serial_number: data.serial_number,
-            },
-            alias: data.alias,
-            current_on_ebus: data.current_on_ebus,
-            al_state: AlState::try_from(data.al_state)
-                .map_err(|_| Error::InvalidAlState(data.al_state))?,
-            error_flag: data.error_flag,
-            sync_count: data.sync_count,
-            sdo_count: data.sdo_count,
-            ports,
-        })
+        ioctl!(self, ec::request::SLAVE, &mut data; ErrorTarget::Slave(position))?;
+        decode_slave_info(&data)
     }
 
     pub fn get_config_info(&self, idx: SlaveConfigIdx) -> Result<ConfigInfo> {
         let mut data = ec::ec_ioctl_config_t::default();
         data.config_index = idx;
-        ioctl!(self, ec::ioctl::CONFIG, &mut data)?;
-        let id = SlaveId {
-            vendor_id: data.vendor_id,
-            product_code: data.product_code,
+        ioctl!(self, ec::request::CONFIG, &mut data; ErrorTarget::Config(idx))?;
+        decode_config_info(&data)
+    }
+
+    pub fn get_config_pdo(
+        &self,
+        idx: SlaveConfigIdx,
+        sync_index: SmIdx,
+        pdo_pos: PdoPos,
+    ) -> Result<PdoInfo> {
+        let mut pdo = ec::ec_ioctl_config_pdo_t {
+            config_index: idx,
+            sync_index: u8::from(sync_index),
+            pdo_pos: u8::from(pdo_pos) as u16,
+            ..Default::default()
         };
-        let slave_position = if data.slave_position == -1 {
-            None
-        } else {
-            Some(SlavePos::from(data.slave_position as u16))
+        ioctl!(self, ec::request::CONFIG_PDO, &mut pdo; ErrorTarget::Config(idx))?;
+        Ok(PdoInfo {
+            sm: sync_index,
+            pos: pdo_pos,
+            idx: Idx::from(pdo.index),
+            entry_count: pdo.entry_count,
+            name: c_array_to_string(&pdo.name),
+        })
+    }
+
+    pub fn get_config_pdo_entry(
+        &self,
+        idx: SlaveConfigIdx,
+        sync_index: SmIdx,
+        pdo_pos: PdoPos,
+        entry_pos: PdoEntryPos,
+    ) -> Result<PdoEntryInfo> {
+        let mut entry = ec::ec_ioctl_config_pdo_entry_t {
+            config_index: idx,
+            sync_index: u8::from(sync_index),
+            pdo_pos: u8::from(pdo_pos) as u16,
+            entry_pos: u8::from(entry_pos),
+            ..Default::default()
         };
-        Ok(ConfigInfo {
-            alias: data.alias,
-            position: data.position,
-            id,
-            slave_position,
-            sdo_count: data.sdo_count,
-            idn_count: data.idn_count,
+        ioctl!(self, ec::request::CONFIG_PDO_ENTRY, &mut entry; ErrorTarget::Config(idx))?;
+        Ok(PdoEntryInfo {
+            pos: entry_pos,
+            entry_idx: PdoEntryIdx {
+                idx: Idx::from(entry.index),
+                sub_idx: SubIdx::from(entry.subindex),
+            },
+            bit_len: entry.bit_length,
+            name: c_array_to_string(&entry.name),
         })
     }
 
-    pub fn configure_slave(&mut self, addr: SlaveAddr, expected: SlaveId) -> Result<SlaveConfig> {
-        log::debug!("Configure slave {:?}", addr);
-        let mut data = ec::ec_ioctl_config_t::default();
-        let (alias, pos) = addr.as_pair();
-        data.alias = alias;
-        data.position = pos;
-        data.vendor_id = expected.vendor_id;
-        data.product_code = expected.product_code;
-        ioctl!(self, ec::ioctl::CREATE_SLAVE_CONFIG, &mut data)?;
-        Ok(SlaveConfig {
-            master: self,
-            idx: data.config_index,
+    /// Get the startup SDO at position `sdo_pos` of a slave config.
+    pub fn get_config_sdo(&self, idx: SlaveConfigIdx, sdo_pos: u32) -> Result<ConfigSdo> {
+        let mut sdo = ec::ec_ioctl_config_sdo_t {
+            config_index: idx,
+            sdo_pos,
+            ..Default::default()
+        };
+        ioctl!(self, ec::request::CONFIG_SDO, &mut sdo; ErrorTarget::Config(idx))?;
+        let size = (sdo.size as usize).min(sdo.data.len());
+        Ok(ConfigSdo {
+            idx: SdoIdx::new(sdo.index, sdo.subindex),
+            complete_access: sdo.complete_access != 0,
+            data: sdo.data[..size].to_vec(),
         })
     }
 
+    /// Get the IDN at position `idn_pos` of a slave config.
+    pub fn get_config_idn(&self, idx: SlaveConfigIdx, idn_pos: u32) -> Result<ConfigIdn> {
+        let mut idn = ec::ec_ioctl_config_idn_t {
+            config_index: idx,
+            idn_pos,
+            ..Default::default()
+        };
+        ioctl!(self, ec::request::CONFIG_IDN, &mut idn; ErrorTarget::Config(idx))?;
+        decode_config_idn(&idn)
+    }
+
+    /// List the assigned PDOs of a slave config together with their mapping.
+    pub fn config_pdos(&self, idx: SlaveConfigIdx) -> Result<Vec<ConfigPdo>> {
+        let mut pdos = vec![];
+        for sync in self.get_config_info(idx)?.syncs {
+            for pdo_pos in 0..sync.pdo_count {
+                let info = self.get_config_pdo(idx, sync.idx, PdoPos::from(pdo_pos as u8))?;
+                let entries = (0..info.entry_count)
+                    .map(|entry_pos| {
+                        self.get_config_pdo_entry(
+                            idx,
+                            sync.idx,
+                            info.pos,
+                            PdoEntryPos::from(entry_pos),
+                        )
+                    })
+                    .collect::<Result<_>>()?;
+                pdos.push(ConfigPdo { info, entries });
+            }
+        }
+        Ok(pdos)
+    }
+
+    /// List the startup SDOs of a slave config.
+    pub fn config_sdos(&self, idx: SlaveConfigIdx) -> Result<Vec<ConfigSdo>> {
+        (0..self.get_config_info(idx)?.sdo_count)
+            .map(|pos| self.get_config_sdo(idx, pos))
+            .collect()
+    }
+
+    /// List the IDNs of a slave config.
+    pub fn config_idns(&self, idx: SlaveConfigIdx) -> Result<Vec<ConfigIdn>> {
+        (0..self.get_config_info(idx)?.idn_count)
+            .map(|pos| self.get_config_idn(idx, pos))
+            .collect()
+    }
+
     pub fn get_sdo(&mut self, slave_pos: SlavePos, sdo_pos: SdoPos) -> Result<SdoInfo> {
         let mut sdo = ec::ec_ioctl_slave_sdo_t::default();
         sdo.slave_position = u16::from(slave_pos);
         sdo.sdo_position = u16::from(sdo_pos);
-        ioctl!(self, ec::ioctl::SLAVE_SDO, &mut sdo)?;
-        #[cfg(feature = "sncn")]
-        {
-            Ok(SdoInfo {
-                pos: SdoPos::from(sdo.sdo_position),
-                idx: Idx::from(sdo.sdo_index),
-                max_sub_idx: SubIdx::from(sdo.max_subindex),
-                object_code: Some(sdo.object_code),
-                name: c_array_to_string(sdo.name.as_ptr()),
-            })
-        }
-        #[cfg(not(feature = "sncn"))]
-        {
-            Ok(SdoInfo {
-                pos: SdoPos::from(sdo.sdo_position),
-                idx: Idx::from(sdo.sdo_index),
-                max_sub_idx: SubIdx::from(sdo.max_subindex),
-                object_code: None,
-                name: c_array_to_string(sdo.name.as_ptr()),
-            })
-        }
+        ioctl!(self, ec::request::SLAVE_SDO, &mut sdo; ErrorTarget::Slave(slave_pos))?;
+        Ok(decode_sdo_info(&sdo))
     }
 
     pub fn get_sdo_entry(
@@ -318,27 +523,22 @@ impl Master {
         };
         entry.sdo_spec = spec;
         entry.sdo_entry_subindex = u8::from(sub);
-        ioctl!(self, ec::ioctl::SLAVE_SDO_ENTRY, &mut entry)?;
-        Ok(SdoEntryInfo {
-            data_type: DataType::from_u16(entry.data_type).unwrap_or_else(|| {
-                let fallback = DataType::Raw;
-                log::warn!(
-                    "Slave {} / SDO {}: Unknown data type (type value: {:X}): use '{:?}' as fallback",
-                    u16::from(slave_pos),
-                    match addr {
-                        SdoEntryAddr::ByPos(pos, sub) => format!("{:?} {:?} ", pos, sub),
-                        SdoEntryAddr::ByIdx(idx) =>
-                            format!("{:X}:{}", u16::from(idx.idx), u8::from(idx.sub_idx)),
-                    },
-                    entry.data_type,
-                    fallback
-                );
-                fallback
-            }),
-            bit_len: entry.bit_length,
-            access: get_sdo_entry_access(entry.read_access, entry.write_access),
-            description: c_array_to_string(entry.description.as_ptr()),
-        })
+        ioctl!(self, ec::request::SLAVE_SDO_ENTRY, &mut entry; ErrorTarget::Slave(slave_pos))?;
+        let info = decode_sdo_entry(&entry);
+        if DataType::from_u16(entry.data_type).is_none() {
+            log::warn!(
+                "Slave {} / SDO {}: Unknown data type (type value: {:X}): use '{:?}' as fallback",
+                u16::from(slave_pos),
+                match addr {
+                    SdoEntryAddr::ByPos(pos, sub) => format!("{:?} {:?} ", pos, sub),
+                    SdoEntryAddr::ByIdx(idx) =>
+                        format!("{:X}:{}", u16::from(idx.idx), u8::from(idx.sub_idx)),
+                },
+                entry.data_type,
+                info.data_type
+            );
+        }
+        Ok(info)
     }
 
     pub fn sdo_download<T>(
@@ -366,7 +566,9 @@ impl Master {
             data: data_ptr,
             abort_code: 0,
         };
-        ioctl!(self, ec::ioctl::SLAVE_SDO_DOWNLOAD, &mut data).map(|_| ())
+        ioctl!(self, ec::request::SLAVE_SDO_DOWNLOAD, &mut data; ErrorTarget::Slave(position))
+            .map_err(|e| sdo_error(data.abort_code, e))
+            .map(|_| ())
     }
 
     pub fn sdo_upload<'t>(
@@ -406,8 +608,10 @@ impl Master {
             complete_access: if complete_access { 1 } else { 0 },
         };
 
-        ioctl!(self, ec::ioctl::SLAVE_SDO_UPLOAD, &mut data)?;
-        Ok(&mut target[..data.data_size as usize])
+        ioctl!(self, ec::request::SLAVE_SDO_UPLOAD, &mut data; ErrorTarget::Slave(position))
+            .map_err(|e| sdo_error(data.abort_code, e))?;
+        let size = (data.data_size as usize).min(target.len());
+        Ok(&mut target[..size])
     }
 
     pub fn get_pdo(
@@ -420,14 +624,8 @@ impl Master {
         pdo.slave_position = u16::from(slave_pos);
         pdo.sync_index = u8::from(sync_index) as u32;
         pdo.pdo_pos = u8::from(pdo_position) as u32;
-        ioctl!(self, ec::ioctl::SLAVE_SYNC_PDO, &mut pdo)?;
-        Ok(PdoInfo {
-            sm: SmIdx::from(pdo.sync_index as u8),
-            pos: PdoPos::from(pdo.pdo_pos as u8),
-            idx: Idx::from(pdo.index),
-            entry_count: pdo.entry_count,
-            name: c_array_to_string(pdo.name.as_ptr()),
-        })
+        ioctl!(self, ec::request::SLAVE_SYNC_PDO, &mut pdo; ErrorTarget::Slave(slave_pos))?;
+        Ok(decode_pdo(&pdo))
     }
 
     pub fn get_pdo_entry(
@@ -442,7 +640,7 @@ impl Master {
         entry.sync_index = u8::from(sync_index) as u32;
         entry.pdo_pos = u8::from(pdo_pos) as u32;
         entry.entry_pos = u8::from(entry_pos) as u32;
-        ioctl!(self, ec::ioctl::SLAVE_SYNC_PDO_ENTRY, &mut entry)?;
+        ioctl!(self, ec::request::SLAVE_SYNC_PDO_ENTRY, &mut entry; ErrorTarget::Slave(slave_pos))?;
         Ok(PdoEntryInfo {
             pos: PdoEntryPos::from(entry.pdo_pos as u8),
             entry_idx: PdoEntryIdx {
@@ -450,7 +648,7 @@ impl Master {
                 sub_idx: SubIdx::from(entry.subindex),
             },
             bit_len: entry.bit_length,
-            name: c_array_to_string(entry.name.as_ptr()),
+            name: c_array_to_string(&entry.name),
         })
     }
 
@@ -458,22 +656,15 @@ impl Master {
         let mut sync = ec::ec_ioctl_slave_sync_t::default();
         sync.slave_position = u16::from(slave_pos);
         sync.sync_index = u8::from(sm) as u32;
-        ioctl!(self, ec::ioctl::SLAVE_SYNC, &mut sync)?;
-        Ok(SmInfo {
-            idx: SmIdx::from(sync.sync_index as u8),
-            start_addr: sync.physical_start_address,
-            default_size: sync.default_size,
-            control_register: sync.control_register,
-            enable: sync.enable == 1,
-            pdo_count: sync.pdo_count,
-        })
+        ioctl!(self, ec::request::SLAVE_SYNC, &mut sync; ErrorTarget::Slave(slave_pos))?;
+        Ok(decode_sync(&sync))
     }
 
     pub fn request_state(&mut self, slave_pos: SlavePos, state: AlState) -> Result<()> {
         let mut data = ec::ec_ioctl_slave_state_t::default();
         data.slave_position = u16::from(slave_pos);
         data.al_state = state as u8;
-        ioctl!(self, ec::ioctl::SLAVE_STATE, &mut data)?;
+        ioctl!(self, ec::request::SLAVE_STATE, &mut data; ErrorTarget::Slave(slave_pos))?;
         Ok(())
     }
 
@@ -481,162 +672,1023 @@ impl Master {
     pub fn dict_upload(&mut self, slave_pos: SlavePos) -> Result<()> {
         let mut data = ec::ec_ioctl_slave_dict_upload_t::default();
         data.slave_position = u16::from(slave_pos);
-        ioctl!(self, ec::ioctl::SLAVE_DICT_UPLOAD, &mut data)?;
+        ioctl!(self, ec::request::SLAVE_DICT_UPLOAD, &mut data; ErrorTarget::Slave(slave_pos))?;
         Ok(())
     }
 
-    // XXX missing: write_idn, read_idn,
-    // application_time, sync_reference_clock, sync_slave_clocks,
-    // reference_clock_time, sync_monitor_queue, sync_monitor_process
-}
-
-fn c_array_to_string(data: *const i8) -> String {
-    unsafe { CStr::from_ptr(data).to_string_lossy().into_owned() }
-}
-
-#[test]
-fn test_c_array_to_string() {
-    let arr: [i8; 64] = [0_i8; 64];
-    assert_eq!(c_array_to_string(arr.as_ptr()), "");
-
-    let mut arr: [i8; 64] = [0_i8; 64];
-    [80_i8, 114, 111, 100, 117, 99, 116, 32, 99, 111, 100, 101]
-        .iter()
-        .enumerate()
-        .for_each(|(idx, v)| {
-            arr[idx] = *v;
-        });
-    assert_eq!(c_array_to_string(arr.as_ptr()), "Product code");
-}
-
-pub struct SlaveConfig<'m> {
-    master: &'m Master,
-    idx: SlaveConfigIdx,
-}
-
-impl<'m> SlaveConfig<'m> {
-    pub const fn index(&self) -> SlaveConfigIdx {
-        self.idx
-    }
-
-    pub fn state(&self) -> Result<SlaveConfigState> {
-        let mut state = ec::ec_slave_config_state_t::default();
-        let mut data = ec::ec_ioctl_sc_state_t {
-            config_index: self.idx,
-            state: &mut state,
-        };
-        ioctl!(self.master, ec::ioctl::SC_STATE, &mut data)?;
-        let al_state_u8 = state.al_state() as u8;
-        Ok(SlaveConfigState {
-            online: state.online() != 0,
-            operational: state.operational() != 0,
-            al_state: AlState::try_from(al_state_u8)
-                .map_err(|_| Error::InvalidAlState(al_state_u8))?,
-        })
-    }
-
-    /// Configure PDOs of a specifc Sync Manager
-    pub fn config_sm_pdos(&mut self, sm_cfg: SmCfg, pdo_cfgs: &[PdoCfg]) -> Result<()> {
-        self.config_sync_manager(&sm_cfg)?;
-        self.clear_pdo_assignments(sm_cfg.idx)?;
-        for pdo_cfg in &*pdo_cfgs {
-            self.add_pdo_assignment(sm_cfg.idx, pdo_cfg.idx)?;
-            if !pdo_cfg.entries.is_empty() {
-                self.clear_pdo_mapping(pdo_cfg.idx)?;
-                for entry in &pdo_cfg.entries {
-                    self.add_pdo_mapping(pdo_cfg.idx, entry)?;
+    /// Read a file from a slave via FoE.
+    ///
+    /// The receive buffer is enlarged automatically if the file does not
+    /// fit in it. A `password` of 0 means that no password is sent.
+    pub fn foe_read(&self, slave_pos: SlavePos, file_name: &str, password: u32) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; FOE_INITIAL_BUFFER_SIZE];
+        loop {
+            let mut data = ec::ec_ioctl_slave_foe_t {
+                slave_position: u16::from(slave_pos),
+                buffer_size: buffer.len() as u64,
+                buffer: buffer.as_mut_ptr(),
+                ..Default::default()
+            };
+            set_foe_params(&mut data, file_name, password)?;
+            match ioctl!(self, ec::request::SLAVE_FOE_READ, &mut data; ErrorTarget::Slave(slave_pos))
+            {
+                Ok(_) => {
+                    buffer.truncate(data.data_size as usize);
+                    return Ok(buffer);
+                }
+                Err(ref e)
+                    if e.raw_os_error() == Some(libc::EOVERFLOW)
+                        && buffer.len() < FOE_MAX_BUFFER_SIZE =>
+                {
+                    let size = buffer.len() * 2;
+                    log::debug!("FoE buffer too small, retrying with {} bytes", size);
+                    buffer.resize(size, 0);
                 }
+                Err(e) => return Err(foe_error(&data).unwrap_or(e)),
             }
         }
-        Ok(())
     }
 
-    pub fn config_watchdog(&mut self, divider: u16, intervals: u16) -> Result<()> {
-        let mut data = ec::ec_ioctl_config_t::default();
-        data.config_index = self.idx;
-        data.watchdog_divider = divider;
-        data.watchdog_intervals = intervals;
-        ioctl!(self.master, ec::ioctl::SC_WATCHDOG, &data).map(|_| ())
+    /// Write a file to a slave via FoE.
+    ///
+    /// A `password` of 0 means that no password is sent.
+    pub fn foe_write(
+        &mut self,
+        slave_pos: SlavePos,
+        file_name: &str,
+        password: u32,
+        content: &[u8],
+    ) -> Result<()> {
+        let mut data = ec::ec_ioctl_slave_foe_t {
+            slave_position: u16::from(slave_pos),
+            buffer_size: content.len() as u64,
+            buffer: content.as_ptr() as *mut u8,
+            ..Default::default()
+        };
+        set_foe_params(&mut data, file_name, password)?;
+        // The kernel writes back the result, even though this is a write ioctl.
+        ioctl!(self, ec::request::SLAVE_FOE_WRITE, &mut data; ErrorTarget::Slave(slave_pos))
+            .map(|_| ())
+            .map_err(|e| foe_error(&data).unwrap_or(e))
     }
 
-    #[cfg(feature = "sncn")]
-    pub fn config_overlapping_pdos(&mut self, allow: bool) -> Result<()> {
-        let mut data = ec::ec_ioctl_config_t::default();
-        data.config_index = self.idx;
-        data.allow_overlapping_pdos = allow as u8;
-        ioctl!(self.master, ec::ioctl::SC_OVERLAPPING_IO, &data).map(|_| ())
+    /// Read words from the SII contents of a slave.
+    ///
+    /// The data is taken from the master's copy, which is read from the
+    /// slave during the bus scan. Use `SlaveInfo::sii_nwords` and
+    /// `sii::Sii::parse` to decode the complete contents.
+    pub fn sii_read(&self, slave_pos: SlavePos, offset: u16, nwords: usize) -> Result<Vec<u16>> {
+        let mut words = vec![0; nwords];
+        let mut data = ec::ec_ioctl_slave_sii_t {
+            slave_position: u16::from(slave_pos),
+            offset,
+            nwords: nwords as u32,
+            words: words.as_mut_ptr(),
+        };
+        ioctl!(self, ec::request::SLAVE_SII_READ, &mut data; ErrorTarget::Slave(slave_pos))?;
+        Ok(words)
     }
 
-    pub fn config_sync_manager(&mut self, cfg: &SmCfg) -> Result<()> {
-        log::debug!("Configure Sync Manager: {:?}", cfg);
-        if u8::from(cfg.idx) >= ec::EC_MAX_SYNC_MANAGERS as u8 {
-            return Err(Error::SmIdxTooLarge);
-        }
-        let mut data = ec::ec_ioctl_config_t::default();
-        data.config_index = self.idx;
-        let ix = u8::from(cfg.idx) as usize;
-        data.syncs[ix].dir = cfg.direction as u32;
-        data.syncs[ix].watchdog_mode = cfg.watchdog_mode as u32;
-        data.syncs[ix].config_this = 1;
-        ioctl!(self.master, ec::ioctl::SC_SYNC, &data).map(|_| ())
+    /// Write words to the SII EEPROM of a slave.
+    pub fn sii_write(&mut self, slave_pos: SlavePos, offset: u16, words: &[u16]) -> Result<()> {
+        let data = ec::ec_ioctl_slave_sii_t {
+            slave_position: u16::from(slave_pos),
+            offset,
+            nwords: words.len() as u32,
+            words: words.as_ptr() as *mut u16,
+        };
+        ioctl!(self, ec::request::SLAVE_SII_WRITE, &data; ErrorTarget::Slave(slave_pos)).map(|_| ())
     }
 
-    pub fn clear_pdo_assignments(&mut self, sync_idx: SmIdx) -> Result<()> {
-        let mut data = ec::ec_ioctl_config_pdo_t::default();
-        data.config_index = self.idx;
-        data.sync_index = u8::from(sync_idx);
-        ioctl!(self.master, ec::ioctl::SC_CLEAR_PDOS, &data).map(|_| ())
+    /// Read ESC registers of a slave, starting at `address`.
+    ///
+    /// Emergency requests are processed even if the master is in
+    /// operation and the slave's state machine is busy.
+    pub fn reg_read(
+        &self,
+        slave_pos: SlavePos,
+        address: u16,
+        emergency: bool,
+        target: &mut [u8],
+    ) -> Result<()> {
+        let mut data = ec::ec_ioctl_slave_reg_t {
+            slave_position: u16::from(slave_pos),
+            emergency: emergency as u8,
+            address,
+            size: target.len() as u64,
+            data: target.as_mut_ptr(),
+        };
+        ioctl!(self, ec::request::SLAVE_REG_READ, &mut data; ErrorTarget::Slave(slave_pos))
+            .map(|_| ())
     }
 
-    pub fn add_pdo_assignment(&mut self, sync_idx: SmIdx, pdo_idx: PdoIdx) -> Result<()> {
-        let mut data = ec::ec_ioctl_config_pdo_t::default();
-        data.config_index = self.idx;
-        data.sync_index = u8::from(sync_idx);
-        data.index = u16::from(pdo_idx);
-        ioctl!(self.master, ec::ioctl::SC_ADD_PDO, &data).map(|_| ())
+    /// Write ESC registers of a slave, starting at `address`.
+    pub fn reg_write(
+        &mut self,
+        slave_pos: SlavePos,
+        address: u16,
+        emergency: bool,
+        value: &[u8],
+    ) -> Result<()> {
+        let data = ec::ec_ioctl_slave_reg_t {
+            slave_position: u16::from(slave_pos),
+            emergency: emergency as u8,
+            address,
+            size: value.len() as u64,
+            data: value.as_ptr() as *mut u8,
+        };
+        ioctl!(self, ec::request::SLAVE_REG_WRITE, &data; ErrorTarget::Slave(slave_pos)).map(|_| ())
     }
 
-    pub fn clear_pdo_mapping(&mut self, pdo_idx: PdoIdx) -> Result<()> {
-        let mut data = ec::ec_ioctl_config_pdo_t::default();
-        data.config_index = self.idx;
-        data.index = u16::from(pdo_idx);
-        ioctl!(self.master, ec::ioctl::SC_CLEAR_ENTRIES, &data).map(|_| ())
+    /// Read and decode a well-known ESC register of a slave.
+    pub fn read_register<R: Register>(&self, slave_pos: SlavePos) -> Result<R> {
+        let mut data = vec![0; R::SIZE];
+        self.reg_read(slave_pos, R::ADDRESS, false, &mut data)?;
+        Ok(R::decode(&data))
     }
 
-    pub fn add_pdo_mapping(&mut self, pdo_index: PdoIdx, entry: &PdoEntryInfo) -> Result<()> {
-        let data = ec::ec_ioctl_add_pdo_entry_t {
-            config_index: self.idx,
-            pdo_index: u16::from(pdo_index),
-            entry_index: u16::from(entry.entry_idx.idx),
-            entry_subindex: u8::from(entry.entry_idx.sub_idx),
-            entry_bit_length: entry.bit_len,
-        };
-        ioctl!(self.master, ec::ioctl::SC_ADD_ENTRY, &data).map(|_| ())
+    /// Get information about all EoE handlers of the master.
+    pub fn eoe_handlers(&self) -> Result<Vec<EoeHandlerInfo>> {
+        let mut master = ec::ec_ioctl_master_t::default();
+        ioctl!(self, ec::request::MASTER, &mut master)?;
+        (0..master.eoe_handler_count as u16)
+            .map(|eoe_index| {
+                let mut data = ec::ec_ioctl_eoe_handler_t {
+                    eoe_index,
+                    ..Default::default()
+                };
+                ioctl!(self, ec::request::EOE_HANDLER, &mut data)?;
+                Ok(EoeHandlerInfo {
+                    name: c_array_to_string(&data.name),
+                    slave_pos: SlavePos::from(data.slave_position),
+                    open: data.open != 0,
+                    rx_bytes: data.rx_bytes,
+                    rx_rate: data.rx_rate,
+                    tx_bytes: data.tx_bytes,
+                    tx_rate: data.tx_rate,
+                    tx_queued_frames: data.tx_queued_frames,
+                    tx_queue_size: data.tx_queue_size,
+                })
+            })
+            .collect()
     }
 
-    pub fn register_pdo_entry(&mut self, index: PdoEntryIdx, domain: DomainIdx) -> Result<Offset> {
-        let mut data = ec::ec_ioctl_reg_pdo_entry_t {
-            config_index: self.idx,
-            entry_index: u16::from(index.idx),
-            entry_subindex: u8::from(index.sub_idx),
-            domain_index: u32::try_from(domain)
-                .map_err(|_| Error::DomainIdx(usize::from(domain)))?,
-            bit_position: 0,
+    /// Set the IP parameters of a slave via EoE.
+    ///
+    /// Only the parameters that are `Some` are sent to the slave.
+    #[cfg(feature = "sncn")]
+    pub fn set_eoe_ip(&mut self, slave_pos: SlavePos, params: &EoeIpParams) -> Result<()> {
+        // The addresses are sent as they are stored in memory,
+        // i.e. in network byte order.
+        let ip = |addr: Option<std::net::Ipv4Addr>| addr.map(|a| u32::from_ne_bytes(a.octets()));
+        let mut data = ec::ec_ioctl_slave_eoe_ip_t {
+            slave_position: u16::from(slave_pos),
+            ..Default::default()
         };
-        let byte = ioctl!(self.master, ec::ioctl::SC_REG_PDO_ENTRY, &mut data)?;
-        Ok(Offset {
-            byte: byte as usize,
-            bit: data.bit_position,
+        if let Some(mac) = params.mac_address {
+            data.mac_address_included = 1;
+            data.mac_address = mac;
+        }
+        if let Some(addr) = ip(params.ip_address) {
+            data.ip_address_included = 1;
+            data.ip_address = addr;
+        }
+        if let Some(addr) = ip(params.subnet_mask) {
+            data.subnet_mask_included = 1;
+            data.subnet_mask = addr;
+        }
+        if let Some(addr) = ip(params.gateway) {
+            data.gateway_included = 1;
+            data.gateway = addr;
+        }
+        if let Some(addr) = ip(params.dns) {
+            data.dns_included = 1;
+            data.dns = addr;
+        }
+        if let Some(name) = &params.hostname {
+            // The name has to be zero-terminated.
+            if name.len() >= data.name.len() {
+                return Err(Error::EoeHostnameTooLong);
+            }
+            for (c, b) in data.name.iter_mut().zip(name.bytes()) {
+                *c = b as c_char;
+            }
+            data.name_included = 1;
+        }
+        // The kernel writes back the result, even though this is a write ioctl.
+        let res =
+            ioctl!(self, ec::request::SLAVE_EOE_IP_PARAM, &mut data; ErrorTarget::Slave(slave_pos));
+        if data.result != 0 {
+            return Err(Error::EoeResult(data.result));
+        }
+        res.map(|_| ())
+    }
+
+    /// Read the value of an IDN from a drive via SoE.
+    pub fn soe_read<'t>(
+        &self,
+        slave_pos: SlavePos,
+        drive_no: u8,
+        idn: Idn,
+        target: &'t mut [u8],
+    ) -> Result<&'t mut [u8]> {
+        let mut data = ec::ec_ioctl_slave_soe_read_t {
+            slave_position: u16::from(slave_pos),
+            drive_no,
+            idn: u16::from(idn),
+            mem_size: target.len() as u64,
+            data: target.as_mut_ptr(),
+            ..Default::default()
+        };
+        ioctl!(self, ec::request::SLAVE_SOE_READ, &mut data; ErrorTarget::Slave(slave_pos))
+            .map_err(|e| soe_error(data.error_code, e))?;
+        let size = (data.data_size as usize).min(target.len());
+        Ok(&mut target[..size])
+    }
+
+    /// Write the value of an IDN to a drive via SoE.
+    pub fn soe_write(
+        &mut self,
+        slave_pos: SlavePos,
+        drive_no: u8,
+        idn: Idn,
+        value: &[u8],
+    ) -> Result<()> {
+        let mut data = ec::ec_ioctl_slave_soe_write_t {
+            slave_position: u16::from(slave_pos),
+            drive_no,
+            idn: u16::from(idn),
+            data_size: value.len() as u64,
+            data: value.as_ptr() as *mut u8,
+            ..Default::default()
+        };
+        ioctl!(self, ec::request::SLAVE_SOE_WRITE, &mut data; ErrorTarget::Slave(slave_pos))
+            .map(|_| ())
+            .map_err(|e| soe_error(data.error_code, e))
+    }
+}
+
+impl Master<Configuring> {
+    pub fn create_domain(&self) -> Result<DomainIdx> {
+        Ok((ioctl!(self, ec::request::CREATE_DOMAIN)? as usize).into())
+    }
+
+    pub fn set_send_interval(&mut self, interval_us: usize) -> Result<()> {
+        ioctl!(self, ec::request::SET_SEND_INTERVAL, &interval_us).map(|_| ())
+    }
+
+    pub fn configure_slave(&mut self, addr: SlaveAddr, expected: SlaveId) -> Result<SlaveConfig> {
+        log::debug!("Configure slave {:?}", addr);
+        let mut data = ec::ec_ioctl_config_t::default();
+        let (alias, pos) = addr.as_pair();
+        data.alias = alias;
+        data.position = pos;
+        data.vendor_id = expected.vendor_id;
+        data.product_code = expected.product_code;
+        ioctl!(self, ec::request::CREATE_SLAVE_CONFIG, &mut data)?;
+        Ok(SlaveConfig {
+            idx: data.config_index,
+            origin: self.origin(),
+        })
+    }
+
+    /// Activate the master with the configuration done so far.
+    ///
+    /// If the process data cannot be mapped, the master is deactivated
+    /// again, which discards the configuration as `deactivate` does.
+    pub fn activate(mut self) -> std::result::Result<Master<Active>, PhaseError<Configuring>> {
+        log::debug!("Activate EtherCAT Master");
+        let mut data = ec::ec_ioctl_master_activate_t::default();
+        if let Err(error) = ioctl!(self, ec::request::ACTIVATE, &mut data) {
+            return Err(PhaseError {
+                master: Box::new(self),
+                error,
+            });
+        }
+
+        let size = data.process_data_size as usize;
+        let mut map = match self.backend.map_process_data(size) {
+            Ok(map) => map,
+            Err(error) => {
+                if let Err(e) = ioctl!(self, ec::request::DEACTIVATE) {
+                    log::warn!("Failed to deactivate the master again: {}", e);
+                }
+                self.domains.clear();
+                self.generation += 1;
+                return Err(PhaseError {
+                    master: Box::new(self),
+                    error: error.into(),
+                });
+            }
+        };
+        if let Some(first) = map.as_mut_slice().first_mut() {
+            *first = 0;
+        }
+        let mut master = self.into_phase();
+        master.map = Some(map);
+        Ok(master)
+    }
+}
+
+impl<P: Reserved> Master<P> {
+    /// Set the application time in nanoseconds since 2000-01-01 00:00.
+    ///
+    /// This has to be called cyclically before `send` if distributed clocks
+    /// are in use, since it is the time base for the slaves' sync signals.
+    pub fn application_time(&mut self, app_time: u64) -> Result<()> {
+        ioctl!(self, ec::request::APP_TIME, &app_time).map(|_| ())
+    }
+}
+
+impl Master<Active> {
+    pub const fn domain(&self, idx: DomainIdx) -> Domain {
+        Domain::new(idx, self)
+    }
+
+    pub fn domain_data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
+        let p = self
+            .domain_data_placement(idx)
+            .map_err(|_| Error::NoDomain)?;
+        let data = self
+            .map
+            .as_mut()
+            .expect("the process data of an active master is mapped");
+        Ok(&mut data.as_mut_slice()[p.offset..p.offset + p.size])
+    }
+
+    fn domain_data_placement(&mut self, idx: DomainIdx) -> Result<DomainDataPlacement> {
+        Ok(match self.domains.get(&idx) {
+            None => {
+                let d_idx =
+                    c_ulong::try_from(idx).map_err(|_| Error::DomainIdx(usize::from(idx)))?;
+                let offset = ioctl!(
+                    self,
+                    ec::request::DOMAIN_OFFSET,
+                    d_idx;
+                    ErrorTarget::Domain(idx)
+                )? as usize;
+                let size = ioctl!(self, ec::request::DOMAIN_SIZE, d_idx; ErrorTarget::Domain(idx))?
+                    as usize;
+                let meta_data = DomainDataPlacement { offset, size };
+                self.domains.insert(idx, meta_data);
+                meta_data
+            }
+            Some(d) => *d,
+        })
+    }
+
+    /// Deactivate the master, which discards all domains and slave
+    /// configurations, so that it can be configured again.
+    pub fn deactivate(self) -> std::result::Result<Master<Configuring>, PhaseError<Active>> {
+        log::debug!("Deactivate EtherCAT Master");
+        if let Err(error) = ioctl!(self, ec::request::DEACTIVATE) {
+            return Err(PhaseError {
+                master: Box::new(self),
+                error,
+            });
+        }
+        let mut master = self.into_phase();
+        master.domains.clear();
+        master.map = None;
+        master.generation += 1;
+        Ok(master)
+    }
+
+    pub fn send(&mut self) -> Result<usize> {
+        let mut sent = 0;
+        ioctl!(self, ec::request::SEND, &mut sent as *mut _ as c_ulong)?;
+        Ok(sent)
+    }
+
+    pub fn receive(&mut self) -> Result<()> {
+        ioctl!(self, ec::request::RECEIVE).map(|_| ())
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        ioctl!(self, ec::request::RESET).map(|_| ())
+    }
+
+    /// Queue a datagram that synchronizes the DC reference clock to the
+    /// application time set by `application_time`.
+    pub fn sync_reference_clock(&mut self) -> Result<()> {
+        ioctl!(self, ec::request::SYNC_REF).map(|_| ())
+    }
+
+    /// Queue a datagram that synchronizes the DC reference clock to the
+    /// given time (in nanoseconds since 2000-01-01 00:00).
+    pub fn sync_reference_clock_to(&mut self, sync_time: u64) -> Result<()> {
+        ioctl!(self, ec::request::SYNC_REF_TO, &sync_time).map(|_| ())
+    }
+
+    /// Queue a datagram that synchronizes all DC slave clocks to the
+    /// reference clock.
+    pub fn sync_slave_clocks(&mut self) -> Result<()> {
+        ioctl!(self, ec::request::SYNC_SLAVES).map(|_| ())
+    }
+
+    /// Get the lower 32 bit of the reference clock system time
+    /// (in nanoseconds) as received with the last `sync_slave_clocks`.
+    pub fn reference_clock_time(&self) -> Result<u32> {
+        let mut time = 0;
+        ioctl!(self, ec::request::REF_CLOCK_TIME, &mut time)?;
+        Ok(time)
+    }
+
+    /// Queue the DC synchrony monitoring datagram.
+    pub fn sync_monitor_queue(&mut self) -> Result<()> {
+        ioctl!(self, ec::request::SYNC_MON_QUEUE).map(|_| ())
+    }
+
+    /// Process the DC synchrony monitoring datagram queued with
+    /// `sync_monitor_queue`.
+    ///
+    /// Returns an upper estimate of the maximum time difference between
+    /// the slave clocks in nanoseconds.
+    pub fn sync_monitor_process(&mut self) -> Result<u32> {
+        let mut time_diff = 0;
+        ioctl!(self, ec::request::SYNC_MON_PROCESS, &mut time_diff)?;
+        Ok(time_diff)
+    }
+}
+
+const FOE_INITIAL_BUFFER_SIZE: usize = 0x1_0000;
+const FOE_MAX_BUFFER_SIZE: usize = 0x100_0000;
+
+fn set_foe_params(
+    data: &mut ec::ec_ioctl_slave_foe_t,
+    file_name: &str,
+    #[allow(unused_variables)] password: u32,
+) -> Result<()> {
+    // The name has to be zero-terminated.
+    if file_name.len() >= data.file_name.len() {
+        return Err(Error::Foe(FoeError::FileNameTooLong));
+    }
+    for (c, b) in data.file_name.iter_mut().zip(file_name.bytes()) {
+        *c = b as c_char;
+    }
+    #[cfg(feature = "sncn")]
+    {
+        data.password = password;
+    }
+    #[cfg(not(feature = "sncn"))]
+    {
+        if password != 0 {
+            return Err(Error::Foe(FoeError::PasswordNotSupported));
+        }
+    }
+    Ok(())
+}
+
+fn foe_error(data: &ec::ec_ioctl_slave_foe_t) -> Option<Error> {
+    FoeError::from_result(data.result, data.error_code).map(Error::Foe)
+}
+
+fn sdo_error(abort_code: u32, err: Error) -> Error {
+    if abort_code == 0 {
+        err
+    } else {
+        Error::SdoAbort(SdoAbortCode::from(abort_code))
+    }
+}
+
+fn soe_error(error_code: u16, err: Error) -> Error {
+    if error_code == 0 {
+        err
+    } else {
+        Error::Soe(SoeError::from(error_code))
+    }
+}
+
+fn decode_master_info(data: &ec::ec_ioctl_master_t) -> Result<MasterInfo> {
+    let ec::ec_ioctl_master_t {
+        slave_count,
+        config_count,
+        domain_count,
+        eoe_handler_count,
+        phase,
+        active,
+        devices,
+        num_devices,
+        scan_busy,
+        app_time,
+        dc_ref_time,
+        ref_clock,
+        ..
+    } = *data;
+    let first_device = devices.get(0).ok_or_else(|| Error::NoDevices)?;
+    let link_up = first_device.link_state != 0;
+    let scan_busy = scan_busy != 0;
+    let ref_clock = if ref_clock == 0xFFFF {
+        None
+    } else {
+        Some(SlavePos::from(ref_clock))
+    };
+    let devices = devices
+        .iter()
+        .take(num_devices as usize)
+        .map(|dev| MasterDeviceInfo {
+            mac_address: dev.address,
+            attached: dev.attached != 0,
+            link_up: dev.link_state != 0,
+            tx_errors: dev.tx_errors,
+            stats: FrameStats {
+                tx_count: dev.tx_count,
+                rx_count: dev.rx_count,
+                tx_bytes: dev.tx_bytes,
+                rx_bytes: dev.rx_bytes,
+                tx_frame_rates: Rates::from_frame_rates(dev.tx_frame_rates),
+                rx_frame_rates: Rates::from_frame_rates(dev.rx_frame_rates),
+                tx_byte_rates: Rates::from_byte_rates(dev.tx_byte_rates),
+                rx_byte_rates: Rates::from_byte_rates(dev.rx_byte_rates),
+            },
+        })
+        .collect();
+    Ok(MasterInfo {
+        slave_count,
+        link_up,
+        scan_busy,
+        app_time,
+        ref_clock,
+        phase: MasterPhase::try_from(phase).map_err(|_| Error::InvalidMasterPhase(phase))?,
+        active: active != 0,
+        config_count,
+        domain_count,
+        eoe_handler_count,
+        devices,
+        stats: FrameStats {
+            tx_count: data.tx_count,
+            rx_count: data.rx_count,
+            tx_bytes: data.tx_bytes,
+            rx_bytes: data.rx_bytes,
+            tx_frame_rates: Rates::from_frame_rates(data.tx_frame_rates),
+            rx_frame_rates: Rates::from_frame_rates(data.rx_frame_rates),
+            tx_byte_rates: Rates::from_byte_rates(data.tx_byte_rates),
+            rx_byte_rates: Rates::from_byte_rates(data.rx_byte_rates),
+        },
+        loss_rates: Rates::from_frame_rates(data.loss_rates),
+        dc_ref_time,
+    })
+}
+
+fn decode_slave_info(data: &ec::ec_ioctl_slave_t) -> Result<SlaveInfo> {
+    let mut ports = [SlavePortInfo::default(); ec::EC_MAX_PORTS as usize];
+    for (i, port) in ports.iter_mut().enumerate().take(ec::EC_MAX_PORTS as usize) {
+        let desc = data.ports[i].desc;
+        port.desc = SlavePortType::try_from(desc).map_err(|_| Error::InvalidPortType(desc))?;
+        port.link = SlavePortLink {
+            link_up: data.ports[i].link.link_up != 0,
+            loop_closed: data.ports[i].link.loop_closed != 0,
+            signal_detected: data.ports[i].link.signal_detected != 0,
+        };
+        port.receive_time = data.ports[i].receive_time;
+        port.next_slave = data.ports[i].next_slave;
+        port.delay_to_next_dc = data.ports[i].delay_to_next_dc;
+    }
+    let mailbox = |offset, size| SiiMailbox { offset, size };
+    let coe = &data.coe_details;
+    let mut coe_details = CoeDetails::empty();
+    coe_details.set(CoeDetails::SDO, coe.enable_sdo() != 0);
+    coe_details.set(CoeDetails::SDO_INFO, coe.enable_sdo_info() != 0);
+    coe_details.set(CoeDetails::PDO_ASSIGN, coe.enable_pdo_assign() != 0);
+    coe_details.set(
+        CoeDetails::PDO_CONFIGURATION,
+        coe.enable_pdo_configuration() != 0,
+    );
+    coe_details.set(
+        CoeDetails::UPLOAD_AT_STARTUP,
+        coe.enable_upload_at_startup() != 0,
+    );
+    coe_details.set(
+        CoeDetails::SDO_COMPLETE_ACCESS,
+        coe.enable_sdo_complete_access() != 0,
+    );
+    let mut general_flags = GeneralFlags::empty();
+    general_flags.set(
+        GeneralFlags::ENABLE_SAFEOP,
+        data.general_flags.enable_safeop() != 0,
+    );
+    general_flags.set(
+        GeneralFlags::ENABLE_NOT_LRW,
+        data.general_flags.enable_not_lrw() != 0,
+    );
+    let has_general = data.has_general_category != 0;
+    Ok(SlaveInfo {
+        name: c_array_to_string(&data.name),
+        ring_pos: data.position,
+        id: SlaveId {
+            vendor_id: data.vendor_id,
+            product_code: data.product_code,
+        },
+        rev: SlaveRev {
+            revision_number: data.revision_number,
+            serial_number: data.serial_number,
+        },
+        alias: data.alias,
+        current_on_ebus: data.current_on_ebus,
+        al_state: AlState::try_from(data.al_state)
+            .map_err(|_| Error::InvalidAlState(data.al_state))?,
+        error_flag: data.error_flag,
+        sync_count: data.sync_count,
+        sdo_count: data.sdo_count,
+        boot_rx_mailbox: mailbox(data.boot_rx_mailbox_offset, data.boot_rx_mailbox_size),
+        boot_tx_mailbox: mailbox(data.boot_tx_mailbox_offset, data.boot_tx_mailbox_size),
+        std_rx_mailbox: mailbox(data.std_rx_mailbox_offset, data.std_rx_mailbox_size),
+        std_tx_mailbox: mailbox(data.std_tx_mailbox_offset, data.std_tx_mailbox_size),
+        mailbox_protocols: MailboxProtocols::from_bits_truncate(data.mailbox_protocols),
+        coe_details: if has_general { Some(coe_details) } else { None },
+        general_flags: if has_general {
+            Some(general_flags)
+        } else {
+            None
+        },
+        dc_supported: data.dc_supported != 0,
+        dc_range: DcRange::try_from(data.dc_range)
+            .map_err(|_| Error::InvalidDcRange(data.dc_range))?,
+        has_dc_system_time: data.has_dc_system_time != 0,
+        transmission_delay: data.transmission_delay,
+        sii_nwords: data.sii_nwords,
+        group: c_array_to_string(&data.group),
+        image: c_array_to_string(&data.image),
+        order: c_array_to_string(&data.order),
+        ports,
+    })
+}
+
+fn decode_config_info(data: &ec::ec_ioctl_config_t) -> Result<ConfigInfo> {
+    let id = SlaveId {
+        vendor_id: data.vendor_id,
+        product_code: data.product_code,
+    };
+    let slave_position = if data.slave_position == -1 {
+        None
+    } else {
+        Some(SlavePos::from(data.slave_position as u16))
+    };
+    let syncs = data
+        .syncs
+        .iter()
+        .enumerate()
+        .filter(|(_, sync)| sync.dir != ec::EC_DIR_INVALID || sync.pdo_count > 0)
+        .map(|(i, sync)| {
+            Ok(SmConfigInfo {
+                idx: SmIdx::from(i as u8),
+                direction: SyncDirection::try_from(sync.dir)
+                    .map_err(|_| Error::InvalidSyncDirection(sync.dir))?,
+                watchdog_mode: WatchdogMode::try_from(sync.watchdog_mode)
+                    .map_err(|_| Error::InvalidWatchdogMode(sync.watchdog_mode))?,
+                pdo_count: sync.pdo_count,
+            })
+        })
+        .collect::<Result<_>>()?;
+    let dc_sync = |i: usize| DcSyncSignal {
+        cycle_time: data.dc_sync[i].cycle_time,
+        shift_time: data.dc_sync[i].shift_time,
+    };
+    Ok(ConfigInfo {
+        alias: data.alias,
+        position: data.position,
+        id,
+        slave_position,
+        sdo_count: data.sdo_count,
+        idn_count: data.idn_count,
+        syncs,
+        watchdog_divider: data.watchdog_divider,
+        watchdog_intervals: data.watchdog_intervals,
+        dc_assign_activate: data.dc_assign_activate,
+        dc_sync: [dc_sync(0), dc_sync(1)],
+    })
+}
+
+fn decode_config_idn(idn: &ec::ec_ioctl_config_idn_t) -> Result<ConfigIdn> {
+    let al_state = u8::try_from(idn.state)
+        .ok()
+        .and_then(|state| AlState::try_from(state).ok())
+        .ok_or(Error::InvalidAlState(idn.state as u8))?;
+    let size = (idn.size as usize).min(idn.data.len());
+    Ok(ConfigIdn {
+        drive_no: idn.drive_no,
+        idn: Idn::from(idn.idn),
+        al_state,
+        data: idn.data[..size].to_vec(),
+    })
+}
+
+fn decode_sdo_info(sdo: &ec::ec_ioctl_slave_sdo_t) -> SdoInfo {
+    SdoInfo {
+        pos: SdoPos::from(sdo.sdo_position),
+        idx: Idx::from(sdo.sdo_index),
+        max_sub_idx: SubIdx::from(sdo.max_subindex),
+        #[cfg(feature = "sncn")]
+        object_code: Some(sdo.object_code),
+        #[cfg(not(feature = "sncn"))]
+        object_code: None,
+        name: c_array_to_string(&sdo.name),
+    }
+}
+
+/// Decode an SDO entry; unknown data types are reported as `DataType::Raw`.
+fn decode_sdo_entry(entry: &ec::ec_ioctl_slave_sdo_entry_t) -> SdoEntryInfo {
+    SdoEntryInfo {
+        data_type: DataType::from_u16(entry.data_type).unwrap_or(DataType::Raw),
+        bit_len: entry.bit_length,
+        access: get_sdo_entry_access(entry.read_access, entry.write_access),
+        description: c_array_to_string(&entry.description),
+    }
+}
+
+fn decode_pdo(pdo: &ec::ec_ioctl_slave_sync_pdo_t) -> PdoInfo {
+    PdoInfo {
+        sm: SmIdx::from(pdo.sync_index as u8),
+        pos: PdoPos::from(pdo.pdo_pos as u8),
+        idx: Idx::from(pdo.index),
+        entry_count: pdo.entry_count,
+        name: c_array_to_string(&pdo.name),
+    }
+}
+
+fn decode_sync(sync: &ec::ec_ioctl_slave_sync_t) -> SmInfo {
+    SmInfo {
+        idx: SmIdx::from(sync.sync_index as u8),
+        start_addr: sync.physical_start_address,
+        default_size: sync.default_size,
+        control_register: sync.control_register,
+        enable: sync.enable == 1,
+        pdo_count: sync.pdo_count,
+    }
+}
+
+fn decode_config_state(state: &ec::ec_slave_config_state_t) -> Result<SlaveConfigState> {
+    let al_state_u8 = state.al_state() as u8;
+    Ok(SlaveConfigState {
+        online: state.online() != 0,
+        operational: state.operational() != 0,
+        al_state: AlState::try_from(al_state_u8).map_err(|_| Error::InvalidAlState(al_state_u8))?,
+    })
+}
+
+fn decode_domain_state(state: &ec::ec_domain_state_t) -> Result<DomainState> {
+    Ok(DomainState {
+        working_counter: state.working_counter,
+        redundancy_active: state.redundancy_active != 0,
+        wc_state: WcState::try_from(state.wc_state)
+            .map_err(|_| Error::InvalidWcState(state.wc_state))?,
+    })
+}
+
+/// Convert a NUL-terminated C string buffer, stopping at the end of the
+/// buffer if the terminator is missing.
+fn c_array_to_string(data: &[c_char]) -> String {
+    let bytes: Vec<u8> = data
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[test]
+fn test_c_array_to_string() {
+    let arr: [i8; 64] = [0_i8; 64];
+    assert_eq!(c_array_to_string(&arr), "");
+
+    let mut arr: [i8; 64] = [0_i8; 64];
+    [80_i8, 114, 111, 100, 117, 99, 116, 32, 99, 111, 100, 101]
+        .iter()
+        .enumerate()
+        .for_each(|(idx, v)| {
+            arr[idx] = *v;
+        });
+    assert_eq!(c_array_to_string(&arr), "Product code");
+}
+
+#[test]
+fn test_decode_config_info_syncs() {
+    let mut data = ec::ec_ioctl_config_t::default();
+    data.syncs[1].dir = ec::EC_DIR_OUTPUT;
+    // PDOs assigned without configuring the direction.
+    data.syncs[2].pdo_count = 1;
+    let syncs = decode_config_info(&data).unwrap().syncs;
+    assert_eq!(syncs.len(), 2);
+    assert_eq!(syncs[0].idx, SmIdx::from(1));
+    assert_eq!(syncs[0].direction, SyncDirection::Output);
+    assert_eq!(syncs[1].idx, SmIdx::from(2));
+    assert_eq!(syncs[1].direction, SyncDirection::Invalid);
+    assert_eq!(syncs[1].pdo_count, 1);
+}
+
+/// Handle of a slave configuration created with `Master::configure_slave`.
+///
+/// The handle does not borrow the master, which is passed to its methods
+/// instead, so that several slave configurations can be set up at once.
+/// While the master is `Configuring`, the configuration can be changed;
+/// once it is `Active`, only the state and the emergency messages of the
+/// slave are available.
+///
+/// `Master::deactivate` discards all slave configurations, so that handles
+/// created before become stale. Using a stale handle, or one of another
+/// master, fails with `Error::StaleHandle`; this also applies to the
+/// requests and handlers created from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlaveConfig {
+    idx: SlaveConfigIdx,
+    origin: Origin,
+}
+
+impl SlaveConfig {
+    pub const fn index(&self) -> SlaveConfigIdx {
+        self.idx
+    }
+
+    pub fn state<P: Reserved>(&self, master: &Master<P>) -> Result<SlaveConfigState> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut state = ec::ec_slave_config_state_t::default();
+        let mut data = ec::ec_ioctl_sc_state_t {
+            config_index: self.idx,
+            state: &mut state,
+        };
+        ioctl!(master, ec::request::SC_STATE, &mut data; ErrorTarget::Config(self.idx))?;
+        decode_config_state(&state)
+    }
+
+    pub fn pop_emerg<P: Reserved>(&self, master: &Master<P>, target: &mut [u8]) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_sc_emerg_t::default();
+        data.config_index = self.idx;
+        data.target = target.as_mut_ptr();
+        ioctl!(master, ec::request::SC_EMERG_POP, &mut data; ErrorTarget::Config(self.idx))
+            .map(|_| ())
+    }
+
+    pub fn clear_emerg<P: Reserved>(&self, master: &Master<P>) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_sc_emerg_t::default();
+        data.config_index = self.idx;
+        ioctl!(master, ec::request::SC_EMERG_CLEAR, &data; ErrorTarget::Config(self.idx))
+            .map(|_| ())
+    }
+
+    pub fn emerg_overruns<P: Reserved>(&self, master: &Master<P>) -> Result<i32> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_sc_emerg_t::default();
+        data.config_index = self.idx;
+        ioctl!(
+            master,
+            ec::request::SC_EMERG_OVERRUNS,
+            &mut data;
+            ErrorTarget::Config(self.idx)
+        )?;
+        Ok(data.overruns)
+    }
+
+    /// Configure PDOs of a specifc Sync Manager
+    pub fn config_sm_pdos(
+        &self,
+        master: &Master<Configuring>,
+        sm_cfg: SmCfg,
+        pdo_cfgs: &[PdoCfg],
+    ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        self.config_sync_manager(master, &sm_cfg)?;
+        self.clear_pdo_assignments(master, sm_cfg.idx)?;
+        for pdo_cfg in &*pdo_cfgs {
+            self.add_pdo_assignment(master, sm_cfg.idx, pdo_cfg.idx)?;
+            if !pdo_cfg.entries.is_empty() {
+                self.clear_pdo_mapping(master, pdo_cfg.idx)?;
+                for entry in &pdo_cfg.entries {
+                    self.add_pdo_mapping(master, pdo_cfg.idx, entry)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn config_watchdog(
+        &self,
+        master: &Master<Configuring>,
+        divider: u16,
+        intervals: u16,
+    ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_config_t::default();
+        data.config_index = self.idx;
+        data.watchdog_divider = divider;
+        data.watchdog_intervals = intervals;
+        ioctl!(master, ec::request::SC_WATCHDOG, &data; ErrorTarget::Config(self.idx)).map(|_| ())
+    }
+
+    #[cfg(feature = "sncn")]
+    pub fn config_overlapping_pdos(&self, master: &Master<Configuring>, allow: bool) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_config_t::default();
+        data.config_index = self.idx;
+        data.allow_overlapping_pdos = allow as u8;
+        ioctl!(master, ec::request::SC_OVERLAPPING_IO, &data; ErrorTarget::Config(self.idx))
+            .map(|_| ())
+    }
+
+    pub fn config_sync_manager(&self, master: &Master<Configuring>, cfg: &SmCfg) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        log::debug!("Configure Sync Manager: {:?}", cfg);
+        if u8::from(cfg.idx) >= ec::EC_MAX_SYNC_MANAGERS as u8 {
+            return Err(Error::SmIdxTooLarge);
+        }
+        let mut data = ec::ec_ioctl_config_t::default();
+        data.config_index = self.idx;
+        let ix = u8::from(cfg.idx) as usize;
+        data.syncs[ix].dir = cfg.direction as u32;
+        data.syncs[ix].watchdog_mode = cfg.watchdog_mode as u32;
+        data.syncs[ix].config_this = 1;
+        ioctl!(master, ec::request::SC_SYNC, &data; ErrorTarget::Config(self.idx)).map(|_| ())
+    }
+
+    pub fn clear_pdo_assignments(
+        &self,
+        master: &Master<Configuring>,
+        sync_idx: SmIdx,
+    ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_config_pdo_t::default();
+        data.config_index = self.idx;
+        data.sync_index = u8::from(sync_idx);
+        ioctl!(master, ec::request::SC_CLEAR_PDOS, &data; ErrorTarget::Config(self.idx)).map(|_| ())
+    }
+
+    pub fn add_pdo_assignment(
+        &self,
+        master: &Master<Configuring>,
+        sync_idx: SmIdx,
+        pdo_idx: PdoIdx,
+    ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_config_pdo_t::default();
+        data.config_index = self.idx;
+        data.sync_index = u8::from(sync_idx);
+        data.index = u16::from(pdo_idx);
+        ioctl!(master, ec::request::SC_ADD_PDO, &data; ErrorTarget::Config(self.idx)).map(|_| ())
+    }
+
+    pub fn clear_pdo_mapping(&self, master: &Master<Configuring>, pdo_idx: PdoIdx) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_config_pdo_t::default();
+        data.config_index = self.idx;
+        data.index = u16::from(pdo_idx);
+        ioctl!(master, ec::request::SC_CLEAR_ENTRIES, &data; ErrorTarget::Config(self.idx))
+            .map(|_| ())
+    }
+
+    pub fn add_pdo_mapping(
+        &self,
+        master: &Master<Configuring>,
+        pdo_index: PdoIdx,
+        entry: &PdoEntryInfo,
+    ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        let data = ec::ec_ioctl_add_pdo_entry_t {
+            config_index: self.idx,
+            pdo_index: u16::from(pdo_index),
+            entry_index: u16::from(entry.entry_idx.idx),
+            entry_subindex: u8::from(entry.entry_idx.sub_idx),
+            entry_bit_length: entry.bit_len,
+        };
+        ioctl!(master, ec::request::SC_ADD_ENTRY, &data; ErrorTarget::Config(self.idx)).map(|_| ())
+    }
+
+    pub fn register_pdo_entry(
+        &self,
+        master: &Master<Configuring>,
+        index: PdoEntryIdx,
+        domain: DomainIdx,
+    ) -> Result<Offset> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_reg_pdo_entry_t {
+            config_index: self.idx,
+            entry_index: u16::from(index.idx),
+            entry_subindex: u8::from(index.sub_idx),
+            domain_index: u32::try_from(domain)
+                .map_err(|_| Error::DomainIdx(usize::from(domain)))?,
+            bit_position: 0,
+        };
+        let byte = ioctl!(
+            master,
+            ec::request::SC_REG_PDO_ENTRY,
+            &mut data;
+            ErrorTarget::Config(self.idx)
+        )?;
+        Ok(Offset {
+            byte: byte as usize,
+            bit: data.bit_position,
         })
     }
 
     pub fn register_pdo_entry_by_position(
-        &mut self,
+        &self,
+        master: &Master<Configuring>,
         sync_index: SmIdx,
         pdo_pos: u32,
         entry_pos: u32,
         domain: DomainIdx,
     ) -> Result<Offset> {
+        master.check_handle(self.origin, self.idx)?;
         let mut data = ec::ec_ioctl_reg_pdo_pos_t {
             config_index: self.idx,
             sync_index: u8::from(sync_index) as u32,
@@ -646,7 +1698,12 @@ impl<'m> SlaveConfig<'m> {
                 .map_err(|_| Error::DomainIdx(usize::from(domain)))?,
             bit_position: 0,
         };
-        let byte = ioctl!(self.master, ec::ioctl::SC_REG_PDO_POS, &mut data)?;
+        let byte = ioctl!(
+            master,
+            ec::request::SC_REG_PDO_POS,
+            &mut data;
+            ErrorTarget::Config(self.idx)
+        )?;
         Ok(Offset {
             byte: byte as usize,
             bit: data.bit_position,
@@ -654,13 +1711,15 @@ impl<'m> SlaveConfig<'m> {
     }
 
     pub fn config_dc(
-        &mut self,
+        &self,
+        master: &Master<Configuring>,
         assign_activate: u16,
         sync0_cycle_time: u32,
         sync0_shift_time: i32,
         sync1_cycle_time: u32,
         sync1_shift_time: i32,
     ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
         let mut data = ec::ec_ioctl_config_t::default();
         data.config_index = self.idx;
         data.dc_assign_activate = assign_activate;
@@ -668,13 +1727,35 @@ impl<'m> SlaveConfig<'m> {
         data.dc_sync[0].shift_time = sync0_shift_time;
         data.dc_sync[1].cycle_time = sync1_cycle_time;
         data.dc_sync[1].shift_time = sync1_shift_time;
-        ioctl!(self.master, ec::ioctl::SC_DC, &data).map(|_| ())
+        ioctl!(master, ec::request::SC_DC, &data; ErrorTarget::Config(self.idx)).map(|_| ())
+    }
+
+    /// Select this slave as the distributed clocks reference clock.
+    ///
+    /// If the configuration is already attached to a slave, the slave is
+    /// checked to support distributed clocks.
+    pub fn select_as_reference_clock(&self, master: &Master<Configuring>) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
+        if let Some(pos) = master.get_config_info(self.idx)?.slave_position {
+            if !master.get_slave_info(pos)?.dc_supported {
+                return Err(Error::NoDcSupport(pos));
+            }
+        }
+        log::debug!("Select slave config {} as reference clock", self.idx);
+        ioctl!(
+            master,
+            ec::request::SELECT_REF_CLOCK,
+            self.idx as c_ulong;
+            ErrorTarget::Config(self.idx)
+        )
+        .map(|_| ())
     }
 
-    pub fn add_sdo<T>(&mut self, index: SdoIdx, data: &T) -> Result<()>
+    pub fn add_sdo<T>(&self, master: &Master<Configuring>, index: SdoIdx, data: &T) -> Result<()>
     where
         T: SdoData + ?Sized,
     {
+        master.check_handle(self.origin, self.idx)?;
         let data = ec::ec_ioctl_sc_sdo_t {
             config_index: self.idx,
             index: u16::from(index.idx),
@@ -683,10 +1764,16 @@ impl<'m> SlaveConfig<'m> {
             size: data.data_size() as u64,
             complete_access: 0,
         };
-        ioctl!(self.master, ec::ioctl::SC_SDO, &data).map(|_| ())
+        ioctl!(master, ec::request::SC_SDO, &data; ErrorTarget::Config(self.idx)).map(|_| ())
     }
 
-    pub fn add_complete_sdo(&mut self, index: SdoIdx, data: &[u8]) -> Result<()> {
+    pub fn add_complete_sdo(
+        &self,
+        master: &Master<Configuring>,
+        index: SdoIdx,
+        data: &[u8],
+    ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
         let data = ec::ec_ioctl_sc_sdo_t {
             config_index: self.idx,
             index: u16::from(index.idx),
@@ -695,67 +1782,126 @@ impl<'m> SlaveConfig<'m> {
             size: data.len() as u64,
             complete_access: 1,
         };
-        ioctl!(self.master, ec::ioctl::SC_SDO, &data).map(|_| ())
+        ioctl!(master, ec::request::SC_SDO, &data; ErrorTarget::Config(self.idx)).map(|_| ())
     }
 
     pub fn config_idn(
-        &mut self,
+        &self,
+        master: &Master<Configuring>,
         drive_no: u8,
-        idn: u16,
+        idn: Idn,
         al_state: AlState,
         data: &[u8],
     ) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
         let data = ec::ec_ioctl_sc_idn_t {
             config_index: self.idx,
             drive_no,
-            idn,
+            idn: u16::from(idn),
             al_state: al_state as u32,
             data: data.as_ptr(),
             size: data.len() as u64,
         };
-        ioctl!(self.master, ec::ioctl::SC_IDN, &data).map(|_| ())
+        ioctl!(master, ec::request::SC_IDN, &data; ErrorTarget::Config(self.idx)).map(|_| ())
     }
 
-    pub fn set_emerg_size(&mut self, elements: u64) -> Result<()> {
+    pub fn set_emerg_size(&self, master: &Master<Configuring>, elements: u64) -> Result<()> {
+        master.check_handle(self.origin, self.idx)?;
         let mut data = ec::ec_ioctl_sc_emerg_t::default();
         data.config_index = self.idx;
         data.size = elements;
-        ioctl!(self.master, ec::ioctl::SC_EMERG_SIZE, &data).map(|_| ())
+        ioctl!(master, ec::request::SC_EMERG_SIZE, &data; ErrorTarget::Config(self.idx)).map(|_| ())
     }
 
-    pub fn pop_emerg(&mut self, target: &mut [u8]) -> Result<()> {
-        let mut data = ec::ec_ioctl_sc_emerg_t::default();
-        data.config_index = self.idx;
-        data.target = target.as_mut_ptr();
-        ioctl!(self.master, ec::ioctl::SC_EMERG_POP, &mut data).map(|_| ())
+    /// Create a request object for non-blocking SDO transfers.
+    ///
+    /// `size` is the initially reserved size of the data buffer.
+    pub fn create_sdo_request(
+        &self,
+        master: &Master<Configuring>,
+        sdo_idx: SdoIdx,
+        size: usize,
+    ) -> Result<SdoRequest> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_sdo_request_t {
+            config_index: self.idx,
+            sdo_index: u16::from(sdo_idx.idx),
+            sdo_subindex: u8::from(sdo_idx.sub_idx),
+            size: size as u64,
+            ..Default::default()
+        };
+        ioctl!(master, ec::request::SC_SDO_REQUEST, &mut data; ErrorTarget::Config(self.idx))?;
+        Ok(SdoRequest {
+            config_idx: self.idx,
+            origin: self.origin,
+            idx: data.request_index,
+            sdo_idx,
+            data: vec![0; size],
+            data_size: 0,
+        })
     }
 
-    pub fn clear_emerg(&mut self) -> Result<()> {
-        let mut data = ec::ec_ioctl_sc_emerg_t::default();
-        data.config_index = self.idx;
-        ioctl!(self.master, ec::ioctl::SC_EMERG_CLEAR, &data).map(|_| ())
+    /// Create a request object for non-blocking register transfers.
+    ///
+    /// `size` is the maximum size of a single transfer.
+    pub fn create_reg_request(
+        &self,
+        master: &Master<Configuring>,
+        size: usize,
+    ) -> Result<RegRequest> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_reg_request_t {
+            config_index: self.idx,
+            mem_size: size as u64,
+            ..Default::default()
+        };
+        ioctl!(master, ec::request::SC_REG_REQUEST, &mut data; ErrorTarget::Config(self.idx))?;
+        Ok(RegRequest {
+            config_idx: self.idx,
+            origin: self.origin,
+            idx: data.request_index,
+            data: vec![0; size],
+            transfer_size: 0,
+        })
     }
 
-    pub fn emerg_overruns(&mut self) -> Result<i32> {
-        let mut data = ec::ec_ioctl_sc_emerg_t::default();
-        data.config_index = self.idx;
-        ioctl!(self.master, ec::ioctl::SC_EMERG_OVERRUNS, &mut data)?;
-        Ok(data.overruns)
+    /// Create a handler for vendor-specific mailbox (VoE) transfers.
+    ///
+    /// `size` is the maximum size of the data sent at once (excluding the
+    /// VoE header). The buffer grows if more data is received.
+    pub fn create_voe_handler(
+        &self,
+        master: &Master<Configuring>,
+        size: usize,
+    ) -> Result<VoeHandler> {
+        master.check_handle(self.origin, self.idx)?;
+        let mut data = ec::ec_ioctl_voe_t {
+            config_index: self.idx,
+            size: size as u64,
+            ..Default::default()
+        };
+        ioctl!(master, ec::request::SC_VOE, &mut data; ErrorTarget::Config(self.idx))?;
+        Ok(VoeHandler {
+            config_idx: self.idx,
+            origin: self.origin,
+            idx: data.voe_index,
+            data: vec![0; size],
+            data_size: 0,
+        })
     }
-
-    // XXX missing: create_sdo_request, create_reg_request, create_voe_handler
 }
 
 impl<'m> Domain<'m> {
-    pub const fn new(idx: DomainIdx, master: &'m Master) -> Self {
+    pub const fn new(idx: DomainIdx, master: &'m Master<Active>) -> Self {
         Self { idx, master }
     }
 
     pub fn size(&self) -> Result<usize> {
         ioctl!(
             self.master,
-            ec::ioctl::DOMAIN_SIZE,
-            c_ulong::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?
+            ec::request::DOMAIN_SIZE,
+            c_ulong::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?;
+            ErrorTarget::Domain(self.idx)
         )
         .map(|v| v as usize)
     }
@@ -767,19 +1913,16 @@ impl<'m> Domain<'m> {
                 .map_err(|_| Error::DomainIdx(usize::from(self.idx)))?,
             state: &mut state,
         };
-        ioctl!(self.master, ec::ioctl::DOMAIN_STATE, &mut data)?;
-        Ok(DomainState {
-            working_counter: state.working_counter,
-            redundancy_active: state.redundancy_active != 0,
-            wc_state: WcState::from(state.wc_state),
-        })
+        ioctl!(self.master, ec::request::DOMAIN_STATE, &mut data; ErrorTarget::Domain(self.idx))?;
+        decode_domain_state(&state)
     }
 
     pub fn process(&mut self) -> Result<()> {
         ioctl!(
             self.master,
-            ec::ioctl::DOMAIN_PROCESS,
-            c_ulong::from(usize::from(self.idx) as u64)
+            ec::request::DOMAIN_PROCESS,
+            c_ulong::from(usize::from(self.idx) as u64);
+            ErrorTarget::Domain(self.idx)
         )
         .map(|_| ())
     }
@@ -787,9 +1930,638 @@ impl<'m> Domain<'m> {
     pub fn queue(&mut self) -> Result<()> {
         ioctl!(
             self.master,
-            ec::ioctl::DOMAIN_QUEUE,
-            c_ulong::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?
+            ec::request::DOMAIN_QUEUE,
+            c_ulong::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?;
+            ErrorTarget::Domain(self.idx)
         )
         .map(|_| ())
     }
 }
+
+/// A non-blocking SDO transfer of a slave configuration.
+///
+/// Requests are created with `SlaveConfig::create_sdo_request` and are
+/// processed by the master in the background, so they can be used from
+/// within the cyclic task: start a transfer with `read` or `write` and
+/// poll `state` until it is no longer busy.
+#[derive(Debug)]
+pub struct SdoRequest {
+    config_idx: SlaveConfigIdx,
+    idx: u32,
+    origin: Origin,
+    sdo_idx: SdoIdx,
+    data: Vec<u8>,
+    data_size: usize,
+}
+
+impl SdoRequest {
+    pub const fn sdo_idx(&self) -> SdoIdx {
+        self.sdo_idx
+    }
+
+    fn ioctl_data(&self) -> ec::ec_ioctl_sdo_request_t {
+        ec::ec_ioctl_sdo_request_t {
+            config_index: self.config_idx,
+            request_index: self.idx,
+            ..Default::default()
+        }
+    }
+
+    /// Change the SDO index and subindex of the request.
+    pub fn set_sdo_idx(&mut self, master: &Master<Active>, sdo_idx: SdoIdx) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.ioctl_data();
+        data.sdo_index = u16::from(sdo_idx.idx);
+        data.sdo_subindex = u8::from(sdo_idx.sub_idx);
+        ioctl!(
+            master,
+            ec::request::SDO_REQUEST_INDEX,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )?;
+        self.sdo_idx = sdo_idx;
+        Ok(())
+    }
+
+    /// Set the timeout for the transfer. A zero timeout means no timeout.
+    pub fn set_timeout(&mut self, master: &Master<Active>, timeout: Duration) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.ioctl_data();
+        data.timeout = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        ioctl!(
+            master,
+            ec::request::SDO_REQUEST_TIMEOUT,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )
+        .map(|_| ())
+    }
+
+    /// Get the current state of the request.
+    ///
+    /// If an upload has completed, the received data is fetched and can be
+    /// accessed with `data` afterwards.
+    pub fn state(&mut self, master: &Master<Active>) -> Result<RequestState> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.ioctl_data();
+        ioctl!(
+            master,
+            ec::request::SDO_REQUEST_STATE,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )?;
+        let state = request_state(data.state)?;
+        if data.size > 0 {
+            // New data is waiting to be copied.
+            let size = data.size as usize;
+            if self.data.len() < size {
+                self.data.resize(size, 0);
+            }
+            data.data = self.data.as_mut_ptr();
+            ioctl!(
+                master,
+                ec::request::SDO_REQUEST_DATA,
+                &mut data;
+                ErrorTarget::Config(self.config_idx)
+            )?;
+            self.data_size = data.size as usize;
+        }
+        Ok(state)
+    }
+
+    /// Start an SDO upload (read from the slave).
+    pub fn read(&mut self, master: &Master<Active>) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.ioctl_data();
+        ioctl!(master, ec::request::SDO_REQUEST_READ, &mut data; ErrorTarget::Config(self.config_idx))
+            .map(|_| ())
+    }
+
+    /// Start an SDO download (write to the slave).
+    pub fn write(&mut self, master: &Master<Active>, value: &[u8]) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        if self.data.len() < value.len() {
+            self.data.resize(value.len(), 0);
+        }
+        self.data[..value.len()].copy_from_slice(value);
+        self.data_size = value.len();
+        let mut data = self.ioctl_data();
+        data.data = self.data.as_mut_ptr();
+        data.size = value.len() as u64;
+        ioctl!(
+            master,
+            ec::request::SDO_REQUEST_WRITE,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )
+        .map(|_| ())
+    }
+
+    /// The data of the last completed upload or the last started download.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.data_size]
+    }
+}
+
+/// A non-blocking ESC register transfer of a slave configuration.
+///
+/// Requests are created with `SlaveConfig::create_reg_request`. Like
+/// `SdoRequest`, a transfer is started with `read` or `write` and
+/// `state` is polled until it is no longer busy.
+#[derive(Debug)]
+pub struct RegRequest {
+    config_idx: SlaveConfigIdx,
+    idx: u32,
+    origin: Origin,
+    data: Vec<u8>,
+    transfer_size: usize,
+}
+
+impl RegRequest {
+    fn ioctl_data(&self) -> ec::ec_ioctl_reg_request_t {
+        ec::ec_ioctl_reg_request_t {
+            config_index: self.config_idx,
+            request_index: self.idx,
+            ..Default::default()
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<()> {
+        if size > self.data.len() {
+            return Err(Error::RequestSize(size, self.data.len()));
+        }
+        Ok(())
+    }
+
+    /// Get the current state of the request.
+    ///
+    /// If a read has completed, the received data is fetched and can be
+    /// accessed with `data` afterwards.
+    pub fn state(&mut self, master: &Master<Active>) -> Result<RequestState> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.ioctl_data();
+        ioctl!(
+            master,
+            ec::request::REG_REQUEST_STATE,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )?;
+        let state = request_state(data.state)?;
+        if data.new_data != 0 {
+            // New data is waiting to be copied.
+            data.data = self.data.as_mut_ptr();
+            data.mem_size = self.data.len() as u64;
+            ioctl!(
+                master,
+                ec::request::REG_REQUEST_DATA,
+                &mut data;
+                ErrorTarget::Config(self.config_idx)
+            )?;
+        }
+        Ok(state)
+    }
+
+    /// Start reading `size` bytes from the register at `address`.
+    pub fn read(&mut self, master: &Master<Active>, address: u16, size: usize) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        self.check_size(size)?;
+        let mut data = self.ioctl_data();
+        data.address = address;
+        data.transfer_size = size as u64;
+        ioctl!(
+            master,
+            ec::request::REG_REQUEST_READ,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )?;
+        self.transfer_size = size;
+        Ok(())
+    }
+
+    /// Start writing `value` to the register at `address`.
+    pub fn write(&mut self, master: &Master<Active>, address: u16, value: &[u8]) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.prepare_write(address, value)?;
+        ioctl!(
+            master,
+            ec::request::REG_REQUEST_WRITE,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )?;
+        self.transfer_size = value.len();
+        Ok(())
+    }
+
+    /// Start writing `value` to the register at `address` and reading
+    /// back the register contents in the same datagram.
+    #[cfg(feature = "sncn")]
+    pub fn readwrite(&mut self, master: &Master<Active>, address: u16, value: &[u8]) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.prepare_write(address, value)?;
+        ioctl!(
+            master,
+            ec::request::REG_REQUEST_READWRITE,
+            &mut data;
+            ErrorTarget::Config(self.config_idx)
+        )?;
+        self.transfer_size = value.len();
+        Ok(())
+    }
+
+    fn prepare_write(&mut self, address: u16, value: &[u8]) -> Result<ec::ec_ioctl_reg_request_t> {
+        self.check_size(value.len())?;
+        self.data[..value.len()].copy_from_slice(value);
+        let mut data = self.ioctl_data();
+        data.data = self.data.as_mut_ptr();
+        data.address = address;
+        data.transfer_size = value.len() as u64;
+        Ok(data)
+    }
+
+    /// The data of the last transfer.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.transfer_size]
+    }
+}
+
+/// A handler for vendor-specific mailbox (VoE) transfers.
+///
+/// Handlers are created with `SlaveConfig::create_voe_handler`. A transfer
+/// is started with `read`, `read_nosync` or `write`, and then `execute` has
+/// to be called cyclically until it is no longer busy.
+#[derive(Debug)]
+pub struct VoeHandler {
+    config_idx: SlaveConfigIdx,
+    idx: u32,
+    origin: Origin,
+    data: Vec<u8>,
+    data_size: usize,
+}
+
+impl VoeHandler {
+    fn ioctl_data(&self) -> ec::ec_ioctl_voe_t {
+        ec::ec_ioctl_voe_t {
+            config_index: self.config_idx,
+            voe_index: self.idx,
+            ..Default::default()
+        }
+    }
+
+    /// Set the VoE header for the following write operations.
+    pub fn send_header(&self, master: &Master<Active>, header: VoeHeader) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut vendor_id = header.vendor_id;
+        let mut vendor_type = header.vendor_type;
+        let mut data = self.ioctl_data();
+        data.vendor_id = &mut vendor_id;
+        data.vendor_type = &mut vendor_type;
+        ioctl!(master, ec::request::VOE_SEND_HEADER, &data; ErrorTarget::Config(self.config_idx))
+            .map(|_| ())
+    }
+
+    /// Get the VoE header of the last received data.
+    pub fn received_header(&self, master: &Master<Active>) -> Result<VoeHeader> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut vendor_id = 0;
+        let mut vendor_type = 0;
+        let mut data = self.ioctl_data();
+        data.vendor_id = &mut vendor_id;
+        data.vendor_type = &mut vendor_type;
+        ioctl!(master, ec::request::VOE_REC_HEADER, &mut data; ErrorTarget::Config(self.config_idx))?;
+        Ok(VoeHeader {
+            vendor_id,
+            vendor_type,
+        })
+    }
+
+    /// Start a read operation, which waits for the slave's mailbox to be
+    /// filled first.
+    pub fn read(&self, master: &Master<Active>) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let data = self.ioctl_data();
+        ioctl!(master, ec::request::VOE_READ, &data; ErrorTarget::Config(self.config_idx))
+            .map(|_| ())
+    }
+
+    /// Start a read operation without waiting for the slave's mailbox
+    /// to be filled.
+    pub fn read_nosync(&self, master: &Master<Active>) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let data = self.ioctl_data();
+        ioctl!(master, ec::request::VOE_READ_NOSYNC, &data; ErrorTarget::Config(self.config_idx))
+            .map(|_| ())
+    }
+
+    /// Start a write operation with the given data.
+    pub fn write(&mut self, master: &Master<Active>, value: &[u8]) -> Result<()> {
+        master.check_handle(self.origin, self.config_idx)?;
+        if value.len() > self.data.len() {
+            return Err(Error::RequestSize(value.len(), self.data.len()));
+        }
+        self.data[..value.len()].copy_from_slice(value);
+        self.data_size = value.len();
+        let mut data = self.ioctl_data();
+        data.data = self.data.as_mut_ptr();
+        data.size = value.len() as u64;
+        ioctl!(master, ec::request::VOE_WRITE, &mut data; ErrorTarget::Config(self.config_idx))
+            .map(|_| ())
+    }
+
+    /// Execute the handler.
+    ///
+    /// This has to be called cyclically while the state is `Busy`. If a read
+    /// operation has completed, the received data is fetched and can be
+    /// accessed with `data` afterwards.
+    pub fn execute(&mut self, master: &Master<Active>) -> Result<RequestState> {
+        master.check_handle(self.origin, self.config_idx)?;
+        let mut data = self.ioctl_data();
+        ioctl!(master, ec::request::VOE_EXEC, &mut data; ErrorTarget::Config(self.config_idx))?;
+        let state = request_state(data.state)?;
+        if data.size > 0 {
+            // New data is waiting to be copied.
+            let size = data.size as usize;
+            if size > self.data.len() {
+                self.data.resize(size, 0);
+            }
+            data.data = self.data.as_mut_ptr();
+            ioctl!(master, ec::request::VOE_DATA, &mut data; ErrorTarget::Config(self.config_idx))?;
+            self.data_size = data.size as usize;
+        }
+        Ok(state)
+    }
+
+    /// The data of the last completed read or the last started write.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.data_size]
+    }
+}
+
+fn request_state(state: u32) -> Result<RequestState> {
+    Ok(match state {
+        ec::EC_REQUEST_UNUSED => RequestState::Unused,
+        ec::EC_REQUEST_BUSY => RequestState::Busy,
+        ec::EC_REQUEST_SUCCESS => RequestState::Success,
+        ec::EC_REQUEST_ERROR => RequestState::Error,
+        x => return Err(Error::InvalidRequestState(x)),
+    })
+}
+
+#[test]
+fn test_ioctl_error_classification() {
+    assert_eq!(ec::request::SLAVE_SDO_UPLOAD.raw().name, "SLAVE_SDO_UPLOAD");
+    let err = |errno| {
+        Error::from_ioctl(
+            "SLAVE",
+            ErrorTarget::Slave(SlavePos::from(3)),
+            std::io::Error::from_raw_os_error(errno),
+        )
+    };
+    assert!(matches!(err(libc::EBUSY), Error::Busy { .. }));
+    assert!(matches!(err(libc::ETIMEDOUT), Error::Timeout { .. }));
+    assert!(matches!(err(libc::EINVAL), Error::NotFound { .. }));
+    assert!(matches!(err(libc::EPERM), Error::WrongPhase { .. }));
+    match err(libc::ENOMEM) {
+        Error::Ioctl { op, target, .. } => {
+            assert_eq!(op, "SLAVE");
+            assert_eq!(target, ErrorTarget::Slave(SlavePos::from(3)));
+        }
+        e => panic!("unexpected error {:?}", e),
+    }
+    assert_eq!(err(libc::EIO).raw_os_error(), Some(libc::EIO));
+    assert!(err(libc::ENOENT)
+        .to_string()
+        .starts_with("SLAVE on slave 3: "));
+}
+
+/// Reinterpret arbitrary bytes as a raw kernel struct.
+///
+/// Only used with structs consisting of integers, for which every bit
+/// pattern is valid.
+#[cfg(test)]
+fn raw_struct<T: Copy>(bytes: &[u8]) -> T {
+    assert_eq!(bytes.len(), std::mem::size_of::<T>());
+    unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+#[cfg(test)]
+fn raw_bytes<T>() -> impl proptest::strategy::Strategy<Value = Vec<u8>> {
+    proptest::collection::vec(proptest::num::u8::ANY, std::mem::size_of::<T>())
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn prop_decode_master_info(bytes in raw_bytes::<ec::ec_ioctl_master_t>()) {
+        let data: ec::ec_ioctl_master_t = raw_struct(&bytes);
+        match decode_master_info(&data) {
+            Ok(info) => proptest::prop_assert!(info.devices.len() <= data.devices.len()),
+            Err(e) => proptest::prop_assert!(matches!(e, Error::InvalidMasterPhase(_))),
+        }
+    }
+
+    #[test]
+    fn prop_decode_slave_info(bytes in raw_bytes::<ec::ec_ioctl_slave_t>()) {
+        let data: ec::ec_ioctl_slave_t = raw_struct(&bytes);
+        match decode_slave_info(&data) {
+            Ok(info) => proptest::prop_assert!(info.name.len() <= 3 * data.name.len()),
+            Err(e) => proptest::prop_assert!(matches!(
+                e,
+                Error::InvalidPortType(_) | Error::InvalidAlState(_) | Error::InvalidDcRange(_)
+            )),
+        }
+    }
+
+    #[test]
+    fn prop_decode_config_info(bytes in raw_bytes::<ec::ec_ioctl_config_t>()) {
+        let data: ec::ec_ioctl_config_t = raw_struct(&bytes);
+        let valid = data.syncs.iter().all(|sync| sync.dir <= 2 && sync.watchdog_mode <= 2);
+        match decode_config_info(&data) {
+            Ok(info) => {
+                proptest::prop_assert!(valid);
+                proptest::prop_assert!(info.syncs.len() <= ec::EC_MAX_SYNC_MANAGERS as usize);
+            }
+            Err(e) => proptest::prop_assert!(matches!(
+                e,
+                Error::InvalidSyncDirection(_) | Error::InvalidWatchdogMode(_)
+            )),
+        }
+    }
+
+    #[test]
+    fn prop_decode_sdo_info(bytes in raw_bytes::<ec::ec_ioctl_slave_sdo_t>()) {
+        let data: ec::ec_ioctl_slave_sdo_t = raw_struct(&bytes);
+        let info = decode_sdo_info(&data);
+        proptest::prop_assert_eq!(u16::from(info.idx), data.sdo_index);
+        proptest::prop_assert_eq!(u8::from(info.max_sub_idx), data.max_subindex);
+        proptest::prop_assert!(!info.name.contains('\0'));
+    }
+
+    #[test]
+    fn prop_decode_sdo_entry(bytes in raw_bytes::<ec::ec_ioctl_slave_sdo_entry_t>()) {
+        let data: ec::ec_ioctl_slave_sdo_entry_t = raw_struct(&bytes);
+        let info = decode_sdo_entry(&data);
+        match DataType::from_u16(data.data_type) {
+            Some(data_type) => proptest::prop_assert_eq!(info.data_type, data_type),
+            None => proptest::prop_assert_eq!(info.data_type, DataType::Raw),
+        }
+        proptest::prop_assert_eq!(info.bit_len, data.bit_length);
+        proptest::prop_assert!(!info.description.contains('\0'));
+    }
+
+    #[test]
+    fn prop_decode_pdo(bytes in raw_bytes::<ec::ec_ioctl_slave_sync_pdo_t>()) {
+        let data: ec::ec_ioctl_slave_sync_pdo_t = raw_struct(&bytes);
+        let info = decode_pdo(&data);
+        proptest::prop_assert_eq!(u8::from(info.sm), data.sync_index as u8);
+        proptest::prop_assert_eq!(u16::from(info.idx), data.index);
+        proptest::prop_assert_eq!(info.entry_count, data.entry_count);
+        proptest::prop_assert!(!info.name.contains('\0'));
+    }
+
+    #[test]
+    fn prop_decode_sync(bytes in raw_bytes::<ec::ec_ioctl_slave_sync_t>()) {
+        let data: ec::ec_ioctl_slave_sync_t = raw_struct(&bytes);
+        let info = decode_sync(&data);
+        proptest::prop_assert_eq!(u8::from(info.idx), data.sync_index as u8);
+        proptest::prop_assert_eq!(info.start_addr, data.physical_start_address);
+        proptest::prop_assert_eq!(info.enable, data.enable == 1);
+        proptest::prop_assert_eq!(info.pdo_count, data.pdo_count);
+    }
+
+    #[test]
+    fn prop_decode_config_state(bytes in raw_bytes::<ec::ec_slave_config_state_t>()) {
+        let data: ec::ec_slave_config_state_t = raw_struct(&bytes);
+        let al_state = data.al_state() as u8;
+        match decode_config_state(&data) {
+            Ok(state) => {
+                proptest::prop_assert_eq!(state.al_state as u8, al_state);
+                proptest::prop_assert_eq!(state.online, data.online() != 0);
+            }
+            Err(e) => {
+                proptest::prop_assert!(AlState::try_from(al_state).is_err());
+                proptest::prop_assert!(matches!(e, Error::InvalidAlState(s) if s == al_state));
+            }
+        }
+    }
+
+    #[test]
+    fn prop_decode_domain_state(bytes in raw_bytes::<ec::ec_domain_state_t>()) {
+        let data: ec::ec_domain_state_t = raw_struct(&bytes);
+        match decode_domain_state(&data) {
+            Ok(state) => {
+                proptest::prop_assert!(data.wc_state <= 2);
+                proptest::prop_assert_eq!(state.working_counter, data.working_counter);
+            }
+            Err(e) => proptest::prop_assert!(matches!(e, Error::InvalidWcState(_))),
+        }
+    }
+
+    #[test]
+    fn prop_request_state(value: u32) {
+        match request_state(value) {
+            Ok(state) => proptest::prop_assert_eq!(state as u32, value),
+            Err(e) => {
+                proptest::prop_assert!(value > 3);
+                proptest::prop_assert!(matches!(e, Error::InvalidRequestState(v) if v == value));
+            }
+        }
+    }
+
+    #[test]
+    fn prop_decode_config_idn(bytes in raw_bytes::<ec::ec_ioctl_config_idn_t>()) {
+        let data: ec::ec_ioctl_config_idn_t = raw_struct(&bytes);
+        match decode_config_idn(&data) {
+            Ok(idn) => proptest::prop_assert!(idn.data.len() <= data.data.len()),
+            Err(e) => proptest::prop_assert!(matches!(e, Error::InvalidAlState(_))),
+        }
+    }
+
+    #[test]
+    fn prop_c_array_to_string(data in proptest::collection::vec(proptest::num::i8::ANY, 0..80)) {
+        let data: Vec<c_char> = data.into_iter().map(|c| c as c_char).collect();
+        let s = c_array_to_string(&data);
+        proptest::prop_assert!(!s.contains('\0'));
+    }
+}
+
+#[test]
+fn test_backend_dispatch() {
+    struct TestBackend(u32);
+
+    impl Backend for TestBackend {
+        unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> std::io::Result<c_int> {
+            if request == ec::request::MODULE.raw() {
+                assert_eq!(
+                    request.arg_size(),
+                    std::mem::size_of::<ec::ec_ioctl_module_t>()
+                );
+                let module = &mut *(arg as *mut ec::ec_ioctl_module_t);
+                module.ioctl_version_magic = self.0;
+                module.master_count = 2;
+                Ok(0)
+            } else {
+                Err(std::io::Error::from_raw_os_error(libc::EBUSY))
+            }
+        }
+
+        fn map_process_data(&self, _: usize) -> std::io::Result<Box<dyn ProcessDataMemory>> {
+            Err(std::io::Error::from_raw_os_error(libc::ENODEV))
+        }
+    }
+
+    assert!(matches!(
+        Master::with_backend(TestBackend(0)),
+        Err(Error::KernelModule(..))
+    ));
+    let master = Master::with_backend(TestBackend(ec::EC_IOCTL_VERSION_MAGIC)).unwrap();
+    match master.reserve() {
+        Err(PhaseError {
+            master,
+            error: Error::Busy { op, target, .. },
+        }) => {
+            assert_eq!(op, "REQUEST");
+            assert_eq!(target, ErrorTarget::Master);
+            // The master is handed back for another attempt.
+            assert!(master.reserve().is_err());
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_voe_buffer_grows() {
+    struct VoeBackend;
+
+    impl Backend for VoeBackend {
+        unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> std::io::Result<c_int> {
+            if request == ec::request::MODULE.raw() {
+                let module = &mut *(arg as *mut ec::ec_ioctl_module_t);
+                module.ioctl_version_magic = ec::EC_IOCTL_VERSION_MAGIC;
+                module.master_count = 1;
+            } else if request == ec::request::VOE_EXEC.raw() {
+                let voe = &mut *(arg as *mut ec::ec_ioctl_voe_t);
+                voe.state = ec::EC_REQUEST_SUCCESS;
+                voe.size = 6;
+            } else if request == ec::request::VOE_DATA.raw() {
+                // The kernel copies the whole received data.
+                let voe = &mut *(arg as *mut ec::ec_ioctl_voe_t);
+                std::ptr::copy_nonoverlapping([1, 2, 3, 4, 5, 6].as_ptr(), voe.data, 6);
+                voe.size = 6;
+            }
+            Ok(0)
+        }
+
+        fn map_process_data(&self, size: usize) -> std::io::Result<Box<dyn ProcessDataMemory>> {
+            Ok(Box::new(vec![0; size]))
+        }
+    }
+
+    let mut master = Master::with_backend(VoeBackend).unwrap().reserve().unwrap();
+    let config = master
+        .configure_slave(SlaveAddr::ByPos(0), SlaveId::new(0, 0))
+        .unwrap();
+    let mut voe = config.create_voe_handler(&master, 2).unwrap();
+    let master = master.activate().unwrap();
+    assert_eq!(voe.execute(&master).unwrap(), RequestState::Success);
+    assert_eq!(voe.data(), &[1, 2, 3, 4, 5, 6]);
+}
@@ -0,0 +1,1506 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! A userspace EtherCAT master on a raw socket.
+//!
+//! `PacketMaster` sends EtherCAT frames over an `AF_PACKET` socket of a
+//! network interface, so that applications using `Master`, `SlaveConfig`
+//! and `Domain` can be run without the IgH kernel module:
+//!
+//! ```no_run
+//! use ethercat::{packet::PacketMaster, Master};
+//!
+//! let packet = PacketMaster::open("eth0")?;
+//! let master = Master::with_backend(packet.backend())?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Opening the socket needs the `CAP_NET_RAW` capability. The slaves are
+//! scanned when the master is opened: they get station addresses, their
+//! SII is read and they are brought to PreOp with the mailbox configured.
+//!
+//! The requests of the application are handled like in the `sim` module.
+//! SDOs are transferred with CoE, but the object dictionary is not listed.
+//! On activation, changed PDO assignments and mappings are downloaded, the
+//! sync managers and FMMUs are set up, and the configured slaves are
+//! brought to Op, advancing one state per `Master::receive`. Every domain
+//! is exchanged with a single LRW datagram and has to fit into a frame.
+//!
+//! Other links than a `RawSocket`, e.g. software slaves for testing, can be
+//! used with `PacketMaster::with_link`.
+
+use crate::{
+    backend::{Backend, ProcessDataMemory},
+    ec,
+    emulation::{
+        next_al_state, Answer, Bus, ConfigError, ConfigResult, Connection, Network, SdoError,
+        SdoResult, SlaveDesc, SyncLayout,
+    },
+    register::{AlStatus, AlStatusCode, Register},
+    sdo::SdoAbortCode,
+    sii::{Sii, SiiMailbox, SiiSyncManager, SiiSyncManagerType},
+    types::*,
+};
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    io, mem,
+    os::raw::{c_int, c_ulong, c_void},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const ETHERTYPE: u16 = 0x88A4;
+/// Source address of the sent frames.
+const SOURCE: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+/// Maximum data size of a datagram in a frame.
+const MAX_DATA: usize = 1486;
+const FIRST_STATION: u16 = 0x1001;
+
+const APWR: u8 = 2;
+const FPRD: u8 = 4;
+const FPWR: u8 = 5;
+const BRD: u8 = 7;
+const LRW: u8 = 12;
+
+const REG_STATION: u16 = 0x0010;
+const REG_AL_CONTROL: u16 = 0x0120;
+const REG_AL_STATUS: u16 = 0x0130;
+const REG_SII_CONFIG: u16 = 0x0500;
+const REG_SII_CONTROL: u16 = 0x0502;
+const REG_FMMU: u16 = 0x0600;
+const REG_SM: u16 = 0x0800;
+
+const FRAME_TIMEOUT: Duration = Duration::from_millis(100);
+const FRAME_RETRIES: usize = 3;
+const STATE_TIMEOUT: Duration = Duration::from_secs(5);
+const SII_TIMEOUT: Duration = Duration::from_millis(100);
+const MAILBOX_TIMEOUT: Duration = Duration::from_secs(3);
+/// First pause between polls of the mailbox, doubled up to `MAX_POLL_PAUSE`.
+const POLL_PAUSE: Duration = Duration::from_micros(100);
+const MAX_POLL_PAUSE: Duration = Duration::from_millis(10);
+/// Limit of the SII size in words, which has to hold the end category.
+const MAX_SII_WORDS: usize = 0x1000;
+
+const MAILBOX_COE: u8 = 3;
+const COE_EMERGENCY: u16 = 1;
+const COE_SDO_REQUEST: u16 = 2;
+const COE_SDO_RESPONSE: u16 = 3;
+
+/// A link to send and receive Ethernet frames.
+pub trait Link: Send {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    /// Receive a frame into `buf`, waiting at most `timeout`.
+    ///
+    /// Returns the size of the frame, or `None` if none arrived.
+    fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<Option<usize>>;
+}
+
+/// An `AF_PACKET` socket for EtherCAT frames, bound to a network interface.
+pub struct RawSocket {
+    fd: c_int,
+}
+
+impl RawSocket {
+    pub fn open(interface: &str) -> io::Result<Self> {
+        Self::bind(interface, ETHERTYPE)
+    }
+
+    fn bind(interface: &str, protocol: u16) -> io::Result<Self> {
+        let name = CString::new(interface).map_err(|_| errno(libc::EINVAL))?;
+        let protocol = protocol.to_be();
+        unsafe {
+            let index = libc::if_nametoindex(name.as_ptr());
+            if index == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as c_int);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = RawSocket { fd };
+            let mut addr: libc::sockaddr_ll = mem::zeroed();
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = protocol;
+            addr.sll_ifindex = index as c_int;
+            let res = libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of_val(&addr) as libc::socklen_t,
+            );
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(socket)
+        }
+    }
+}
+
+impl Link for RawSocket {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let res = unsafe { libc::send(self.fd, frame.as_ptr() as *const c_void, frame.len(), 0) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<Option<usize>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut pollfd = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = left.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int;
+            if unsafe { libc::poll(&mut pollfd, 1, millis) } < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if pollfd.revents & libc::POLLIN == 0 {
+                return Ok(None);
+            }
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            let mut len = mem::size_of_val(&addr) as libc::socklen_t;
+            let size = unsafe {
+                libc::recvfrom(
+                    self.fd,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len(),
+                    0,
+                    &mut addr as *mut _ as *mut libc::sockaddr,
+                    &mut len,
+                )
+            };
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Sockets bound to all protocols also see the frames sent by us.
+            if addr.sll_pkttype != libc::PACKET_OUTGOING {
+                return Ok(Some(size as usize));
+            }
+        }
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// A userspace master on a `Link`.
+///
+/// The handle can be cloned; all backends created from it share the same
+/// master.
+#[derive(Clone)]
+pub struct PacketMaster {
+    bus: Arc<Mutex<Bus<PacketNetwork>>>,
+}
+
+impl PacketMaster {
+    /// Open a raw socket on a network interface and scan the slaves.
+    pub fn open(interface: &str) -> io::Result<Self> {
+        Self::with_link(RawSocket::open(interface)?)
+    }
+
+    /// Scan the slaves reached over `link`.
+    pub fn with_link<L: Link + 'static>(link: L) -> io::Result<Self> {
+        let mut network = PacketNetwork {
+            link: Box::new(link),
+            slaves: vec![],
+            next_index: 0,
+            pending: vec![],
+            state_reads: vec![],
+            queued: vec![],
+            answers: vec![],
+            buf: vec![0; 2048],
+        };
+        network.scan()?;
+        Ok(Self {
+            bus: Bus::new(network),
+        })
+    }
+
+    /// Create a backend for a `Master`, like opening `/dev/EtherCATn`.
+    pub fn backend(&self) -> PacketBackend {
+        PacketBackend(Connection::new(&self.bus))
+    }
+}
+
+/// A connection of a `Master` to a `PacketMaster`.
+///
+/// The connection which reserved the master releases it when dropped.
+pub struct PacketBackend(Connection<PacketNetwork>);
+
+impl Backend for PacketBackend {
+    unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        self.0.ioctl(request, arg)
+    }
+
+    fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>> {
+        self.0.map_process_data(size)
+    }
+
+    fn take_error(&self) -> Option<Error> {
+        self.0.take_error()
+    }
+}
+
+fn errno(code: i32) -> io::Error {
+    io::Error::from_raw_os_error(code)
+}
+
+/// The address of a register of the slave at a ring position.
+fn auto_increment(position: u16, register: u16) -> u32 {
+    u32::from(0u16.wrapping_sub(position)) | u32::from(register) << 16
+}
+
+/// The address of a register of the slave with a station address.
+fn configured(station: u16, register: u16) -> u32 {
+    u32::from(station) | u32::from(register) << 16
+}
+
+/// A datagram of a frame.
+struct Datagram {
+    command: u8,
+    index: u8,
+    address: u32,
+    data: Vec<u8>,
+    working_counter: u16,
+}
+
+impl Datagram {
+    fn new(command: u8, index: u8, address: u32, data: Vec<u8>) -> Self {
+        Self {
+            command,
+            index,
+            address,
+            data,
+            working_counter: 0,
+        }
+    }
+
+    /// The size of the datagram in a frame.
+    fn size(&self) -> usize {
+        12 + self.data.len()
+    }
+}
+
+/// Build a frame containing datagrams.
+fn frame(datagrams: &[Datagram]) -> Vec<u8> {
+    let length: usize = datagrams.iter().map(Datagram::size).sum();
+    let mut frame = Vec::with_capacity(60.max(16 + length));
+    frame.extend_from_slice(&[0xFF; 6]);
+    frame.extend_from_slice(&SOURCE);
+    frame.extend_from_slice(&ETHERTYPE.to_be_bytes());
+    frame.extend_from_slice(&(length as u16 | 0x1000).to_le_bytes());
+    for (i, datagram) in datagrams.iter().enumerate() {
+        let more = if i + 1 < datagrams.len() { 0x8000 } else { 0 };
+        frame.push(datagram.command);
+        frame.push(datagram.index);
+        frame.extend_from_slice(&datagram.address.to_le_bytes());
+        frame.extend_from_slice(&(datagram.data.len() as u16 | more).to_le_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&datagram.data);
+        frame.extend_from_slice(&datagram.working_counter.to_le_bytes());
+    }
+    frame.resize(frame.len().max(60), 0);
+    frame
+}
+
+/// Parse the datagrams of a received frame.
+fn parse_frame(frame: &[u8]) -> Option<Vec<Datagram>> {
+    if frame.get(12..14)? != ETHERTYPE.to_be_bytes() || frame.get(15)? >> 4 != 1 {
+        return None;
+    }
+    let mut datagrams = vec![];
+    let mut offset = 16;
+    loop {
+        let header = frame.get(offset..offset + 10)?;
+        let length = u16::from_le_bytes([header[6], header[7]]);
+        let len = (length & 0x7FF) as usize;
+        let data = frame.get(offset + 10..offset + 10 + len)?;
+        let wc = frame.get(offset + 10 + len..offset + 12 + len)?;
+        datagrams.push(Datagram {
+            command: header[0],
+            index: header[1],
+            address: u32_at(header, 2),
+            data: data.to_vec(),
+            working_counter: u16::from_le_bytes([wc[0], wc[1]]),
+        });
+        if length & 0x8000 == 0 {
+            return Some(datagrams);
+        }
+        offset += 12 + len;
+    }
+}
+
+/// Contents of a sync manager's registers.
+fn sm_registers(start: u16, size: u16, control: u8, enable: bool) -> [u8; 8] {
+    let [s0, s1] = start.to_le_bytes();
+    let [l0, l1] = size.to_le_bytes();
+    [s0, s1, l0, l1, control, 0, enable as u8, 0]
+}
+
+/// Contents of an FMMU's registers, mapping a sync manager's memory.
+fn fmmu_registers(address: u32, size: u16, start: u16, dir: ec::ec_direction_t) -> [u8; 16] {
+    let mut regs = [0; 16];
+    regs[0..4].copy_from_slice(&address.to_le_bytes());
+    regs[4..6].copy_from_slice(&size.to_le_bytes());
+    regs[7] = 7;
+    regs[8..10].copy_from_slice(&start.to_le_bytes());
+    regs[11] = if dir == ec::EC_DIR_OUTPUT { 2 } else { 1 };
+    regs[12] = 1;
+    regs
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Describe a slave by its SII.
+fn describe(sii: &Sii) -> SlaveDesc {
+    let name = sii
+        .general
+        .and_then(|g| sii.string(g.name_idx))
+        .unwrap_or_default();
+    let mut desc = SlaveDesc::new(sii.header.id);
+    desc.rev = sii.header.rev;
+    desc.alias = sii.header.alias;
+    desc.name = name.to_owned();
+    for (i, sm) in sii.sync_managers.iter().enumerate() {
+        let idx = SmIdx::from(i as u8);
+        let cfg = match sm.sm_type {
+            SiiSyncManagerType::MailboxOut | SiiSyncManagerType::ProcessDataOut => {
+                SmCfg::output(idx)
+            }
+            SiiSyncManagerType::MailboxIn | SiiSyncManagerType::ProcessDataIn => SmCfg::input(idx),
+            _ => continue,
+        };
+        let pdos = sii
+            .rx_pdos
+            .iter()
+            .chain(&sii.tx_pdos)
+            .filter(|p| p.sync_manager as usize == i)
+            .map(|p| PdoCfg {
+                idx: PdoIdx::from(p.idx),
+                entries: p
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .map(|(pos, e)| PdoEntryInfo {
+                        pos: PdoEntryPos::from(pos as u8),
+                        entry_idx: PdoEntryIdx::new(e.idx, e.sub_idx),
+                        bit_len: e.bit_len,
+                        name: sii.string(e.name_idx).unwrap_or_default().to_owned(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        desc.set_sync(cfg, pdos);
+    }
+    desc
+}
+
+struct Slave {
+    desc: SlaveDesc,
+    station: u16,
+    /// The standard mailbox: receive (written by the master) and send.
+    mailbox: Option<(SiiMailbox, SiiMailbox)>,
+    syncs: Vec<SiiSyncManager>,
+    al_state: AlState,
+    requested: AlState,
+    mailbox_counter: u8,
+}
+
+/// The slaves reached over a link.
+struct PacketNetwork {
+    link: Box<dyn Link>,
+    slaves: Vec<Slave>,
+    next_index: u8,
+    /// Datagram indices of the sent process data, with their domains.
+    pending: Vec<(u8, usize)>,
+    /// Datagram indices of the sent AL status reads, with their slaves and
+    /// whether a cycle passed since.
+    state_reads: Vec<(u8, usize, bool)>,
+    /// Datagrams to send along with the next process data.
+    queued: Vec<Datagram>,
+    answers: Vec<Answer>,
+    buf: Vec<u8>,
+}
+
+impl PacketNetwork {
+    fn next_index(&mut self) -> u8 {
+        self.next_index = self.next_index.wrapping_add(1);
+        self.next_index
+    }
+
+    /// Send a datagram and wait for it to return.
+    fn transact(&mut self, command: u8, address: u32, data: &[u8]) -> io::Result<(Vec<u8>, u16)> {
+        for _ in 0..FRAME_RETRIES {
+            let index = self.next_index();
+            let datagram = Datagram::new(command, index, address, data.to_vec());
+            self.link.send(&frame(&[datagram]))?;
+            let deadline = Instant::now() + FRAME_TIMEOUT;
+            while let Some(datagrams) = self.wait(deadline)? {
+                let answer = datagrams
+                    .into_iter()
+                    .find(|d| d.index == index && d.command == command);
+                if let Some(datagram) = answer {
+                    return Ok((datagram.data, datagram.working_counter));
+                }
+            }
+            log::debug!("Datagram 0x{:02X} to 0x{:08X} got lost", command, address);
+        }
+        Err(errno(libc::ETIMEDOUT))
+    }
+
+    /// Receive a frame until `deadline`, handling the answers to process
+    /// data and AL status reads on the way.
+    ///
+    /// Returns the other datagrams of the frame.
+    fn wait(&mut self, deadline: Instant) -> io::Result<Option<Vec<Datagram>>> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let size = match self.link.receive(&mut self.buf, timeout)? {
+                Some(size) => size,
+                None => return Ok(None),
+            };
+            let datagrams = match parse_frame(&self.buf[..size]) {
+                Some(datagrams) => datagrams,
+                None => continue,
+            };
+            let mut others = vec![];
+            for datagram in datagrams {
+                let pending = self
+                    .pending
+                    .iter()
+                    .position(|(index, _)| *index == datagram.index && datagram.command == LRW);
+                let state_read = self
+                    .state_reads
+                    .iter()
+                    .position(|(index, ..)| *index == datagram.index && datagram.command == FPRD);
+                if let Some(i) = pending {
+                    let (_, domain) = self.pending.remove(i);
+                    self.answers.push(Answer {
+                        domain,
+                        working_counter: datagram.working_counter,
+                        data: datagram.data,
+                    });
+                } else if let Some(i) = state_read {
+                    let (_, slave, _) = self.state_reads.remove(i);
+                    self.advance(slave, datagram);
+                } else {
+                    others.push(datagram);
+                }
+            }
+            if !others.is_empty() {
+                return Ok(Some(others));
+            }
+        }
+    }
+
+    /// Take queued datagrams in order, as long as they fit into `room`
+    /// bytes of a frame.
+    fn take_queued(&mut self, mut room: usize) -> Vec<Datagram> {
+        let count = self
+            .queued
+            .iter()
+            .take_while(|datagram| match room.checked_sub(datagram.size()) {
+                Some(left) => {
+                    room = left;
+                    true
+                }
+                None => false,
+            })
+            .count();
+        self.queued.drain(..count).collect()
+    }
+
+    fn read(&mut self, slave: usize, register: u16, size: usize) -> io::Result<Vec<u8>> {
+        let address = configured(self.slaves[slave].station, register);
+        let (data, wc) = self.transact(FPRD, address, &vec![0; size])?;
+        check_wc(slave, register, wc)?;
+        Ok(data)
+    }
+
+    fn write(&mut self, slave: usize, register: u16, data: &[u8]) -> io::Result<()> {
+        let address = configured(self.slaves[slave].station, register);
+        let (_, wc) = self.transact(FPWR, address, data)?;
+        check_wc(slave, register, wc)
+    }
+
+    /// Count the slaves, assign station addresses and bring them to PreOp.
+    fn scan(&mut self) -> io::Result<()> {
+        let (_, count) = self.transact(BRD, 0, &[0])?;
+        log::info!("Found {} slaves", count);
+        for position in 0..count {
+            let station = FIRST_STATION + position;
+            let address = auto_increment(position, REG_STATION);
+            let (_, wc) = self.transact(APWR, address, &station.to_le_bytes())?;
+            check_wc(position as usize, REG_STATION, wc)?;
+            self.slaves.push(Slave {
+                desc: SlaveDesc::new(SlaveId::new(0, 0)),
+                station,
+                mailbox: None,
+                syncs: vec![],
+                al_state: AlState::Init,
+                requested: AlState::Init,
+                mailbox_counter: 0,
+            });
+        }
+        for slave in 0..self.slaves.len() {
+            self.init_slave(slave)?;
+        }
+        Ok(())
+    }
+
+    fn init_slave(&mut self, slave: usize) -> io::Result<()> {
+        self.request_state(slave, AlState::Init, true)?;
+        self.write(slave, REG_FMMU, &[0; 256])?;
+        self.write(slave, REG_SM, &[0; 128])?;
+        let words = self.read_sii(slave)?;
+        let sii = Sii::parse(&words).map_err(|err| {
+            log::warn!("Invalid SII of slave {}: {}", slave, err);
+            errno(libc::EIO)
+        })?;
+        let (rx, tx) = (sii.header.std_rx_mailbox, sii.header.std_tx_mailbox);
+        if rx.size > 0 && tx.size > 0 {
+            self.write(slave, REG_SM, &sm_registers(rx.offset, rx.size, 0x26, true))?;
+            self.write(
+                slave,
+                REG_SM + 8,
+                &sm_registers(tx.offset, tx.size, 0x22, true),
+            )?;
+            self.slaves[slave].mailbox = Some((rx, tx));
+        }
+        self.slaves[slave].desc = describe(&sii);
+        self.slaves[slave].syncs = sii.sync_managers;
+        self.request_state(slave, AlState::PreOp, true)
+    }
+
+    /// Read the SII contents up to the end category.
+    fn read_sii(&mut self, slave: usize) -> io::Result<Vec<u16>> {
+        // Take the EEPROM access from the PDI.
+        self.write(slave, REG_SII_CONFIG, &[0, 0])?;
+        let mut words = vec![];
+        let mut offset = 0x40;
+        loop {
+            if offset + 2 > MAX_SII_WORDS {
+                log::warn!("SII of slave {} has no end category", slave);
+                return Err(errno(libc::EIO));
+            }
+            while words.len() < offset + 2 {
+                let address = words.len() as u32;
+                words.extend(self.read_sii_words(slave, address)?);
+            }
+            if words[offset] == 0xFFFF {
+                return Ok(words);
+            }
+            offset += 2 + words[offset + 1] as usize;
+        }
+    }
+
+    fn read_sii_words(&mut self, slave: usize, address: u32) -> io::Result<Vec<u16>> {
+        let mut request = vec![0x00, 0x01];
+        request.extend_from_slice(&address.to_le_bytes());
+        self.write(slave, REG_SII_CONTROL, &request)?;
+        let deadline = Instant::now() + SII_TIMEOUT;
+        loop {
+            let regs = self.read(slave, REG_SII_CONTROL, 14)?;
+            let status = u16_at(&regs, 0);
+            if status & 0x8000 == 0 {
+                if status & 0x7800 != 0 {
+                    log::warn!("SII read of slave {} failed: 0x{:04X}", slave, status);
+                    return Err(errno(libc::EIO));
+                }
+                let count = if status & 0x0040 != 0 { 4 } else { 2 };
+                return Ok((0..count).map(|i| u16_at(&regs, 6 + 2 * i)).collect());
+            }
+            if Instant::now() >= deadline {
+                return Err(errno(libc::ETIMEDOUT));
+            }
+        }
+    }
+
+    /// Read the AL state of a slave, acknowledging an error.
+    fn read_state(&mut self, slave: usize) -> io::Result<(AlState, Option<AlStatusCode>)> {
+        let regs = self.read(slave, REG_AL_STATUS, 6)?;
+        let (state, code) = self.store_state(slave, &regs)?;
+        if code.is_some() {
+            self.write(slave, REG_AL_CONTROL, &[state as u8 | 0x10, 0])?;
+        }
+        Ok((state, code))
+    }
+
+    /// Store the AL state of a slave from its AL status registers.
+    ///
+    /// Returns the state and, if the slave refused a state change, the
+    /// status code, which needs to be acknowledged.
+    fn store_state(
+        &mut self,
+        slave: usize,
+        regs: &[u8],
+    ) -> io::Result<(AlState, Option<AlStatusCode>)> {
+        let status = AlStatus::decode(regs);
+        let state = AlState::try_from(status.state).map_err(|_| errno(libc::EIO))?;
+        self.slaves[slave].al_state = state;
+        let code = if status.error {
+            Some(AlStatusCode::decode(&regs[4..]))
+        } else {
+            None
+        };
+        Ok((state, code))
+    }
+
+    /// Change the AL state of a slave and wait for the new state.
+    fn change_state(&mut self, slave: usize, state: AlState) -> io::Result<()> {
+        self.write(slave, REG_AL_CONTROL, &[state as u8, 0])?;
+        let deadline = Instant::now() + STATE_TIMEOUT;
+        loop {
+            match self.read_state(slave)? {
+                (current, None) if current == state => return Ok(()),
+                (_, Some(code)) => {
+                    log::warn!("Slave {} refused {:?}: {}", slave, state, code);
+                    return Err(errno(libc::EIO));
+                }
+                _ if Instant::now() >= deadline => return Err(errno(libc::ETIMEDOUT)),
+                _ => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+
+    /// Queue reads of the AL status of the slaves which have not reached
+    /// the requested state, unless a read of the last cycle is pending.
+    fn queue_state_reads(&mut self) {
+        // Forget the reads of the cycle before, which did not return.
+        self.state_reads.retain(|&(.., old)| !old);
+        for read in &mut self.state_reads {
+            read.2 = true;
+        }
+        for slave in 0..self.slaves.len() {
+            let pending = self.state_reads.iter().any(|&(_, s, _)| s == slave);
+            if pending || self.slaves[slave].al_state == self.slaves[slave].requested {
+                continue;
+            }
+            let index = self.next_index();
+            let address = configured(self.slaves[slave].station, REG_AL_STATUS);
+            self.queued
+                .push(Datagram::new(FPRD, index, address, vec![0; 6]));
+            self.state_reads.push((index, slave, false));
+        }
+    }
+
+    /// Handle the answer to a queued AL status read, by acknowledging a
+    /// refused state change or requesting the next state on the way to the
+    /// requested one.
+    fn advance(&mut self, slave: usize, datagram: Datagram) {
+        if datagram.working_counter != 1 {
+            log::debug!("Slave {} did not answer at 0x{:04X}", slave, REG_AL_STATUS);
+            return;
+        }
+        let requested = self.slaves[slave].requested;
+        let control = match self.store_state(slave, &datagram.data) {
+            Ok((state, Some(code))) => {
+                log::warn!("Slave {} refused {:?}: {}", slave, requested, code);
+                self.slaves[slave].requested = state;
+                state as u8 | 0x10
+            }
+            Ok((state, None)) if state != requested => next_al_state(state, requested) as u8,
+            Ok(_) => return,
+            Err(_) => {
+                log::debug!("Invalid AL status of slave {}", slave);
+                return;
+            }
+        };
+        let index = self.next_index();
+        let address = configured(self.slaves[slave].station, REG_AL_CONTROL);
+        self.queued
+            .push(Datagram::new(FPWR, index, address, vec![control, 0]));
+    }
+
+    /// Send a CoE request and return the CoE response.
+    fn coe(&mut self, slave: usize, request: &[u8]) -> io::Result<Vec<u8>> {
+        let (rx, tx) = self.slaves[slave]
+            .mailbox
+            .ok_or_else(|| errno(libc::EPROTONOSUPPORT))?;
+        if 6 + request.len() > rx.size as usize {
+            return Err(errno(libc::EOVERFLOW));
+        }
+        let counter = self.slaves[slave].mailbox_counter % 7 + 1;
+        self.slaves[slave].mailbox_counter = counter;
+        let mut mailbox = vec![0; rx.size as usize];
+        mailbox[0..2].copy_from_slice(&(request.len() as u16).to_le_bytes());
+        mailbox[5] = counter << 4 | MAILBOX_COE;
+        mailbox[6..6 + request.len()].copy_from_slice(request);
+
+        let address = configured(self.slaves[slave].station, rx.offset);
+        let deadline = Instant::now() + MAILBOX_TIMEOUT;
+        let mut pause = POLL_PAUSE;
+        // The write fails while the slave has not read the last request.
+        while self.transact(FPWR, address, &mailbox)?.1 != 1 {
+            if Instant::now() >= deadline {
+                return Err(errno(libc::ETIMEDOUT));
+            }
+            back_off(&mut pause);
+        }
+        pause = POLL_PAUSE;
+        loop {
+            if self.read(slave, REG_SM + 8 + 5, 1)?[0] & 0x08 != 0 {
+                let data = self.read(slave, tx.offset, tx.size as usize)?;
+                let len = u16_at(&data, 0) as usize;
+                let payload = data.get(6..6 + len).ok_or_else(|| errno(libc::EIO))?;
+                match data[5] & 0x0F {
+                    MAILBOX_COE if payload.len() >= 2 => {
+                        if u16_at(payload, 0) >> 12 != COE_EMERGENCY {
+                            return Ok(payload.to_vec());
+                        }
+                        log::warn!("Emergency of slave {}: {:02X?}", slave, &payload[2..]);
+                    }
+                    0 => {
+                        log::warn!("Mailbox error of slave {}: {:02X?}", slave, payload);
+                        return Err(errno(libc::EIO));
+                    }
+                    _ => log::debug!("Ignoring mailbox message of slave {}", slave),
+                }
+            } else if Instant::now() >= deadline {
+                return Err(errno(libc::ETIMEDOUT));
+            } else {
+                back_off(&mut pause);
+            }
+        }
+    }
+
+    /// Exchange an SDO request and response, which start with a command
+    /// byte.
+    fn sdo(&mut self, slave: usize, request: &[u8]) -> SdoResult<Vec<u8>> {
+        let mut coe = (COE_SDO_REQUEST << 12).to_le_bytes().to_vec();
+        coe.extend_from_slice(request);
+        let response = self.coe(slave, &coe)?;
+        if response.len() < 10 || u16_at(&response, 0) >> 12 != COE_SDO_RESPONSE {
+            return Err(errno(libc::EIO).into());
+        }
+        if response[2] >> 5 == 4 {
+            return Err(SdoAbortCode::from(u32_at(&response, 6)).into());
+        }
+        Ok(response[2..].to_vec())
+    }
+
+    /// Download the PDO assignment of a sync manager and the PDO mappings
+    /// which differ from the slave's default.
+    fn download_pdos(&mut self, slave: usize, sync: u8, pdos: &[PdoCfg]) -> ConfigResult<()> {
+        let assign = 0x1C10 + u16::from(sync);
+        self.config_download(slave, assign, 0, &[0])?;
+        for (i, pdo) in pdos.iter().enumerate() {
+            let idx = u16::from(pdo.idx);
+            let default = self.slaves[slave]
+                .desc
+                .default_pdos(sync)
+                .iter()
+                .find(|p| p.idx == pdo.idx)
+                .map(|p| &p.entries);
+            let same = default.is_some_and(|entries| {
+                entries.len() == pdo.entries.len()
+                    && entries
+                        .iter()
+                        .zip(&pdo.entries)
+                        .all(|(a, b)| a.entry_idx == b.entry_idx && a.bit_len == b.bit_len)
+            });
+            if !same {
+                self.config_download(slave, idx, 0, &[0])?;
+                for (j, entry) in pdo.entries.iter().enumerate() {
+                    let value = u32::from(u16::from(entry.entry_idx.idx)) << 16
+                        | u32::from(u8::from(entry.entry_idx.sub_idx)) << 8
+                        | u32::from(entry.bit_len);
+                    self.config_download(slave, idx, j as u8 + 1, &value.to_le_bytes())?;
+                }
+                self.config_download(slave, idx, 0, &[pdo.entries.len() as u8])?;
+            }
+            self.config_download(slave, assign, i as u8 + 1, &idx.to_le_bytes())?;
+        }
+        self.config_download(slave, assign, 0, &[pdos.len() as u8])
+    }
+
+    /// Download an SDO of the configuration, reporting the object and the
+    /// abort code if the slave refuses it.
+    fn config_download(
+        &mut self,
+        slave: usize,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> ConfigResult<()> {
+        self.download(slave, index, subindex, false, data)
+            .map_err(|err| match err {
+                SdoError::Abort(code) => ConfigError::SdoAbort {
+                    idx: SdoIdx::new(index, subindex),
+                    code,
+                },
+                SdoError::Io(err) => err.into(),
+            })
+    }
+
+    fn download(
+        &mut self,
+        slave: usize,
+        index: u16,
+        subindex: u8,
+        complete_access: bool,
+        data: &[u8],
+    ) -> SdoResult<()> {
+        let [i0, i1] = index.to_le_bytes();
+        let access = if complete_access { 0x10 } else { 0 };
+        if (1..=4).contains(&data.len()) {
+            let mut request = vec![
+                0x23 | access | (4 - data.len() as u8) << 2,
+                i0,
+                i1,
+                subindex,
+            ];
+            request.extend_from_slice(data);
+            request.resize(8, 0);
+            return check_command(&self.sdo(slave, &request)?, 0x60);
+        }
+        let mailbox_size = self.slaves[slave]
+            .mailbox
+            .map_or(0, |(rx, _)| rx.size as usize);
+        let first = data.len().min(mailbox_size.saturating_sub(16));
+        let mut request = vec![0x21 | access, i0, i1, subindex];
+        request.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        request.extend_from_slice(&data[..first]);
+        check_command(&self.sdo(slave, &request)?, 0x60)?;
+
+        let max_segment = mailbox_size.saturating_sub(9).max(7);
+        let mut toggle = 0;
+        for segment in data[first..].chunks(max_segment) {
+            let last = segment.as_ptr_range().end == data.as_ptr_range().end;
+            let padding = 7_usize.saturating_sub(segment.len()) as u8;
+            let mut request = vec![toggle << 4 | padding << 1 | last as u8];
+            request.extend_from_slice(segment);
+            request.resize(request.len() + padding as usize, 0);
+            check_command(&self.sdo(slave, &request)?, 0x20 | toggle << 4)?;
+            toggle ^= 1;
+        }
+        Ok(())
+    }
+}
+
+/// Sleep for `pause` between polls, and double it up to a limit.
+fn back_off(pause: &mut Duration) {
+    thread::sleep(*pause);
+    *pause = (*pause * 2).min(MAX_POLL_PAUSE);
+}
+
+fn check_wc(slave: usize, register: u16, wc: u16) -> io::Result<()> {
+    if wc == 1 {
+        Ok(())
+    } else {
+        log::debug!("Slave {} did not answer at 0x{:04X}", slave, register);
+        Err(errno(libc::EIO))
+    }
+}
+
+/// Check the command specifier of an SDO response.
+fn check_command(response: &[u8], expected: u8) -> SdoResult<()> {
+    if response[0] & 0xF0 == expected {
+        Ok(())
+    } else {
+        Err(errno(libc::EIO).into())
+    }
+}
+
+impl Network for PacketNetwork {
+    fn slave_count(&self) -> usize {
+        self.slaves.len()
+    }
+
+    fn desc(&self, slave: usize) -> &SlaveDesc {
+        &self.slaves[slave].desc
+    }
+
+    fn has_mailbox(&self, slave: usize) -> bool {
+        self.slaves[slave].mailbox.is_some()
+    }
+
+    fn al_state(&self, slave: usize) -> AlState {
+        self.slaves[slave].al_state
+    }
+
+    fn request_state(&mut self, slave: usize, state: AlState, wait: bool) -> io::Result<()> {
+        self.slaves[slave].requested = state;
+        if wait {
+            let (mut current, _) = self.read_state(slave)?;
+            while current != state {
+                current = next_al_state(current, state);
+                self.change_state(slave, current)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn sdo_upload(&mut self, slave: usize, index: u16, subindex: u8) -> SdoResult<Vec<u8>> {
+        let [i0, i1] = index.to_le_bytes();
+        let response = self.sdo(slave, &[0x40, i0, i1, subindex, 0, 0, 0, 0])?;
+        check_command(&response, 0x40)?;
+        let command = response[0];
+        if command & 0x02 != 0 {
+            let size = if command & 0x01 != 0 {
+                4 - (command >> 2 & 0x03) as usize
+            } else {
+                4
+            };
+            return Ok(response[4..4 + size].to_vec());
+        }
+        let size = u32_at(&response, 4) as usize;
+        let mut data = response[8..].to_vec();
+        let mut toggle = 0;
+        while data.len() < size {
+            let response = self.sdo(slave, &[0x60 | toggle << 4, 0, 0, 0, 0, 0, 0, 0])?;
+            check_command(&response, toggle << 4)?;
+            let command = response[0];
+            let mut segment = &response[1..];
+            if segment.len() == 7 {
+                segment = &segment[..7 - (command >> 1 & 0x07) as usize];
+            }
+            data.extend_from_slice(segment);
+            if command & 0x01 != 0 {
+                break;
+            }
+            toggle ^= 1;
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    fn sdo_download(
+        &mut self,
+        slave: usize,
+        index: u16,
+        subindex: u8,
+        complete_access: bool,
+        data: &[u8],
+    ) -> SdoResult<()> {
+        self.download(slave, index, subindex, complete_access, data)
+    }
+
+    fn configure(&mut self, slave: usize, syncs: &[SyncLayout]) -> ConfigResult<()> {
+        self.write(slave, REG_FMMU, &[0; 256])?;
+        let process_data: Vec<_> = (self.slaves[slave].syncs.iter().enumerate())
+            .filter(|(_, sm)| {
+                matches!(
+                    sm.sm_type,
+                    SiiSyncManagerType::ProcessDataOut | SiiSyncManagerType::ProcessDataIn
+                )
+            })
+            .map(|(i, _)| i as u16)
+            .collect();
+        for i in process_data {
+            self.write(slave, REG_SM + 8 * i, &[0; 8])?;
+        }
+        let mut fmmu = 0;
+        for sync in syncs {
+            if let Some(pdos) = &sync.pdos {
+                self.download_pdos(slave, sync.index, pdos)?;
+            }
+            let sm = *self.slaves[slave]
+                .syncs
+                .get(sync.index as usize)
+                .ok_or_else(|| errno(libc::EINVAL))?;
+            let mut control = sm.control_register & !0x0C;
+            if sync.dir == ec::EC_DIR_OUTPUT {
+                control |= 0x04;
+            }
+            match sync.watchdog_mode {
+                ec::EC_WD_ENABLE => control |= 0x40,
+                ec::EC_WD_DISABLE => control &= !0x40,
+                _ => {}
+            }
+            let size = sync.size as u16;
+            let regs = sm_registers(sm.start_addr, size, control, size > 0);
+            self.write(slave, REG_SM + 8 * u16::from(sync.index), &regs)?;
+            for &address in &sync.addresses {
+                let regs = fmmu_registers(address as u32, size, sm.start_addr, sync.dir);
+                self.write(slave, REG_FMMU + 16 * fmmu, &regs)?;
+                fmmu += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, domain: usize, address: usize, data: &[u8]) -> io::Result<()> {
+        if data.len() > MAX_DATA {
+            return Err(errno(libc::EMSGSIZE));
+        }
+        // Forget the last frame of the domain, if it did not return.
+        self.pending.retain(|(_, d)| *d != domain);
+        let index = self.next_index();
+        self.pending.push((index, domain));
+        let mut datagrams = self.take_queued(MAX_DATA - data.len());
+        datagrams.push(Datagram::new(LRW, index, address as u32, data.to_vec()));
+        self.link.send(&frame(&datagrams))
+    }
+
+    fn receive(&mut self) -> io::Result<Vec<Answer>> {
+        // Send the datagrams which no process data took along.
+        while !self.queued.is_empty() {
+            let datagrams = self.take_queued(MAX_DATA + 12);
+            self.link.send(&frame(&datagrams))?;
+        }
+        while let Some(datagrams) = self.wait(Instant::now())? {
+            for datagram in datagrams {
+                log::debug!("Ignoring datagram 0x{:02X}", datagram.command);
+            }
+        }
+        self.queue_state_reads();
+        Ok(self.answers.drain(..).collect())
+    }
+}
+
+/// A software slave, processing datagrams on its register memory.
+#[cfg(test)]
+struct TestSlave {
+    memory: Vec<u8>,
+    sii: Vec<u16>,
+    objects: std::collections::BTreeMap<(u16, u8), Vec<u8>>,
+}
+
+#[cfg(test)]
+impl TestSlave {
+    fn new() -> Self {
+        let mut sii = vec![0; 0x40];
+        sii[0x08] = 0x0002;
+        sii[0x0A] = 0x1234;
+        sii[0x18..0x1D].copy_from_slice(&[0x1000, 0x80, 0x1080, 0x80, 0x0004]);
+        let mut category = |cat: u16, data: &[u8]| {
+            sii.push(cat);
+            sii.push(data.len().div_ceil(2) as u16);
+            sii.extend(
+                data.chunks(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[c.len() - 1]])),
+            );
+        };
+        category(10, b"\x01\x07Soft IO");
+        let mut general = [0; 32];
+        general[3] = 1;
+        category(30, &general);
+        #[rustfmt::skip]
+        category(41, &[
+            0x00, 0x10, 0x80, 0x00, 0x26, 0, 1, 1,
+            0x80, 0x10, 0x80, 0x00, 0x22, 0, 1, 2,
+            0x00, 0x11, 0x01, 0x00, 0x64, 0, 1, 3,
+            0x80, 0x11, 0x02, 0x00, 0x20, 0, 1, 4,
+        ]);
+        category(
+            50,
+            &[0x00, 0x1A, 1, 3, 0, 0, 0, 0, 0x00, 0x60, 1, 0, 6, 16, 0, 0],
+        );
+        category(
+            51,
+            &[0x00, 0x16, 1, 2, 0, 0, 0, 0, 0x00, 0x70, 1, 0, 5, 8, 0, 0],
+        );
+        sii.push(0xFFFF);
+        let mut memory = vec![0; 0x1200];
+        memory[REG_AL_STATUS as usize] = AlState::Init as u8;
+        let mut objects = std::collections::BTreeMap::new();
+        objects.insert((0x1008, 0), b"Soft IO slave".to_vec());
+        objects.insert((0x8000, 1), vec![0, 0]);
+        Self {
+            memory,
+            sii,
+            objects,
+        }
+    }
+
+    fn station(&self) -> u16 {
+        u16_at(&self.memory, REG_STATION as usize)
+    }
+
+    fn al_state(&self) -> u8 {
+        self.memory[REG_AL_STATUS as usize] & 0x0F
+    }
+
+    /// The start and size of a sync manager's memory.
+    fn sm(&self, idx: usize) -> std::ops::Range<usize> {
+        let regs = &self.memory[REG_SM as usize + 8 * idx..];
+        let start = u16_at(regs, 0) as usize;
+        start..start + u16_at(regs, 2) as usize
+    }
+
+    /// Process a register read or write, returning the working counter.
+    fn access(&mut self, register: u16, data: &mut [u8], write: bool) -> u16 {
+        let range = register as usize..register as usize + data.len();
+        let (mbx_out, mbx_in) = (self.sm(0), self.sm(1));
+        if !write {
+            if range.contains(&mbx_in.start) && self.memory[0x080D] & 0x08 == 0 {
+                return 0;
+            }
+            data.copy_from_slice(&self.memory[range.clone()]);
+            if range.contains(&mbx_in.start) {
+                self.memory[0x080D] &= !0x08;
+            }
+            return 1;
+        }
+        if range.contains(&mbx_out.start) && self.memory[0x080D] & 0x08 != 0 {
+            return 0;
+        }
+        self.memory[range.clone()].copy_from_slice(data);
+        if range.contains(&(REG_AL_CONTROL as usize)) {
+            self.control(self.memory[REG_AL_CONTROL as usize]);
+        }
+        if range.contains(&(REG_SII_CONTROL as usize)) && data[1] & 0x01 != 0 {
+            let address = u32_at(&self.memory, 0x0504) as usize;
+            for i in 0..2 {
+                let word = self.sii.get(address + i).copied().unwrap_or(0xFFFF);
+                self.memory[0x0508 + 2 * i..0x050A + 2 * i].copy_from_slice(&word.to_le_bytes());
+            }
+            self.memory[0x0503] = 0;
+        }
+        if range.contains(&mbx_out.start) && !mbx_out.is_empty() {
+            let request = self.memory[mbx_out].to_vec();
+            let response = self.coe(&request[6..6 + u16_at(&request, 0) as usize]);
+            let mut mailbox = (response.len() as u16).to_le_bytes().to_vec();
+            mailbox.extend_from_slice(&[0, 0, 0, request[5]]);
+            mailbox.extend_from_slice(&response);
+            self.memory[mbx_in.start..mbx_in.start + mailbox.len()].copy_from_slice(&mailbox);
+            self.memory[0x080D] |= 0x08;
+        }
+        1
+    }
+
+    fn control(&mut self, control: u8) {
+        let current = self.memory[REG_AL_STATUS as usize];
+        if control & 0x10 != 0 {
+            self.memory[REG_AL_STATUS as usize] = current & 0x0F;
+            return;
+        }
+        let (current, requested) = match (
+            AlState::try_from(current & 0x0F),
+            AlState::try_from(control & 0x0F),
+        ) {
+            (Ok(current), Ok(requested)) => (current, requested),
+            _ => return,
+        };
+        let sizes_valid = self.sm(2).len() == 1 && self.sm(3).len() == 2;
+        let code: u16 = if next_al_state(current, requested) != requested {
+            0x0011
+        } else if requested == AlState::SafeOp && !sizes_valid {
+            0x001E
+        } else {
+            self.memory[REG_AL_STATUS as usize] = requested as u8;
+            return;
+        };
+        self.memory[REG_AL_STATUS as usize] = current as u8 | 0x10;
+        self.memory[0x0134..0x0136].copy_from_slice(&code.to_le_bytes());
+    }
+
+    /// Answer an expedited or normal SDO request.
+    fn coe(&mut self, request: &[u8]) -> Vec<u8> {
+        let key = (u16_at(request, 3), request[5]);
+        let mut response = vec![0x00, 0x30, 0, request[3], request[4], request[5]];
+        let command = request[2];
+        match (command >> 5, self.objects.get_mut(&key)) {
+            (2, Some(value)) if value.len() <= 4 => {
+                response[2] = 0x43 | (4 - value.len() as u8) << 2;
+                response.extend_from_slice(value);
+            }
+            (2, Some(value)) => {
+                response[2] = 0x41;
+                response.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                response.extend_from_slice(value);
+            }
+            (1, Some(value)) => {
+                *value = if command & 0x02 != 0 {
+                    request[6..10 - (command >> 2 & 0x03) as usize].to_vec()
+                } else {
+                    request[10..10 + u32_at(request, 6) as usize].to_vec()
+                };
+                response[2] = 0x60;
+            }
+            _ => {
+                response[2] = 0x80;
+                response.extend_from_slice(&0x0602_0000_u32.to_le_bytes());
+            }
+        }
+        response.resize(response.len().max(10), 0);
+        response
+    }
+
+    /// Process a logical datagram, returning the working counter.
+    fn exchange(&mut self, address: u32, data: &mut [u8]) -> u16 {
+        let (mut read, mut written) = (false, false);
+        let range = address as usize..address as usize + data.len();
+        for fmmu in 0..16 {
+            let regs = self.memory[REG_FMMU as usize + 16 * fmmu..][..16].to_vec();
+            let start = u32_at(&regs, 0) as usize;
+            let size = u16_at(&regs, 4) as usize;
+            let physical = u16_at(&regs, 8) as usize;
+            if regs[12] & 1 == 0 || !range.contains(&start) {
+                continue;
+            }
+            let offset = start - range.start;
+            let state = self.al_state();
+            if regs[11] == 1 && state >= AlState::SafeOp as u8 {
+                data[offset..offset + size].copy_from_slice(&self.memory[physical..][..size]);
+                read = true;
+            } else if regs[11] == 2 && state == AlState::Op as u8 {
+                self.memory[physical..][..size].copy_from_slice(&data[offset..offset + size]);
+                written = true;
+            }
+        }
+        read as u16 + 2 * written as u16
+    }
+}
+
+/// Answer a frame, as the software slaves on the wire.
+#[cfg(test)]
+fn answer(slaves: &mut [TestSlave], frame: &[u8]) -> Vec<u8> {
+    let mut datagrams = parse_frame(frame).unwrap();
+    for datagram in &mut datagrams {
+        let address = datagram.address;
+        let (position, register) = (address as u16, (address >> 16) as u16);
+        let data = &mut datagram.data;
+        for (i, slave) in slaves.iter_mut().enumerate() {
+            datagram.working_counter += match datagram.command {
+                APWR if position.wrapping_add(i as u16) == 0 => slave.access(register, data, true),
+                FPRD if position == slave.station() => slave.access(register, data, false),
+                FPWR if position == slave.station() => slave.access(register, data, true),
+                BRD => 1,
+                LRW => slave.exchange(address, data),
+                _ => 0,
+            };
+        }
+    }
+    self::frame(&datagrams)
+}
+
+/// A link to software slaves, answering each frame immediately.
+#[cfg(test)]
+struct TestLink {
+    slaves: Arc<Mutex<Vec<TestSlave>>>,
+    frames: std::collections::VecDeque<Vec<u8>>,
+}
+
+#[cfg(test)]
+impl Link for TestLink {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let frame = answer(&mut self.slaves.lock().unwrap(), frame);
+        self.frames.push_back(frame);
+        Ok(())
+    }
+
+    fn receive(&mut self, buf: &mut [u8], _: Duration) -> io::Result<Option<usize>> {
+        Ok(self.frames.pop_front().map(|frame| {
+            buf[..frame.len()].copy_from_slice(&frame);
+            frame.len()
+        }))
+    }
+}
+
+#[test]
+fn test_packet_master() {
+    use crate::Master;
+
+    let slaves = Arc::new(Mutex::new(vec![TestSlave::new(), TestSlave::new()]));
+    let link = TestLink {
+        slaves: slaves.clone(),
+        frames: Default::default(),
+    };
+    let packet = PacketMaster::with_link(link).unwrap();
+    let mut master = Master::with_backend(packet.backend()).unwrap();
+
+    let info = master.get_slave_info(SlavePos::from(1)).unwrap();
+    assert_eq!(info.name, "Soft IO");
+    assert_eq!(info.id, SlaveId::new(0x2, 0x1234));
+    assert_eq!(info.al_state, AlState::PreOp);
+    let pdo = master
+        .get_pdo_entry(
+            SlavePos::from(0),
+            SmIdx::from(3),
+            PdoPos::from(0),
+            PdoEntryPos::from(0),
+        )
+        .unwrap();
+    assert_eq!(pdo.entry_idx, PdoEntryIdx::new(0x6000, 1));
+    let mut buf = [0; 32];
+    let name = master
+        .sdo_upload(SlavePos::from(1), SdoIdx::new(0x1008, 0), false, &mut buf)
+        .unwrap();
+    assert_eq!(name, b"Soft IO slave");
+    assert!(matches!(
+        master.sdo_upload(SlavePos::from(1), SdoIdx::new(0x8001, 0), false, &mut buf),
+        Err(crate::Error::SdoAbort(SdoAbortCode::NoObject))
+    ));
+    // Empty data is sent as a normal download, expedited ones hold 1 to 4 bytes.
+    master
+        .sdo_download(SlavePos::from(1), SdoIdx::new(0x8000, 1), false, &&[][..])
+        .unwrap();
+    assert!(slaves.lock().unwrap()[1].objects[&(0x8000, 1)].is_empty());
+
+    slaves.lock().unwrap()[0].memory[0x1180..0x1182].copy_from_slice(&[0xCD, 0xAB]);
+    let inputs = crate::testing::run_cycles(master, 42).unwrap();
+    // The state changes go along with the process data, so that the slave
+    // reaches SafeOp in the second and Op in the third cycle.
+    assert_eq!(inputs, [0x02, 0x01, 0, 0, 0, 0, 0xCD, 0xAB]);
+    let slaves = slaves.lock().unwrap();
+    assert_eq!(slaves[0].memory[0x1100], 42);
+    assert_eq!(slaves[0].al_state(), AlState::PreOp as u8);
+    assert_eq!(slaves[1].al_state(), AlState::PreOp as u8);
+}
+
+#[test]
+fn test_packet_sii_without_end() {
+    let mut slave = TestSlave::new();
+    // Replace the end category by more categories than fit into the limit.
+    slave.sii.pop();
+    for _ in 0..MAX_SII_WORDS / 0x100 {
+        slave.sii.extend([0x0001, 0x00FF]);
+        slave.sii.extend([0; 0xFF]);
+    }
+    let link = TestLink {
+        slaves: Arc::new(Mutex::new(vec![slave])),
+        frames: Default::default(),
+    };
+    let err = PacketMaster::with_link(link)
+        .err()
+        .expect("read a truncated SII");
+    assert_eq!(err.raw_os_error(), Some(libc::EIO));
+}
+
+#[test]
+fn test_packet_config_abort() {
+    use crate::{Error, Master, PhaseError, SlaveAddr};
+
+    let link = TestLink {
+        slaves: Arc::new(Mutex::new(vec![TestSlave::new()])),
+        frames: Default::default(),
+    };
+    let packet = PacketMaster::with_link(link).unwrap();
+    let master = Master::with_backend(packet.backend()).unwrap();
+    let mut master = master.reserve().unwrap();
+    let config = master
+        .configure_slave(SlaveAddr::ByPos(0), SlaveId::new(0x2, 0x1234))
+        .unwrap();
+    let pdo = PdoCfg {
+        idx: PdoIdx::from(0x1600),
+        entries: vec![],
+    };
+    config
+        .config_sm_pdos(&master, SmCfg::output(SmIdx::from(2)), &[pdo])
+        .unwrap();
+    // The slave has no object for the PDO assignment.
+    let PhaseError { master, error: err } = master
+        .activate()
+        .err()
+        .expect("activated despite the abort");
+    assert_eq!(
+        err.to_string(),
+        "SDO 0x1C12:0 of slave 0 aborted while configuring: \
+         Object does not exist in the object dictionary"
+    );
+    assert!(matches!(
+        err,
+        Error::ConfigSdoAbort { slave, idx, code: SdoAbortCode::NoObject }
+            if slave == SlavePos::from(0) && idx == SdoIdx::new(0x1C12, 0)
+    ));
+    // The master is handed back to fix the configuration.
+    assert!(master.create_domain().is_ok());
+}
+
+/// A pair of veth interfaces, deleted again on drop.
+#[cfg(test)]
+struct Veth(&'static str);
+
+#[cfg(test)]
+impl Veth {
+    fn create(name: &'static str, peer: &str) -> Self {
+        let ip = |args: &[&str]| {
+            let status = std::process::Command::new("ip")
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success(), "ip {:?} failed", args);
+        };
+        ip(&["link", "add", name, "type", "veth", "peer", "name", peer]);
+        let veth = Veth(name);
+        ip(&["link", "set", name, "up"]);
+        ip(&["link", "set", peer, "up"]);
+        veth
+    }
+}
+
+#[cfg(test)]
+impl Drop for Veth {
+    fn drop(&mut self) {
+        let _ = std::process::Command::new("ip")
+            .args(["link", "del", self.0])
+            .status();
+    }
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN to create a veth pair"]
+fn test_raw_socket() {
+    use crate::Master;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let _veth = Veth::create("ecrs0", "ecrs1");
+    let mut master_side = RawSocket::open("ecrs0").unwrap();
+    let mut slave_side = RawSocket::open("ecrs1").unwrap();
+    let mut buf = [0; 1514];
+
+    // A sent frame arrives at the peer, but not back at the sender, even
+    // on a socket that gets the outgoing frames.
+    let mut tap = RawSocket::bind("ecrs0", libc::ETH_P_ALL as u16).unwrap();
+    let address = configured(FIRST_STATION, REG_AL_STATUS);
+    let frame = frame(&[Datagram::new(FPRD, 1, address, vec![0; 2])]);
+    master_side.send(&frame).unwrap();
+    let len = slave_side.receive(&mut buf, FRAME_TIMEOUT).unwrap();
+    assert_eq!(len.map(|len| &buf[..len]), Some(&frame[..]));
+    let timeout = Duration::from_millis(10);
+    assert_eq!(master_side.receive(&mut buf, timeout).unwrap(), None);
+    assert_eq!(slave_side.receive(&mut buf, timeout).unwrap(), None);
+    assert_eq!(tap.receive(&mut buf, timeout).unwrap(), None);
+    drop(tap);
+
+    // The software slaves answer on the peer.
+    let done = Arc::new(AtomicBool::new(false));
+    let slaves = Arc::new(Mutex::new(vec![TestSlave::new()]));
+    slaves.lock().unwrap()[0].memory[0x1180..0x1182].copy_from_slice(&[0xCD, 0xAB]);
+    let wire = {
+        let (done, slaves) = (done.clone(), slaves.clone());
+        std::thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                if let Some(len) = slave_side.receive(&mut buf, timeout).unwrap() {
+                    let frame = answer(&mut slaves.lock().unwrap(), &buf[..len]);
+                    slave_side.send(&frame).unwrap();
+                }
+            }
+        })
+    };
+    let packet = PacketMaster::with_link(master_side).unwrap();
+    let master = Master::with_backend(packet.backend()).unwrap();
+    let inputs = crate::testing::run_cycles(master, 42);
+    done.store(true, Ordering::Relaxed);
+    wire.join().unwrap();
+    assert_eq!(inputs.unwrap(), [0x02, 0x01, 0, 0, 0, 0, 0xCD, 0xAB]);
+    assert_eq!(slaves.lock().unwrap()[0].memory[0x1100], 42);
+}
@@ -0,0 +1,968 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Recording and replay of the requests of a `Master`.
+//!
+//! A `Recorder` wraps any other backend and captures every request with its
+//! argument struct and the buffers it points to, before and after the call,
+//! together with the result and the process data after each
+//! `Master::receive`. The captured `Session` can be saved, e.g. on the
+//! machine where a problem occurs, and later be served back by a `Replay`:
+//!
+//! ```no_run
+//! use ethercat::{backend::DeviceBackend, record::{Recording, Replay, Session}};
+//! use ethercat::{Master, MasterAccess};
+//! use std::{fs::File, io::BufReader};
+//!
+//! let recording = Recording::new();
+//! let backend = DeviceBackend::open(0, MasterAccess::ReadWrite)?;
+//! let master = Master::with_backend(recording.record(backend))?;
+//! // ... run the application ...
+//! recording.session().save(File::create("session.txt")?)?;
+//!
+//! let session = Session::load(BufReader::new(File::open("session.txt")?))?;
+//! let replay = Replay::new(session);
+//! let master = Master::with_backend(replay.backend())?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The replay expects exactly the recorded sequence of requests, with the
+//! same arguments and the same data written by the application, including
+//! the outputs in the process data at `Master::send`. The first request
+//! that differs fails with a `Divergence` describing the difference, and so
+//! do all following requests.
+//!
+//! Pointers within the argument structs and padding between their fields
+//! are not part of the session.
+
+use crate::{
+    backend::{Backend, ProcessDataMemory, SharedMemory},
+    ec,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    mem::{self, size_of},
+    ops::Range,
+    os::raw::{c_int, c_ulong},
+    ptr, slice,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use thiserror::Error;
+
+/// Size of an emergency message popped with `SC_EMERG_POP`.
+const EMERGENCY_SIZE: usize = 8;
+
+/// Collects the requests of all `Recorder`s created from it.
+#[derive(Clone, Default)]
+pub struct Recording {
+    session: Arc<Mutex<Session>>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap `backend` to record all requests executed by it.
+    pub fn record<B: Backend>(&self, backend: B) -> Recorder<B> {
+        Recorder {
+            inner: backend,
+            session: self.session.clone(),
+            memory: Mutex::default(),
+        }
+    }
+
+    /// The requests recorded so far.
+    pub fn session(&self) -> Session {
+        lock(&self.session).clone()
+    }
+}
+
+/// A backend recording the requests executed by another backend.
+pub struct Recorder<B> {
+    inner: B,
+    session: Arc<Mutex<Session>>,
+    memory: Mutex<Option<Arc<SharedMemory>>>,
+}
+
+impl<B> Recorder<B> {
+    fn process_data(&self) -> Option<Vec<u8>> {
+        lock(&self.memory)
+            .as_ref()
+            .map(|memory| unsafe { memory.data() }.to_vec())
+    }
+}
+
+impl<B: Backend> Backend for Recorder<B> {
+    unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        let layout = Layout::get(request, arg);
+        let before = layout.read(arg);
+        let buffers: Vec<_> = layout
+            .buffers(arg)
+            .into_iter()
+            .map(|(ptr, len)| slice::from_raw_parts(ptr, len))
+            .collect();
+        let buffers_before: Vec<_> = buffers.iter().map(|b| b.to_vec()).collect();
+        let mut process_data = if request == ec::request::SEND.raw() {
+            self.process_data()
+        } else {
+            None
+        };
+
+        let res = self.inner.ioctl(request, arg);
+
+        if res.is_ok() && request == ec::request::RECEIVE.raw() {
+            process_data = self.process_data();
+        }
+        if res.is_ok() && request == ec::request::DEACTIVATE.raw() {
+            *lock(&self.memory) = None;
+        }
+        let exchange = Exchange {
+            request: request.name.to_owned(),
+            arg: if layout.size == 0 { Some(arg) } else { None },
+            after: layout.read(arg),
+            before,
+            buffers: buffers_before
+                .into_iter()
+                .zip(buffers)
+                .map(|(before, after)| (before, after.to_vec()))
+                .collect(),
+            result: match &res {
+                Ok(ret) => *ret,
+                Err(e) => -e.raw_os_error().unwrap_or(libc::EIO),
+            },
+            process_data,
+        };
+        lock(&self.session).exchanges.push(exchange);
+        res
+    }
+
+    fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>> {
+        let memory = SharedMemory::new(self.inner.map_process_data(size)?);
+        let handle = memory.handle();
+        *lock(&self.memory) = Some(memory);
+        Ok(handle)
+    }
+}
+
+/// The requests recorded from a master.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    exchanges: Vec<Exchange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Exchange {
+    request: String,
+    /// The argument, if passed by value.
+    arg: Option<c_ulong>,
+    /// The argument struct before and after the call.
+    before: Vec<u8>,
+    after: Vec<u8>,
+    /// Contents of the buffers pointed to by the argument struct.
+    buffers: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The result, or the negated errno.
+    result: c_int,
+    /// The outputs sent with `SEND`, or the inputs received with `RECEIVE`.
+    process_data: Option<Vec<u8>>,
+}
+
+impl Session {
+    pub fn len(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exchanges.is_empty()
+    }
+
+    /// Names of the recorded requests.
+    pub fn requests(&self) -> impl Iterator<Item = &str> {
+        self.exchanges.iter().map(|e| e.request.as_str())
+    }
+
+    /// Write the session in a line based text format.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "# ethercat master session")?;
+        for e in &self.exchanges {
+            write!(writer, "{} result={}", e.request, e.result)?;
+            if let Some(arg) = e.arg {
+                write!(writer, " arg={}", arg)?;
+            }
+            if !e.before.is_empty() {
+                write_data(&mut writer, "in", &e.before, &e.after)?;
+            }
+            for (before, after) in &e.buffers {
+                write_data(&mut writer, "buf", before, after)?;
+            }
+            if let Some(data) = &e.process_data {
+                write!(writer, " pd={}", hex(data))?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Read a session written by `save`.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut exchanges = vec![];
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: invalid {}", n + 1, what),
+                )
+            };
+            let mut items = line.split_whitespace();
+            let request = items.next().unwrap_or_default().to_owned();
+            let mut exchange = Exchange {
+                request,
+                arg: None,
+                before: vec![],
+                after: vec![],
+                buffers: vec![],
+                result: 0,
+                process_data: None,
+            };
+            for item in items {
+                let (key, value) = item.split_once('=').ok_or_else(|| invalid(item))?;
+                match key {
+                    "result" => exchange.result = value.parse().map_err(|_| invalid(key))?,
+                    "arg" => exchange.arg = Some(value.parse().map_err(|_| invalid(key))?),
+                    "in" => {
+                        let (before, after) = parse_data(value).ok_or_else(|| invalid(key))?;
+                        exchange.before = before;
+                        exchange.after = after;
+                    }
+                    "buf" => {
+                        let data = parse_data(value).ok_or_else(|| invalid(key))?;
+                        exchange.buffers.push(data);
+                    }
+                    "pd" => exchange.process_data = Some(unhex(value).ok_or_else(|| invalid(key))?),
+                    _ => return Err(invalid(key)),
+                }
+            }
+            exchanges.push(exchange);
+        }
+        Ok(Self { exchanges })
+    }
+}
+
+fn write_data<W: Write>(writer: &mut W, key: &str, before: &[u8], after: &[u8]) -> io::Result<()> {
+    if before == after {
+        write!(writer, " {}={}", key, hex(before))
+    } else {
+        write!(writer, " {}={}:{}", key, hex(before), hex(after))
+    }
+}
+
+fn parse_data(value: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    match value.split_once(':') {
+        Some((before, after)) => Some((unhex(before)?, unhex(after)?)),
+        None => unhex(value).map(|data| (data.clone(), data)),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len() * 2);
+    for b in data {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| match c {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(c).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The first difference between a replayed request and the session.
+#[derive(Debug, Clone, Error)]
+#[error("replay diverged at request #{index}: {details}")]
+pub struct Divergence {
+    /// Index of the request within the session.
+    pub index: usize,
+    pub details: String,
+}
+
+/// Serves a recorded `Session` to the `ReplayBackend`s created from it.
+#[derive(Clone)]
+pub struct Replay {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+struct ReplayState {
+    session: Session,
+    next: usize,
+    divergence: Option<Divergence>,
+    memory: Option<Arc<SharedMemory>>,
+}
+
+impl Replay {
+    pub fn new(session: Session) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ReplayState {
+                session,
+                next: 0,
+                divergence: None,
+                memory: None,
+            })),
+        }
+    }
+
+    pub fn backend(&self) -> ReplayBackend {
+        ReplayBackend {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Number of recorded requests that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        let state = lock(&self.state);
+        state.session.len() - state.next
+    }
+
+    pub fn divergence(&self) -> Option<Divergence> {
+        lock(&self.state).divergence.clone()
+    }
+}
+
+/// A backend executing requests by replaying a recorded session.
+pub struct ReplayBackend {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl Backend for ReplayBackend {
+    unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        lock(&self.state).replay(request, arg)
+    }
+
+    fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>> {
+        let memory = SharedMemory::new(Box::new(vec![0; size]));
+        let handle = memory.handle();
+        lock(&self.state).memory = Some(memory);
+        Ok(handle)
+    }
+}
+
+impl ReplayState {
+    unsafe fn replay(&mut self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        if self.divergence.is_none() {
+            if let Err(details) = self.check(request, arg) {
+                self.divergence = Some(Divergence {
+                    index: self.next,
+                    details,
+                });
+            }
+        }
+        if let Some(divergence) = &self.divergence {
+            return Err(io::Error::other(divergence.clone()));
+        }
+
+        let exchange = &self.session.exchanges[self.next];
+        self.next += 1;
+        let layout = Layout::get(request, arg);
+        layout.write_changed(arg, &exchange.before, &exchange.after);
+        for ((ptr, len), (before, after)) in layout.buffers(arg).into_iter().zip(&exchange.buffers)
+        {
+            if before != after {
+                let target = slice::from_raw_parts_mut(ptr as *mut u8, len);
+                let len = len.min(after.len());
+                target[..len].copy_from_slice(&after[..len]);
+            }
+        }
+        if let (Some(memory), Some(data)) = (&self.memory, &exchange.process_data) {
+            if request == ec::request::RECEIVE.raw() {
+                let target = memory.data();
+                let len = target.len().min(data.len());
+                target[..len].copy_from_slice(&data[..len]);
+            }
+        }
+        if request == ec::request::DEACTIVATE.raw() && exchange.result >= 0 {
+            self.memory = None;
+        }
+        if exchange.result < 0 {
+            Err(io::Error::from_raw_os_error(-exchange.result))
+        } else {
+            Ok(exchange.result)
+        }
+    }
+
+    /// Compare the request with the next one of the session.
+    unsafe fn check(&self, request: ec::RawRequest, arg: c_ulong) -> Result<(), String> {
+        let exchange = self
+            .session
+            .exchanges
+            .get(self.next)
+            .ok_or_else(|| format!("expected end of session, got {}", request.name))?;
+        if exchange.request != request.name {
+            return Err(format!(
+                "expected {}, got {}",
+                exchange.request, request.name
+            ));
+        }
+        let layout = Layout::get(request, arg);
+        let value = if layout.size == 0 { Some(arg) } else { None };
+        if exchange.arg != value {
+            return Err(format!(
+                "{}: argument is {:?}, recorded {:?}",
+                request.name, value, exchange.arg
+            ));
+        }
+        let mut diffs = layout.diff(&exchange.before, &layout.read(arg));
+        for (i, ((ptr, len), (before, _))) in layout
+            .buffers(arg)
+            .into_iter()
+            .zip(&exchange.buffers)
+            .enumerate()
+        {
+            let data = slice::from_raw_parts(ptr, len);
+            if let Some(diff) = diff_bytes(before, data) {
+                diffs.push(format!("buffer {} {}", i, diff));
+            }
+        }
+        if request == ec::request::SEND.raw() {
+            if let (Some(memory), Some(data)) = (&self.memory, &exchange.process_data) {
+                if let Some(diff) = diff_bytes(data, memory.data()) {
+                    diffs.push(format!("process data {}", diff));
+                }
+            }
+        }
+        if diffs.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{}: {}", request.name, diffs.join(", ")))
+        }
+    }
+}
+
+/// Describe the differing range of `actual` compared to `recorded`.
+fn diff_bytes(recorded: &[u8], actual: &[u8]) -> Option<String> {
+    if recorded.len() != actual.len() {
+        return Some(format!(
+            "has {} bytes, recorded {}",
+            actual.len(),
+            recorded.len()
+        ));
+    }
+    let first = recorded.iter().zip(actual).position(|(r, a)| r != a)?;
+    let last = recorded.len()
+        - recorded
+            .iter()
+            .zip(actual)
+            .rev()
+            .position(|(r, a)| r != a)?;
+    Some(format!(
+        "differs at bytes {}..{}: {}, recorded {}",
+        first,
+        last,
+        hex(&actual[first..last]),
+        hex(&recorded[first..last])
+    ))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The fields of a request's argument struct.
+#[derive(Debug, Default)]
+struct Layout {
+    size: usize,
+    /// Names and byte ranges of the value fields.
+    fields: Vec<(&'static str, Range<usize>)>,
+    /// Offsets of the pointer fields with the size of the buffers.
+    pointers: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    /// # Safety
+    ///
+    /// `arg` must be a valid argument of `request`.
+    unsafe fn get(request: ec::RawRequest, arg: c_ulong) -> Self {
+        macro_rules! requests {
+            ($($name:ident)* $(#[$meta:meta] { $($cfg_name:ident)* })*) => {
+                $(
+                    if request == ec::request::$name.raw() {
+                        return Layout::of(ec::request::$name, arg);
+                    }
+                )*
+                $(
+                    #[$meta]
+                    {
+                        $(
+                            if request == ec::request::$cfg_name.raw() {
+                                return Layout::of(ec::request::$cfg_name, arg);
+                            }
+                        )*
+                    }
+                )*
+            };
+        }
+
+        if request == ec::request::SEND.raw() {
+            // The number of sent bytes is returned via the argument.
+            return Layout::opaque(size_of::<usize>());
+        }
+        // Requests taking a pointer to a struct; others are passed by value.
+        requests! {
+            MODULE MASTER SLAVE SLAVE_SYNC SLAVE_SYNC_PDO SLAVE_SYNC_PDO_ENTRY DOMAIN DOMAIN_FMMU
+            DOMAIN_DATA SLAVE_STATE SLAVE_SDO SLAVE_SDO_ENTRY SLAVE_SDO_UPLOAD SLAVE_SDO_DOWNLOAD
+            SLAVE_SII_READ SLAVE_SII_WRITE SLAVE_REG_READ SLAVE_REG_WRITE SLAVE_FOE_READ
+            SLAVE_FOE_WRITE SLAVE_SOE_READ SLAVE_SOE_WRITE CONFIG CONFIG_PDO CONFIG_PDO_ENTRY
+            CONFIG_SDO CONFIG_IDN EOE_HANDLER CREATE_SLAVE_CONFIG ACTIVATE
+            MASTER_STATE MASTER_LINK_STATE APP_TIME SYNC_REF_TO REF_CLOCK_TIME SYNC_MON_PROCESS
+            SC_SYNC SC_WATCHDOG SC_ADD_PDO SC_CLEAR_PDOS SC_ADD_ENTRY SC_CLEAR_ENTRIES
+            SC_REG_PDO_ENTRY SC_REG_PDO_POS SC_DC SC_SDO SC_EMERG_SIZE SC_EMERG_POP SC_EMERG_CLEAR
+            SC_EMERG_OVERRUNS SC_SDO_REQUEST SC_REG_REQUEST SC_VOE SC_STATE SC_IDN DOMAIN_STATE
+            SDO_REQUEST_INDEX SDO_REQUEST_TIMEOUT SDO_REQUEST_STATE SDO_REQUEST_READ
+            SDO_REQUEST_WRITE SDO_REQUEST_DATA REG_REQUEST_DATA REG_REQUEST_STATE
+            REG_REQUEST_WRITE REG_REQUEST_READ VOE_SEND_HEADER VOE_REC_HEADER VOE_READ
+            VOE_READ_NOSYNC VOE_WRITE VOE_EXEC VOE_DATA SET_SEND_INTERVAL
+            #[cfg(feature = "sncn")]
+            {
+                SLAVE_EOE_IP_PARAM SLAVE_DICT_UPLOAD SC_OVERLAPPING_IO SLAVE_REBOOT
+                SLAVE_REG_READWRITE REG_REQUEST_READWRITE SETUP_DOMAIN_MEMORY SC_FOE_REQUEST
+                FOE_REQUEST_FILE FOE_REQUEST_TIMEOUT FOE_REQUEST_STATE FOE_REQUEST_READ
+                FOE_REQUEST_WRITE FOE_REQUEST_DATA RT_SLAVE_REQUESTS EOE_ADDIF EOE_DELIF PCAP_DATA
+                MBOX_GATEWAY
+            }
+        }
+        match request.arg_size() {
+            0 => Layout::default(),
+            size => Layout::opaque(size),
+        }
+    }
+
+    /// A struct of unknown layout, consisting of a single value.
+    fn opaque(size: usize) -> Self {
+        Layout {
+            size,
+            fields: vec![("value", 0..size)],
+            pointers: vec![],
+        }
+    }
+
+    unsafe fn of<P: ArgPointer>(_: ec::Request<P>, arg: c_ulong) -> Self {
+        let mut layout = Layout {
+            size: size_of::<P::Target>(),
+            ..Layout::default()
+        };
+        (*(arg as *const P::Target)).fields(arg as usize, &mut layout);
+        layout
+    }
+
+    fn value(&mut self, name: &'static str, offset: usize, size: usize) {
+        self.fields.push((name, offset..offset + size));
+    }
+
+    /// The value fields of the struct at `arg`, with zeros in between.
+    unsafe fn read(&self, arg: c_ulong) -> Vec<u8> {
+        let mut data = vec![0; self.size];
+        for (_, range) in &self.fields {
+            let src = (arg as *const u8).add(range.start);
+            ptr::copy_nonoverlapping(src, data[range.clone()].as_mut_ptr(), range.len());
+        }
+        data
+    }
+
+    /// Write the value fields that changed from `before` to `after`.
+    unsafe fn write_changed(&self, arg: c_ulong, before: &[u8], after: &[u8]) {
+        for (_, range) in &self.fields {
+            if let (Some(before), Some(after)) =
+                (before.get(range.clone()), after.get(range.clone()))
+            {
+                if before != after {
+                    let dst = (arg as *mut u8).add(range.start);
+                    ptr::copy_nonoverlapping(after.as_ptr(), dst, range.len());
+                }
+            }
+        }
+    }
+
+    /// Address and size of the buffers pointed to by the struct at `arg`;
+    /// null pointers yield empty buffers.
+    unsafe fn buffers(&self, arg: c_ulong) -> Vec<(*const u8, usize)> {
+        self.pointers
+            .iter()
+            .map(|&(offset, len)| {
+                let ptr = ptr::read_unaligned((arg as usize + offset) as *const *const u8);
+                if ptr.is_null() {
+                    (ptr::NonNull::dangling().as_ptr() as *const u8, 0)
+                } else {
+                    (ptr, len)
+                }
+            })
+            .collect()
+    }
+
+    fn diff(&self, recorded: &[u8], actual: &[u8]) -> Vec<String> {
+        if recorded.len() != actual.len() {
+            return vec![format!(
+                "argument has {} bytes, recorded {}",
+                actual.len(),
+                recorded.len()
+            )];
+        }
+        self.fields
+            .iter()
+            .filter(|(_, range)| recorded[range.clone()] != actual[range.clone()])
+            .map(|(name, range)| {
+                format!(
+                    "{} is {}, recorded {}",
+                    name,
+                    hex(&actual[range.clone()]),
+                    hex(&recorded[range.clone()])
+                )
+            })
+            .collect()
+    }
+}
+
+/// The typed pointer argument of a request.
+trait ArgPointer {
+    type Target: Fields;
+}
+
+impl<T: Fields> ArgPointer for *mut T {
+    type Target = T;
+}
+
+impl<T: Fields> ArgPointer for *const T {
+    type Target = T;
+}
+
+/// Argument structs whose fields can be described by a `Layout`.
+trait Fields {
+    /// Add the fields to `layout`, with offsets relative to `base`.
+    fn fields(&self, base: usize, layout: &mut Layout);
+}
+
+macro_rules! fields {
+    ($($(#[$meta:meta])* $ty:ident $(|$s:ident|)? { $($body:tt)* })*) => {$(
+        $(#[$meta])*
+        impl Fields for ec::$ty {
+            fn fields(&self, base: usize, layout: &mut Layout) {
+                fields!(@bind self $(, $s)?; base, layout; $($body)*);
+            }
+        }
+    )*};
+    (@bind $this:ident, $s:ident; $($rest:tt)*) => {
+        let $s = $this;
+        fields!(@ $s, $($rest)*);
+    };
+    (@bind $this:ident; $($rest:tt)*) => {
+        let this = $this;
+        fields!(@ this, $($rest)*);
+    };
+    (@ $s:ident, $base:ident, $layout:ident; $(,)?) => {};
+    (@ $s:ident, $base:ident, $layout:ident; * $f:ident = $len:expr $(, $($rest:tt)*)?) => {
+        $layout.pointers.push((&$s.$f as *const _ as usize - $base, $len));
+        fields!(@ $s, $base, $layout; $($($rest)*)?);
+    };
+    (@ $s:ident, $base:ident, $layout:ident; $f:ident[] $(, $($rest:tt)*)?) => {
+        for item in &$s.$f {
+            item.fields($base, $layout);
+        }
+        fields!(@ $s, $base, $layout; $($($rest)*)?);
+    };
+    (@ $s:ident, $base:ident, $layout:ident; $f:ident $(, $($rest:tt)*)?) => {
+        $layout.value(
+            stringify!($f),
+            &$s.$f as *const _ as usize - $base,
+            mem::size_of_val(&$s.$f),
+        );
+        fields!(@ $s, $base, $layout; $($($rest)*)?);
+    };
+}
+
+macro_rules! value_fields {
+    ($($ty:ty),*) => {$(
+        impl Fields for $ty {
+            fn fields(&self, base: usize, layout: &mut Layout) {
+                layout.value("value", self as *const _ as usize - base, size_of::<$ty>());
+            }
+        }
+    )*};
+}
+
+value_fields!(u32, u64, usize);
+
+fields! {
+    ec_ioctl_add_pdo_entry_t {
+        config_index, pdo_index, entry_index, entry_subindex, entry_bit_length,
+    }
+    ec_ioctl_config_idn_t { config_index, idn_pos, drive_no, idn, state, size, data }
+    ec_ioctl_config_pdo_entry_t {
+        config_index, sync_index, pdo_pos, entry_pos, index, subindex, bit_length, name,
+    }
+    ec_ioctl_config_pdo_t { config_index, sync_index, pdo_pos, index, entry_count, name }
+    ec_ioctl_config_sdo_t { config_index, sdo_pos, index, subindex, size, data, complete_access }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_config_t {
+        config_index, alias, position, vendor_id, product_code, syncs[], watchdog_divider,
+        watchdog_intervals, sdo_count, idn_count, slave_position, dc_assign_activate, dc_sync,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_config_t {
+        config_index, alias, position, vendor_id, product_code, syncs[], watchdog_divider,
+        watchdog_intervals, sdo_count, idn_count, slave_position, dc_assign_activate, dc_sync,
+        allow_overlapping_pdos,
+    }
+    ec_ioctl_config_t__bindgen_ty_1 { dir, watchdog_mode, pdo_count, config_this }
+    ec_ioctl_domain_data_t |s| { domain_index, data_size, *target = s.data_size as usize }
+    ec_ioctl_domain_fmmu_t {
+        domain_index, fmmu_index, slave_config_alias, slave_config_position, sync_index, dir,
+        logical_address, data_size,
+    }
+    ec_ioctl_domain_state_t { domain_index, *state = size_of::<ec::ec_domain_state_t>() }
+    ec_ioctl_domain_t {
+        index, data_size, logical_base_address, working_counter, expected_working_counter,
+        fmmu_count,
+    }
+    ec_ioctl_eoe_handler_t {
+        eoe_index, name, slave_position, open, rx_bytes, rx_rate, tx_bytes, tx_rate,
+        tx_queued_frames, tx_queue_size,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_eoe_if_t { alias, position }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_foe_request_t |s| {
+        config_index, request_index, password, size, progress, *data = s.size as usize, timeout,
+        state, result, error_code, file_name,
+    }
+    ec_ioctl_link_state_t { dev_idx, *state = size_of::<ec::ec_master_link_state_t>() }
+    ec_ioctl_master_activate_t { *process_data = 0, process_data_size }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_master_t {
+        slave_count, config_count, domain_count, eoe_handler_count, phase, active, scan_busy,
+        devices[], num_devices, tx_count, rx_count, tx_bytes, rx_bytes, tx_frame_rates,
+        rx_frame_rates, tx_byte_rates, rx_byte_rates, loss_rates, app_time, dc_ref_time, ref_clock,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_master_t {
+        slave_count, config_count, domain_count, eoe_handler_count, phase, active, scan_busy,
+        devices[], num_devices, tx_count, rx_count, tx_bytes, rx_bytes, tx_frame_rates,
+        rx_frame_rates, tx_byte_rates, rx_byte_rates, loss_rates, app_time, dc_ref_time, ref_clock,
+        pcap_size,
+    }
+    ec_ioctl_master_t_ec_ioctl_device {
+        address, attached, link_state, tx_count, rx_count, tx_bytes, rx_bytes, tx_errors,
+        tx_frame_rates, rx_frame_rates, tx_byte_rates, rx_byte_rates,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_mbox_gateway_t |s| { data_size, buff_size, *data = s.buff_size as usize }
+    ec_ioctl_module_t { ioctl_version_magic, master_count }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_pcap_data_t |s| { data_size, reset_data, *target = s.data_size as usize }
+    ec_ioctl_reg_pdo_entry_t {
+        config_index, entry_index, entry_subindex, domain_index, bit_position,
+    }
+    ec_ioctl_reg_pdo_pos_t {
+        config_index, sync_index, pdo_pos, entry_pos, domain_index, bit_position,
+    }
+    ec_ioctl_reg_request_t |s| {
+        config_index, mem_size, request_index,
+        *data = s.mem_size.max(s.transfer_size) as usize, state, new_data, address, transfer_size,
+    }
+    ec_ioctl_sc_emerg_t { config_index, size, *target = EMERGENCY_SIZE, overruns }
+    ec_ioctl_sc_idn_t |s| { config_index, drive_no, idn, al_state, *data = s.size as usize, size }
+    ec_ioctl_sc_sdo_t |s| {
+        config_index, index, subindex, *data = s.size as usize, size, complete_access,
+    }
+    ec_ioctl_sc_state_t { config_index, *state = size_of::<ec::ec_slave_config_state_t>() }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_sdo_request_t |s| {
+        config_index, request_index, sdo_index, sdo_subindex, size, *data = s.size as usize,
+        timeout, state,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_sdo_request_t |s| {
+        config_index, request_index, sdo_index, sdo_subindex, complete_access, size,
+        *data = s.size as usize, timeout, state,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_dict_upload_t { slave_position }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_eoe_ip_t {
+        slave_position, mac_address_included, ip_address_included, subnet_mask_included,
+        gateway_included, dns_included, name_included, mac_address, ip_address, subnet_mask,
+        gateway, dns, name, result,
+    }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_slave_foe_t |s| {
+        slave_position, offset, buffer_size, *buffer = s.buffer_size as usize, data_size, result,
+        error_code, file_name,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_foe_t |s| {
+        password, slave_position, offset, buffer_size, *buffer = s.buffer_size as usize, data_size,
+        result, error_code, file_name,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_reboot_t { slave_position, broadcast }
+    ec_ioctl_slave_reg_t |s| { slave_position, emergency, address, size, *data = s.size as usize }
+    ec_ioctl_slave_sdo_download_t |s| {
+        slave_position, sdo_index, sdo_entry_subindex, complete_access, data_size,
+        *data = s.data_size as usize, abort_code,
+    }
+    ec_ioctl_slave_sdo_entry_t {
+        slave_position, sdo_spec, sdo_entry_subindex, data_type, bit_length, read_access,
+        write_access, description,
+    }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_slave_sdo_t { slave_position, sdo_position, sdo_index, max_subindex, name }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_sdo_t {
+        slave_position, sdo_position, sdo_index, max_subindex, object_code, name,
+    }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_slave_sdo_upload_t |s| {
+        slave_position, sdo_index, sdo_entry_subindex, target_size,
+        *target = s.target_size as usize, data_size, abort_code,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_sdo_upload_t |s| {
+        slave_position, sdo_index, sdo_entry_subindex, complete_access, target_size,
+        *target = s.target_size as usize, data_size, abort_code,
+    }
+    ec_ioctl_slave_sii_t |s| { slave_position, offset, nwords, *words = s.nwords as usize * 2 }
+    ec_ioctl_slave_soe_read_t |s| {
+        slave_position, drive_no, idn, mem_size, *data = s.mem_size as usize, data_size, error_code,
+    }
+    ec_ioctl_slave_soe_write_t |s| {
+        slave_position, drive_no, idn, data_size, *data = s.data_size as usize, error_code,
+    }
+    ec_ioctl_slave_state_t { slave_position, al_state }
+    ec_ioctl_slave_sync_pdo_entry_t {
+        slave_position, sync_index, pdo_pos, entry_pos, index, subindex, bit_length, name,
+    }
+    ec_ioctl_slave_sync_pdo_t { slave_position, sync_index, pdo_pos, index, entry_count, name }
+    ec_ioctl_slave_sync_t {
+        slave_position, sync_index, physical_start_address, default_size, control_register, enable,
+        pdo_count,
+    }
+    #[cfg(not(feature = "sncn"))]
+    ec_ioctl_slave_t {
+        position, device_index, vendor_id, product_code, revision_number, serial_number, alias,
+        boot_rx_mailbox_offset, boot_rx_mailbox_size, boot_tx_mailbox_offset, boot_tx_mailbox_size,
+        std_rx_mailbox_offset, std_rx_mailbox_size, std_tx_mailbox_offset, std_tx_mailbox_size,
+        mailbox_protocols, has_general_category, coe_details, general_flags, current_on_ebus,
+        ports[], fmmu_bit, dc_supported, dc_range, has_dc_system_time, transmission_delay, al_state,
+        error_flag, sync_count, sdo_count, sii_nwords, group, image, order, name,
+    }
+    #[cfg(feature = "sncn")]
+    ec_ioctl_slave_t {
+        position, device_index, vendor_id, product_code, revision_number, serial_number, alias,
+        boot_rx_mailbox_offset, boot_rx_mailbox_size, boot_tx_mailbox_offset, boot_tx_mailbox_size,
+        std_rx_mailbox_offset, std_rx_mailbox_size, std_tx_mailbox_offset, std_tx_mailbox_size,
+        mailbox_protocols, has_general_category, coe_details, general_flags, current_on_ebus,
+        ports[], upstream_port, fmmu_bit, dc_supported, dc_range, has_dc_system_time,
+        transmission_delay, al_state, error_flag, scan_required, ready, sync_count, sdo_count,
+        sii_nwords, group, image, order, name,
+    }
+    ec_ioctl_slave_t__bindgen_ty_1 { desc, link, receive_time, next_slave, delay_to_next_dc }
+    ec_ioctl_voe_t |s| {
+        config_index, voe_index, *vendor_id = 4, *vendor_type = 2, size, *data = s.size as usize,
+        state,
+    }
+    ec_master_state_t { slaves_responding, _bitfield_1 }
+}
+
+#[test]
+fn test_record_replay() {
+    use crate::{sim, testing::run_cycles, Master, SdoIdx, SlavePos, SmIdx};
+
+    let simulation = sim::Simulation::new(vec![sim::test_slave()]);
+    simulation.set_sm_data(SlavePos::from(0), SmIdx::from(3), &[0xCD, 0xAB]);
+    let recording = Recording::new();
+    let master = Master::with_backend(recording.record(simulation.backend())).unwrap();
+    let inputs = run_cycles(master, 42).unwrap();
+    assert_eq!(inputs[..2], [0x02, 0x01]);
+    assert_eq!(inputs[6..], [0xCD, 0xAB]);
+    drop(simulation);
+
+    let mut file = vec![];
+    recording.session().save(&mut file).unwrap();
+    let session = Session::load(&file[..]).unwrap();
+    assert_eq!(session, recording.session());
+    assert_eq!(session.requests().next(), Some("MODULE"));
+
+    let replay = Replay::new(session.clone());
+    let master = Master::with_backend(replay.backend()).unwrap();
+    assert_eq!(run_cycles(master, 42).unwrap(), inputs);
+    assert_eq!(replay.remaining(), 0);
+    assert!(replay.divergence().is_none());
+
+    // Different outputs.
+    let replay = Replay::new(session.clone());
+    let master = Master::with_backend(replay.backend()).unwrap();
+    let err = run_cycles(master, 7).unwrap_err().to_string();
+    assert!(
+        err.ends_with("SEND: process data differs at bytes 0..1: 07, recorded 2a"),
+        "{}",
+        err
+    );
+    let divergence = replay.divergence().unwrap();
+    assert_eq!(session.requests().nth(divergence.index), Some("SEND"));
+    assert!(Master::with_backend(replay.backend()).is_err());
+
+    // Different request data.
+    let replay = Replay::new(session.clone());
+    let mut master = Master::with_backend(replay.backend()).unwrap();
+    let err = master
+        .sdo_download(
+            SlavePos::from(1),
+            SdoIdx::new(0x8000, 1),
+            false,
+            &0x0103_u16,
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string().ends_with(
+            "SLAVE_SDO_DOWNLOAD: slave_position is 0100, recorded 0000, \
+             buffer 0 differs at bytes 0..1: 03, recorded 02"
+        ),
+        "{}",
+        err
+    );
+
+    // Different requests.
+    let replay = Replay::new(session);
+    let master = Master::with_backend(replay.backend()).unwrap();
+    let err = master.get_slave_info(SlavePos::from(0)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "SLAVE on slave 0: replay diverged at request #1: expected SLAVE_SDO_DOWNLOAD, got SLAVE"
+    );
+}
+
+#[test]
+fn test_record_select_reference_clock() {
+    use crate::{sim, Master, SlaveAddr, SlaveId};
+
+    let select = |master: Master| -> crate::Result<()> {
+        let mut master = master.reserve()?;
+        let config = master.configure_slave(SlaveAddr::ByPos(0), SlaveId::new(0x2, 0x1234))?;
+        config.select_as_reference_clock(&master)
+    };
+    let simulation = sim::Simulation::new(vec![sim::test_slave()]);
+    let recording = Recording::new();
+    select(Master::with_backend(recording.record(simulation.backend())).unwrap()).unwrap();
+
+    let mut file = vec![];
+    recording.session().save(&mut file).unwrap();
+    let replay = Replay::new(Session::load(&file[..]).unwrap());
+    select(Master::with_backend(replay.backend()).unwrap()).unwrap();
+    assert_eq!(replay.remaining(), 0);
+}
@@ -0,0 +1,411 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Typed decoders for well-known ESC registers.
+//!
+//! Use `Master::read_register` to read and decode a register in one step,
+//! or decode the raw data of `Master::reg_read` or a `RegRequest`.
+
+use std::{fmt, time::Duration};
+
+/// A decodable ESC register (or register block).
+pub trait Register: Sized {
+    /// Address of the first register byte.
+    const ADDRESS: u16;
+    /// Number of bytes to read.
+    const SIZE: usize;
+
+    /// Decode the register contents.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is shorter than `SIZE` bytes.
+    fn decode(data: &[u8]) -> Self;
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from(u32_at(data, offset)) | u64::from(u32_at(data, offset + 4)) << 32
+}
+
+const fn bit(value: u16, bit: u8) -> bool {
+    value & (1 << bit) != 0
+}
+
+/// Link status of a single port as reported by the DL status register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DlPortStatus {
+    pub link: bool,
+    pub loop_closed: bool,
+    pub communication: bool,
+}
+
+/// DL status register (0x0110).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DlStatus {
+    pub pdi_operational: bool,
+    /// `false` if the PDI watchdog has expired.
+    pub pdi_watchdog_ok: bool,
+    pub enhanced_link_detection: bool,
+    pub ports: [DlPortStatus; 4],
+}
+
+impl Register for DlStatus {
+    const ADDRESS: u16 = 0x0110;
+    const SIZE: usize = 2;
+
+    fn decode(data: &[u8]) -> Self {
+        let v = u16_at(data, 0);
+        let mut ports = [DlPortStatus::default(); 4];
+        for (i, port) in ports.iter_mut().enumerate() {
+            let i = i as u8;
+            port.link = bit(v, 4 + i);
+            port.loop_closed = bit(v, 8 + 2 * i);
+            port.communication = bit(v, 9 + 2 * i);
+        }
+        DlStatus {
+            pdi_operational: bit(v, 0),
+            pdi_watchdog_ok: bit(v, 1),
+            enhanced_link_detection: bit(v, 2),
+            ports,
+        }
+    }
+}
+
+/// AL control register (0x0120).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlControl {
+    /// Raw requested state (see `ethercat::AlState`).
+    pub state: u8,
+    pub error_ack: bool,
+    pub device_identification: bool,
+}
+
+impl Register for AlControl {
+    const ADDRESS: u16 = 0x0120;
+    const SIZE: usize = 2;
+
+    fn decode(data: &[u8]) -> Self {
+        let v = u16_at(data, 0);
+        AlControl {
+            state: (v & 0x0F) as u8,
+            error_ack: bit(v, 4),
+            device_identification: bit(v, 5),
+        }
+    }
+}
+
+/// AL status register (0x0130).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlStatus {
+    /// Raw current state (see `ethercat::AlState`).
+    pub state: u8,
+    pub error: bool,
+    pub device_identification_loaded: bool,
+}
+
+impl Register for AlStatus {
+    const ADDRESS: u16 = 0x0130;
+    const SIZE: usize = 2;
+
+    fn decode(data: &[u8]) -> Self {
+        let v = u16_at(data, 0);
+        AlStatus {
+            state: (v & 0x0F) as u8,
+            error: bit(v, 4),
+            device_identification_loaded: bit(v, 5),
+        }
+    }
+}
+
+/// AL status code register (0x0134).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlStatusCode(pub u16);
+
+impl AlStatusCode {
+    /// Text description of well-known codes.
+    pub fn description(self) -> Option<&'static str> {
+        Some(match self.0 {
+            0x0000 => "No error",
+            0x0001 => "Unspecified error",
+            0x0002 => "No memory",
+            0x0011 => "Invalid requested state change",
+            0x0012 => "Unknown requested state",
+            0x0013 => "Bootstrap not supported",
+            0x0014 => "No valid firmware",
+            0x0015 => "Invalid mailbox configuration (BOOT)",
+            0x0016 => "Invalid mailbox configuration (PREOP)",
+            0x0017 => "Invalid sync manager configuration",
+            0x0018 => "No valid inputs available",
+            0x0019 => "No valid outputs",
+            0x001A => "Synchronization error",
+            0x001B => "Sync manager watchdog",
+            0x001C => "Invalid sync manager types",
+            0x001D => "Invalid output configuration",
+            0x001E => "Invalid input configuration",
+            0x001F => "Invalid watchdog configuration",
+            0x0020 => "Slave needs cold start",
+            0x0021 => "Slave needs INIT",
+            0x0022 => "Slave needs PREOP",
+            0x0023 => "Slave needs SAFEOP",
+            0x0024 => "Invalid input mapping",
+            0x0025 => "Invalid output mapping",
+            0x0026 => "Inconsistent settings",
+            0x0027 => "Freerun not supported",
+            0x0028 => "Synchronization not supported",
+            0x0029 => "Freerun needs 3 buffer mode",
+            0x002A => "Background watchdog",
+            0x002B => "No valid inputs and outputs",
+            0x002C => "Fatal sync error",
+            0x002D => "No sync error",
+            0x0030 => "Invalid DC SYNC configuration",
+            0x0031 => "Invalid DC latch configuration",
+            0x0032 => "PLL error",
+            0x0033 => "DC sync IO error",
+            0x0034 => "DC sync timeout error",
+            0x0035 => "DC invalid sync cycle time",
+            0x0036 => "DC SYNC0 cycle time",
+            0x0037 => "DC SYNC1 cycle time",
+            0x0041 => "Mailbox AoE",
+            0x0042 => "Mailbox EoE",
+            0x0043 => "Mailbox CoE",
+            0x0044 => "Mailbox FoE",
+            0x0045 => "Mailbox SoE",
+            0x004F => "Mailbox VoE",
+            0x0050 => "EEPROM no access",
+            0x0051 => "EEPROM error",
+            0x0060 => "Slave restarted locally",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for AlStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.description() {
+            Some(d) => write!(f, "0x{:04X} ({})", self.0, d),
+            None => write!(f, "0x{:04X}", self.0),
+        }
+    }
+}
+
+impl Register for AlStatusCode {
+    const ADDRESS: u16 = 0x0134;
+    const SIZE: usize = 2;
+
+    fn decode(data: &[u8]) -> Self {
+        AlStatusCode(u16_at(data, 0))
+    }
+}
+
+/// Error counters of a single port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PortErrorCounters {
+    pub invalid_frames: u8,
+    pub rx_errors: u8,
+    pub forwarded_rx_errors: u8,
+    pub lost_links: u8,
+}
+
+/// RX error counter registers (0x0300 - 0x0313).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxErrorCounters {
+    pub ports: [PortErrorCounters; 4],
+    pub processing_unit_errors: u8,
+    pub pdi_errors: u8,
+}
+
+impl Register for RxErrorCounters {
+    const ADDRESS: u16 = 0x0300;
+    const SIZE: usize = 0x14;
+
+    fn decode(data: &[u8]) -> Self {
+        let mut ports = [PortErrorCounters::default(); 4];
+        for (i, port) in ports.iter_mut().enumerate() {
+            port.invalid_frames = data[2 * i];
+            port.rx_errors = data[2 * i + 1];
+            port.forwarded_rx_errors = data[0x08 + i];
+            port.lost_links = data[0x10 + i];
+        }
+        RxErrorCounters {
+            ports,
+            processing_unit_errors: data[0x0C],
+            pdi_errors: data[0x0D],
+        }
+    }
+}
+
+/// Watchdog registers (0x0400 - 0x0443).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchdog {
+    pub divider: u16,
+    pub pdi_time: u16,
+    pub sm_time: u16,
+    /// `false` if the process data watchdog has expired.
+    pub sm_watchdog_ok: bool,
+    pub sm_counter: u8,
+    pub pdi_counter: u8,
+}
+
+impl Watchdog {
+    /// Duration of a watchdog increment (the divider is based on a 25 MHz clock).
+    pub fn increment(&self) -> Duration {
+        Duration::from_nanos((u64::from(self.divider) + 2) * 40)
+    }
+
+    pub fn pdi_timeout(&self) -> Duration {
+        self.increment() * u32::from(self.pdi_time)
+    }
+
+    pub fn sm_timeout(&self) -> Duration {
+        self.increment() * u32::from(self.sm_time)
+    }
+}
+
+impl Register for Watchdog {
+    const ADDRESS: u16 = 0x0400;
+    const SIZE: usize = 0x44;
+
+    fn decode(data: &[u8]) -> Self {
+        Watchdog {
+            divider: u16_at(data, 0x00),
+            pdi_time: u16_at(data, 0x10),
+            sm_time: u16_at(data, 0x20),
+            sm_watchdog_ok: data[0x40] & 0x01 != 0,
+            sm_counter: data[0x42],
+            pdi_counter: data[0x43],
+        }
+    }
+}
+
+/// DC system time registers (0x0910 - 0x092F).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DcSystemTime {
+    /// Local copy of the system time in nanoseconds.
+    pub system_time: u64,
+    /// Receive time of the ECAT processing unit in nanoseconds.
+    pub receive_time: u64,
+    /// Offset between the local time and the system time in nanoseconds.
+    pub offset: u64,
+    /// Delay between the reference clock and this slave in nanoseconds.
+    pub delay: u32,
+    /// Mean difference between local copy and received system time
+    /// in nanoseconds.
+    pub difference: i32,
+}
+
+impl Register for DcSystemTime {
+    const ADDRESS: u16 = 0x0910;
+    const SIZE: usize = 0x20;
+
+    fn decode(data: &[u8]) -> Self {
+        // The difference is transferred as sign and magnitude.
+        let diff = u32_at(data, 0x1C);
+        let magnitude = (diff & 0x7FFF_FFFF) as i32;
+        DcSystemTime {
+            system_time: u64_at(data, 0x00),
+            receive_time: u64_at(data, 0x08),
+            offset: u64_at(data, 0x10),
+            delay: u32_at(data, 0x18),
+            difference: if diff & 0x8000_0000 != 0 {
+                -magnitude
+            } else {
+                magnitude
+            },
+        }
+    }
+}
+
+#[test]
+fn test_decode_dl_status() {
+    let st = DlStatus::decode(&0x0A13_u16.to_le_bytes());
+    assert!(st.pdi_operational);
+    assert!(st.pdi_watchdog_ok);
+    assert!(!st.enhanced_link_detection);
+    assert_eq!(
+        st.ports[0],
+        DlPortStatus {
+            link: true,
+            loop_closed: false,
+            communication: true,
+        }
+    );
+    assert_eq!(
+        st.ports[1],
+        DlPortStatus {
+            link: false,
+            loop_closed: false,
+            communication: true,
+        }
+    );
+}
+
+#[test]
+fn test_decode_al_registers() {
+    let st = AlStatus::decode(&[0x14, 0x00]);
+    assert_eq!(st.state, 4);
+    assert!(st.error);
+    let ctrl = AlControl::decode(&[0x12, 0x00]);
+    assert_eq!(ctrl.state, 2);
+    assert!(ctrl.error_ack);
+    let code = AlStatusCode::decode(&[0x1B, 0x00]);
+    assert_eq!(code.to_string(), "0x001B (Sync manager watchdog)");
+    assert_eq!(AlStatusCode(0x1234).to_string(), "0x1234");
+}
+
+#[test]
+fn test_decode_rx_error_counters() {
+    let mut data = [0; RxErrorCounters::SIZE];
+    data[0x02] = 1;
+    data[0x03] = 2;
+    data[0x09] = 3;
+    data[0x0D] = 4;
+    data[0x11] = 5;
+    let counters = RxErrorCounters::decode(&data);
+    assert_eq!(
+        counters.ports[1],
+        PortErrorCounters {
+            invalid_frames: 1,
+            rx_errors: 2,
+            forwarded_rx_errors: 3,
+            lost_links: 5,
+        }
+    );
+    assert_eq!(counters.pdi_errors, 4);
+}
+
+#[test]
+fn test_decode_watchdog() {
+    let mut data = [0; Watchdog::SIZE];
+    data[0x00..0x02].copy_from_slice(&2498_u16.to_le_bytes());
+    data[0x20..0x22].copy_from_slice(&1000_u16.to_le_bytes());
+    data[0x40] = 1;
+    let wd = Watchdog::decode(&data);
+    assert_eq!(wd.increment(), Duration::from_micros(100));
+    assert_eq!(wd.sm_timeout(), Duration::from_millis(100));
+    assert!(wd.sm_watchdog_ok);
+}
+
+#[test]
+fn test_decode_dc_system_time() {
+    let mut data = [0; DcSystemTime::SIZE];
+    data[0x00..0x08].copy_from_slice(&0x0123_4567_89AB_CDEF_u64.to_le_bytes());
+    data[0x10..0x18].copy_from_slice(&42_u64.to_le_bytes());
+    data[0x1C..0x20].copy_from_slice(&0x8000_0010_u32.to_le_bytes());
+    let dc = DcSystemTime::decode(&data);
+    assert_eq!(dc.system_time, 0x0123_4567_89AB_CDEF);
+    assert_eq!(dc.offset, 42);
+    assert_eq!(dc.difference, -16);
+}
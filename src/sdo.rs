@@ -0,0 +1,166 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! SDO abort codes (CiA 301 and ETG.1000.6)
+
+use thiserror::Error;
+
+/// Abort code sent by a slave to abort an SDO transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum SdoAbortCode {
+    #[error("Toggle bit not changed")]
+    ToggleBit,
+    #[error("SDO protocol timeout")]
+    Timeout,
+    #[error("Client/server command specifier not valid or unknown")]
+    InvalidCommand,
+    #[error("Invalid block size")]
+    InvalidBlockSize,
+    #[error("Invalid sequence number")]
+    InvalidSequenceNumber,
+    #[error("CRC error")]
+    Crc,
+    #[error("Out of memory")]
+    OutOfMemory,
+    #[error("Unsupported access to an object")]
+    UnsupportedAccess,
+    #[error("Attempt to read a write-only object")]
+    WriteOnly,
+    #[error("Attempt to write a read-only object")]
+    ReadOnly,
+    #[error("Subindex cannot be written, subindex 0 must be 0 for write access")]
+    SubIndexNotWritable,
+    #[error("SDO complete access not supported")]
+    CompleteAccessNotSupported,
+    #[error("Object length exceeds mailbox size")]
+    ObjectTooLarge,
+    #[error("Object mapped to RxPDO, SDO download blocked")]
+    ObjectMapped,
+    #[error("Object does not exist in the object dictionary")]
+    NoObject,
+    #[error("Object cannot be mapped into the PDO")]
+    NotMappable,
+    #[error("Number and length of mapped objects exceed the PDO length")]
+    PdoLengthExceeded,
+    #[error("General parameter incompatibility")]
+    ParameterIncompatibility,
+    #[error("General internal incompatibility in the device")]
+    InternalIncompatibility,
+    #[error("Access failed due to a hardware error")]
+    Hardware,
+    #[error("Data type does not match, length of service parameter does not match")]
+    LengthMismatch,
+    #[error("Data type does not match, length of service parameter too high")]
+    LengthTooHigh,
+    #[error("Data type does not match, length of service parameter too low")]
+    LengthTooLow,
+    #[error("Subindex does not exist")]
+    NoSubIndex,
+    #[error("Value range of parameter exceeded")]
+    ValueRange,
+    #[error("Value of parameter written too high")]
+    ValueTooHigh,
+    #[error("Value of parameter written too low")]
+    ValueTooLow,
+    #[error("Maximum value is less than minimum value")]
+    MaxBelowMin,
+    #[error("Resource not available: SDO connection")]
+    NoConnection,
+    #[error("General error")]
+    General,
+    #[error("Data cannot be transferred or stored to the application")]
+    Transfer,
+    #[error("Data cannot be transferred or stored because of local control")]
+    LocalControl,
+    #[error("Data cannot be transferred or stored because of the present device state")]
+    DeviceState,
+    #[error("Object dictionary dynamic generation failed or no object dictionary present")]
+    NoObjectDictionary,
+    #[error("No data available")]
+    NoData,
+    #[error("Unknown abort code 0x{0:08X}")]
+    Other(u32),
+}
+
+const ABORT_CODES: &[(u32, SdoAbortCode)] = {
+    use SdoAbortCode::*;
+    &[
+        (0x0503_0000, ToggleBit),
+        (0x0504_0000, Timeout),
+        (0x0504_0001, InvalidCommand),
+        (0x0504_0002, InvalidBlockSize),
+        (0x0504_0003, InvalidSequenceNumber),
+        (0x0504_0004, Crc),
+        (0x0504_0005, OutOfMemory),
+        (0x0601_0000, UnsupportedAccess),
+        (0x0601_0001, WriteOnly),
+        (0x0601_0002, ReadOnly),
+        (0x0601_0003, SubIndexNotWritable),
+        (0x0601_0004, CompleteAccessNotSupported),
+        (0x0601_0005, ObjectTooLarge),
+        (0x0601_0006, ObjectMapped),
+        (0x0602_0000, NoObject),
+        (0x0604_0041, NotMappable),
+        (0x0604_0042, PdoLengthExceeded),
+        (0x0604_0043, ParameterIncompatibility),
+        (0x0604_0047, InternalIncompatibility),
+        (0x0606_0000, Hardware),
+        (0x0607_0010, LengthMismatch),
+        (0x0607_0012, LengthTooHigh),
+        (0x0607_0013, LengthTooLow),
+        (0x0609_0011, NoSubIndex),
+        (0x0609_0030, ValueRange),
+        (0x0609_0031, ValueTooHigh),
+        (0x0609_0032, ValueTooLow),
+        (0x0609_0036, MaxBelowMin),
+        (0x060A_0023, NoConnection),
+        (0x0800_0000, General),
+        (0x0800_0020, Transfer),
+        (0x0800_0021, LocalControl),
+        (0x0800_0022, DeviceState),
+        (0x0800_0023, NoObjectDictionary),
+        (0x0800_0024, NoData),
+    ]
+};
+
+impl SdoAbortCode {
+    /// The raw abort code.
+    pub fn code(self) -> u32 {
+        match self {
+            SdoAbortCode::Other(code) => code,
+            _ => ABORT_CODES
+                .iter()
+                .find(|(_, abort)| *abort == self)
+                .map(|(code, _)| *code)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<u32> for SdoAbortCode {
+    fn from(code: u32) -> Self {
+        ABORT_CODES
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, abort)| *abort)
+            .unwrap_or(SdoAbortCode::Other(code))
+    }
+}
+
+impl From<SdoAbortCode> for u32 {
+    fn from(abort: SdoAbortCode) -> Self {
+        abort.code()
+    }
+}
+
+#[test]
+fn test_sdo_abort_code() {
+    assert_eq!(SdoAbortCode::from(0x0602_0000), SdoAbortCode::NoObject);
+    assert_eq!(SdoAbortCode::from(0x0609_0030), SdoAbortCode::ValueRange);
+    assert_eq!(SdoAbortCode::from(0x1234), SdoAbortCode::Other(0x1234));
+    for (code, abort) in ABORT_CODES {
+        assert_eq!(SdoAbortCode::from(*code), *abort);
+        assert_eq!(abort.code(), *code);
+    }
+    assert_eq!(SdoAbortCode::Other(0x1234).code(), 0x1234);
+}
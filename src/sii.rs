@@ -0,0 +1,545 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Parser for the slave information interface (SII) EEPROM contents.
+//!
+//! The SII starts with a fixed header containing the ESC configuration and
+//! the slave identity, followed by a list of categories (see ETG.1000.6 and
+//! ETG.2010).
+
+use crate::types::{CoeDetails, MailboxProtocols, SlaveId, SlaveRev};
+use thiserror::Error;
+
+/// Word offset of the first category.
+const CATEGORY_OFFSET: usize = 0x40;
+
+const CAT_NOP: u16 = 0;
+const CAT_STRINGS: u16 = 10;
+const CAT_GENERAL: u16 = 30;
+const CAT_FMMU: u16 = 40;
+const CAT_SYNC_M: u16 = 41;
+const CAT_TX_PDO: u16 = 50;
+const CAT_RX_PDO: u16 = 51;
+const CAT_DC: u16 = 60;
+const CAT_END: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SiiError {
+    #[error("SII contents are too short ({0} words)")]
+    TooShort(usize),
+    #[error("SII category 0x{category:X} at word 0x{offset:X} exceeds the contents")]
+    CategoryOutOfBounds { category: u16, offset: usize },
+    #[error("Invalid length of SII category 0x{category:X}")]
+    InvalidCategoryLength { category: u16 },
+}
+
+/// The parsed SII contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sii {
+    pub header: SiiHeader,
+    pub strings: Vec<String>,
+    pub general: Option<SiiGeneral>,
+    pub fmmus: Vec<FmmuUsage>,
+    pub sync_managers: Vec<SiiSyncManager>,
+    pub tx_pdos: Vec<SiiPdo>,
+    pub rx_pdos: Vec<SiiPdo>,
+    pub dc_syncs: Vec<SiiDcSync>,
+    /// Categories without a dedicated parser, e.g. vendor-specific ones.
+    pub other_categories: Vec<SiiCategory>,
+}
+
+/// The fixed SII header (words 0x00 - 0x3F).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiiHeader {
+    pub pdi_control: u16,
+    pub pdi_config: u16,
+    pub sync_impulse_len: u16,
+    pub pdi_config2: u16,
+    pub alias: u16,
+    /// Checksum over the ESC configuration area as stored in the SII.
+    pub checksum: u8,
+    /// Checksum calculated from the ESC configuration area.
+    pub calculated_checksum: u8,
+    pub id: SlaveId,
+    pub rev: SlaveRev,
+    pub boot_rx_mailbox: SiiMailbox,
+    pub boot_tx_mailbox: SiiMailbox,
+    pub std_rx_mailbox: SiiMailbox,
+    pub std_tx_mailbox: SiiMailbox,
+    pub mailbox_protocols: MailboxProtocols,
+    /// EEPROM size in bytes.
+    pub size: usize,
+    pub version: u16,
+}
+
+impl SiiHeader {
+    pub fn checksum_valid(&self) -> bool {
+        self.checksum == self.calculated_checksum
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SiiMailbox {
+    pub offset: u16,
+    pub size: u16,
+}
+
+/// A category without a dedicated parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiiCategory {
+    pub category_type: u16,
+    pub vendor_specific: bool,
+    pub data: Vec<u8>,
+}
+
+/// The General category.
+///
+/// The `*_idx` fields are indices into `Sii::strings` as used by
+/// `Sii::string`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiiGeneral {
+    pub group_idx: u8,
+    pub image_idx: u8,
+    pub order_idx: u8,
+    pub name_idx: u8,
+    pub coe_details: CoeDetails,
+    pub foe_details: u8,
+    pub eoe_details: u8,
+    pub soe_channels: u8,
+    pub ds402_channels: u8,
+    pub sysman_class: u8,
+    pub flags: u8,
+    pub current_on_ebus: i16,
+    pub physical_port: u16,
+    pub physical_memory_address: u16,
+}
+
+/// Usage of an FMMU as described in the FMMU category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FmmuUsage {
+    Unused,
+    Outputs,
+    Inputs,
+    SyncManagerStatus,
+    Other(u8),
+}
+
+impl From<u8> for FmmuUsage {
+    fn from(usage: u8) -> Self {
+        match usage {
+            0x00 | 0xFF => FmmuUsage::Unused,
+            0x01 => FmmuUsage::Outputs,
+            0x02 => FmmuUsage::Inputs,
+            0x03 => FmmuUsage::SyncManagerStatus,
+            x => FmmuUsage::Other(x),
+        }
+    }
+}
+
+/// Type of a sync manager as described in the SyncM category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiiSyncManagerType {
+    Unused,
+    MailboxOut,
+    MailboxIn,
+    ProcessDataOut,
+    ProcessDataIn,
+    Other(u8),
+}
+
+impl From<u8> for SiiSyncManagerType {
+    fn from(t: u8) -> Self {
+        match t {
+            0 => SiiSyncManagerType::Unused,
+            1 => SiiSyncManagerType::MailboxOut,
+            2 => SiiSyncManagerType::MailboxIn,
+            3 => SiiSyncManagerType::ProcessDataOut,
+            4 => SiiSyncManagerType::ProcessDataIn,
+            x => SiiSyncManagerType::Other(x),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiiSyncManager {
+    pub start_addr: u16,
+    pub length: u16,
+    pub control_register: u8,
+    pub status_register: u8,
+    pub enable: u8,
+    pub sm_type: SiiSyncManagerType,
+}
+
+/// A PDO of the TxPDO or RxPDO category.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiiPdo {
+    pub idx: u16,
+    pub sync_manager: u8,
+    pub dc_sync: u8,
+    pub name_idx: u8,
+    pub flags: u16,
+    pub entries: Vec<SiiPdoEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiiPdoEntry {
+    pub idx: u16,
+    pub sub_idx: u8,
+    pub name_idx: u8,
+    pub data_type: u8,
+    pub bit_len: u8,
+    pub flags: u16,
+}
+
+/// A sync signal configuration of the DC category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiiDcSync {
+    pub cycle_time_0: u32,
+    pub shift_time_0: u32,
+    pub shift_time_1: u32,
+    pub sync1_cycle_factor: i16,
+    pub assign_activate: u16,
+    pub sync0_cycle_factor: i16,
+    pub name_idx: u8,
+    pub desc_idx: u8,
+}
+
+/// Calculate the checksum of the ESC configuration area (the first 14 bytes).
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0xFF, |crc, b| {
+        (0..8).fold(crc ^ b, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+impl Sii {
+    /// Parse the SII contents as returned by `Master::sii_read`.
+    pub fn parse(words: &[u16]) -> Result<Self, SiiError> {
+        if words.len() < CATEGORY_OFFSET {
+            return Err(SiiError::TooShort(words.len()));
+        }
+        let bytes: Vec<u8> = words
+            .iter()
+            .flat_map(|w| w.to_le_bytes().to_vec())
+            .collect();
+        let header = parse_header(words, &bytes);
+        let mut sii = Sii {
+            header,
+            strings: vec![],
+            general: None,
+            fmmus: vec![],
+            sync_managers: vec![],
+            tx_pdos: vec![],
+            rx_pdos: vec![],
+            dc_syncs: vec![],
+            other_categories: vec![],
+        };
+        let mut offset = CATEGORY_OFFSET;
+        while offset < words.len() {
+            let header = words[offset];
+            if header == CAT_END {
+                break;
+            }
+            let len = *words.get(offset + 1).ok_or(SiiError::CategoryOutOfBounds {
+                category: header,
+                offset,
+            })? as usize;
+            let start = offset + 2;
+            if start + len > words.len() {
+                return Err(SiiError::CategoryOutOfBounds {
+                    category: header,
+                    offset,
+                });
+            }
+            let data = &bytes[start * 2..(start + len) * 2];
+            sii.parse_category(header, data)?;
+            offset = start + len;
+        }
+        Ok(sii)
+    }
+
+    fn parse_category(&mut self, header: u16, data: &[u8]) -> Result<(), SiiError> {
+        let category = header & 0x7FFF;
+        let vendor_specific = header & 0x8000 != 0;
+        let invalid = || SiiError::InvalidCategoryLength { category: header };
+        if vendor_specific {
+            self.other_categories.push(SiiCategory {
+                category_type: category,
+                vendor_specific,
+                data: data.to_vec(),
+            });
+            return Ok(());
+        }
+        match category {
+            CAT_NOP => {}
+            CAT_STRINGS => self.strings = parse_strings(data).ok_or_else(invalid)?,
+            CAT_GENERAL => self.general = Some(parse_general(data).ok_or_else(invalid)?),
+            CAT_FMMU => self.fmmus = data.iter().map(|u| FmmuUsage::from(*u)).collect(),
+            CAT_SYNC_M => {
+                self.sync_managers = data
+                    .chunks_exact(8)
+                    .map(|sm| SiiSyncManager {
+                        start_addr: u16_at(sm, 0),
+                        length: u16_at(sm, 2),
+                        control_register: sm[4],
+                        status_register: sm[5],
+                        enable: sm[6],
+                        sm_type: SiiSyncManagerType::from(sm[7]),
+                    })
+                    .collect()
+            }
+            CAT_TX_PDO => self.tx_pdos = parse_pdos(data).ok_or_else(invalid)?,
+            CAT_RX_PDO => self.rx_pdos = parse_pdos(data).ok_or_else(invalid)?,
+            CAT_DC => {
+                self.dc_syncs = data
+                    .chunks_exact(24)
+                    .map(|dc| SiiDcSync {
+                        cycle_time_0: u32_at(dc, 0),
+                        shift_time_0: u32_at(dc, 4),
+                        shift_time_1: u32_at(dc, 8),
+                        sync1_cycle_factor: u16_at(dc, 12) as i16,
+                        assign_activate: u16_at(dc, 14),
+                        sync0_cycle_factor: u16_at(dc, 16) as i16,
+                        name_idx: dc[18],
+                        desc_idx: dc[19],
+                    })
+                    .collect()
+            }
+            _ => self.other_categories.push(SiiCategory {
+                category_type: category,
+                vendor_specific,
+                data: data.to_vec(),
+            }),
+        }
+        Ok(())
+    }
+
+    /// Get a string by its index as used in the categories.
+    ///
+    /// Index 0 means "no string".
+    pub fn string(&self, idx: u8) -> Option<&str> {
+        if idx == 0 {
+            return None;
+        }
+        self.strings.get(idx as usize - 1).map(String::as_str)
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn parse_header(words: &[u16], bytes: &[u8]) -> SiiHeader {
+    let dword = |w: usize| u32::from(words[w]) | u32::from(words[w + 1]) << 16;
+    let mailbox = |w: usize| SiiMailbox {
+        offset: words[w],
+        size: words[w + 1],
+    };
+    SiiHeader {
+        pdi_control: words[0x00],
+        pdi_config: words[0x01],
+        sync_impulse_len: words[0x02],
+        pdi_config2: words[0x03],
+        alias: words[0x04],
+        checksum: (words[0x07] & 0xFF) as u8,
+        calculated_checksum: checksum(&bytes[..14]),
+        id: SlaveId {
+            vendor_id: dword(0x08),
+            product_code: dword(0x0A),
+        },
+        rev: SlaveRev {
+            revision_number: dword(0x0C),
+            serial_number: dword(0x0E),
+        },
+        boot_rx_mailbox: mailbox(0x14),
+        boot_tx_mailbox: mailbox(0x16),
+        std_rx_mailbox: mailbox(0x18),
+        std_tx_mailbox: mailbox(0x1A),
+        mailbox_protocols: MailboxProtocols::from_bits_truncate(words[0x1C]),
+        size: (words[0x3E] as usize + 1) * 128,
+        version: words[0x3F],
+    }
+}
+
+fn parse_strings(data: &[u8]) -> Option<Vec<String>> {
+    let count = *data.first()? as usize;
+    let mut strings = Vec::with_capacity(count);
+    let mut offset = 1;
+    for _ in 0..count {
+        let len = *data.get(offset)? as usize;
+        let s = data.get(offset + 1..offset + 1 + len)?;
+        strings.push(String::from_utf8_lossy(s).into_owned());
+        offset += 1 + len;
+    }
+    Some(strings)
+}
+
+fn parse_general(data: &[u8]) -> Option<SiiGeneral> {
+    if data.len() < 20 {
+        return None;
+    }
+    Some(SiiGeneral {
+        group_idx: data[0],
+        image_idx: data[1],
+        order_idx: data[2],
+        name_idx: data[3],
+        coe_details: CoeDetails::from_bits_truncate(data[5]),
+        foe_details: data[6],
+        eoe_details: data[7],
+        soe_channels: data[8],
+        ds402_channels: data[9],
+        sysman_class: data[10],
+        flags: data[11],
+        current_on_ebus: u16_at(data, 12) as i16,
+        physical_port: u16_at(data, 16),
+        physical_memory_address: u16_at(data, 18),
+    })
+}
+
+fn parse_pdos(mut data: &[u8]) -> Option<Vec<SiiPdo>> {
+    let mut pdos = vec![];
+    while data.len() >= 8 {
+        let entry_count = data[2] as usize;
+        let entries = data.get(8..8 + entry_count * 8)?;
+        pdos.push(SiiPdo {
+            idx: u16_at(data, 0),
+            sync_manager: data[3],
+            dc_sync: data[4],
+            name_idx: data[5],
+            flags: u16_at(data, 6),
+            entries: entries
+                .chunks_exact(8)
+                .map(|e| SiiPdoEntry {
+                    idx: u16_at(e, 0),
+                    sub_idx: e[2],
+                    name_idx: e[3],
+                    data_type: e[4],
+                    bit_len: e[5],
+                    flags: u16_at(e, 6),
+                })
+                .collect(),
+        });
+        data = &data[8 + entry_count * 8..];
+    }
+    Some(pdos)
+}
+
+#[cfg(test)]
+fn test_image() -> Vec<u16> {
+    let mut words = vec![0; CATEGORY_OFFSET];
+    words[0x00] = 0x0C80;
+    words[0x01] = 0xE088;
+    words[0x04] = 0x0007;
+    words[0x07] = 0x00E9;
+    words[0x08] = 0x0002;
+    words[0x0A] = 0x03F0;
+    words[0x0B] = 0x0C88;
+    words[0x0C] = 0x0010;
+    words[0x0D] = 0x0014;
+    words[0x18] = 0x1000;
+    words[0x19] = 0x0080;
+    words[0x1A] = 0x1080;
+    words[0x1B] = 0x0080;
+    words[0x1C] = 0x0004;
+    words[0x3E] = 0x000F;
+    words[0x3F] = 0x0001;
+    let mut category = |cat: u16, data: &[u8]| {
+        words.push(cat);
+        words.push(data.len().div_ceil(2) as u16);
+        for chunk in data.chunks(2) {
+            words.push(u16::from_le_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]));
+        }
+    };
+    category(CAT_STRINGS, b"\x02\x06EL1008\x04Term");
+    let mut general = [0; 32];
+    general[3] = 1;
+    general[12] = 90;
+    category(CAT_GENERAL, &general);
+    category(CAT_FMMU, &[2, 0xFF]);
+    category(
+        CAT_SYNC_M,
+        &[0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04],
+    );
+    category(
+        CAT_TX_PDO,
+        &[
+            0x00, 0x1A, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, // PDO 0x1A00
+            0x00, 0x60, 0x01, 0x00, 0x01, 0x01, 0x00, 0x00, // entry 0x6000:01
+        ],
+    );
+    category(0x8001, &[0xAB, 0xCD]);
+    words.push(CAT_END);
+    words
+}
+
+#[test]
+fn test_checksum() {
+    let bytes = [0x80, 0x0C, 0x88, 0xE0, 0, 0, 0, 0, 0x07, 0, 0, 0, 0, 0];
+    assert_eq!(checksum(&bytes), 0xE9);
+}
+
+#[test]
+fn test_parse_sii() {
+    let sii = Sii::parse(&test_image()).unwrap();
+    assert_eq!(sii.header.alias, 7);
+    assert!(sii.header.checksum_valid());
+    assert_eq!(sii.header.id.vendor_id, 2);
+    assert_eq!(sii.header.id.product_code, 0x0C88_03F0);
+    assert_eq!(sii.header.rev.revision_number, 0x0014_0010);
+    assert_eq!(
+        sii.header.std_rx_mailbox,
+        SiiMailbox {
+            offset: 0x1000,
+            size: 0x80
+        }
+    );
+    assert_eq!(sii.header.mailbox_protocols, MailboxProtocols::COE);
+    assert_eq!(sii.header.size, 2048);
+    assert_eq!(sii.strings, vec!["EL1008", "Term"]);
+    let general = sii.general.unwrap();
+    assert_eq!(sii.string(general.name_idx), Some("EL1008"));
+    assert_eq!(general.current_on_ebus, 90);
+    assert_eq!(sii.fmmus, vec![FmmuUsage::Inputs, FmmuUsage::Unused]);
+    assert_eq!(sii.sync_managers.len(), 1);
+    assert_eq!(sii.sync_managers[0].start_addr, 0x1000);
+    assert_eq!(
+        sii.sync_managers[0].sm_type,
+        SiiSyncManagerType::ProcessDataIn
+    );
+    assert_eq!(sii.tx_pdos.len(), 1);
+    assert_eq!(sii.tx_pdos[0].idx, 0x1A00);
+    assert_eq!(sii.tx_pdos[0].name_idx, 2);
+    assert_eq!(sii.tx_pdos[0].entries[0].idx, 0x6000);
+    assert_eq!(sii.tx_pdos[0].entries[0].bit_len, 1);
+    assert!(sii.rx_pdos.is_empty());
+    assert_eq!(sii.other_categories.len(), 1);
+    assert!(sii.other_categories[0].vendor_specific);
+}
+
+#[test]
+fn test_parse_sii_errors() {
+    assert_eq!(Sii::parse(&[0; 10]), Err(SiiError::TooShort(10)));
+    let mut image = test_image();
+    image.truncate(CATEGORY_OFFSET + 4);
+    assert_eq!(
+        Sii::parse(&image),
+        Err(SiiError::CategoryOutOfBounds {
+            category: CAT_STRINGS,
+            offset: CATEGORY_OFFSET
+        })
+    );
+}
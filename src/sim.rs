@@ -0,0 +1,485 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! A simulated EtherCAT network.
+//!
+//! `Simulation` emulates the IgH master together with a bus of slaves, so
+//! that applications using `Master`, `SlaveConfig` and `Domain` can be run
+//! without the kernel module:
+//!
+//! ```no_run
+//! use ethercat::{sim::{SimSlave, Simulation}, Master, SlaveId};
+//!
+//! let sim = Simulation::new(vec![SimSlave::new(SlaveId::new(0x2, 0x07d83052))]);
+//! let master = Master::with_backend(sim.backend())?;
+//! # Ok::<(), ethercat::Error>(())
+//! ```
+//!
+//! The simulation covers slave information and PDO descriptions, SDO
+//! upload and download, the AL state machine, slave configurations and
+//! cyclic process data exchange of domains. Other requests fail with
+//! `ENOTTY`.
+//!
+//! Slaves start in PreOp. While the master is idle, requested AL states are
+//! reached immediately; after activation, configured slaves are brought to
+//! Op and every slave advances one state per `Master::receive`.
+
+use crate::{
+    backend::{Backend, ProcessDataMemory},
+    ec,
+    emulation::{
+        lock, next_al_state, sdo_key, Answer, Bus, ConfigResult, Connection, Network, SdoResult,
+        SlaveDesc, SyncLayout,
+    },
+    sdo::SdoAbortCode,
+    types::*,
+};
+use std::{
+    collections::BTreeMap,
+    io,
+    os::raw::{c_int, c_ulong},
+    sync::{Arc, Mutex},
+};
+
+/// Description of a simulated slave.
+#[derive(Debug, Clone)]
+pub struct SimSlave(SlaveDesc);
+
+impl SimSlave {
+    pub fn new(id: SlaveId) -> Self {
+        Self(SlaveDesc::new(id))
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.0.name = name.to_owned();
+        self
+    }
+
+    pub fn with_rev(mut self, rev: SlaveRev) -> Self {
+        self.0.rev = rev;
+        self
+    }
+
+    pub fn with_alias(mut self, alias: u16) -> Self {
+        self.0.alias = alias;
+        self
+    }
+
+    /// Add a sync manager with its default PDO assignment and mapping,
+    /// as described in the SII.
+    pub fn with_sync(mut self, cfg: SmCfg, pdos: Vec<PdoCfg>) -> Self {
+        self.0.set_sync(cfg, pdos);
+        self
+    }
+
+    /// Add an object to the object dictionary.
+    ///
+    /// The length of `value` is fixed: downloads with another length are
+    /// aborted.
+    pub fn with_object(mut self, idx: SdoIdx, value: &[u8]) -> Self {
+        self.0.objects.insert(sdo_key(idx), value.to_vec());
+        self
+    }
+}
+
+/// A simulated bus of slaves.
+///
+/// The handle can be cloned to inspect and modify the slaves while a
+/// `Master` is using the simulation.
+#[derive(Clone)]
+pub struct Simulation {
+    bus: Arc<Mutex<Bus<SimNetwork>>>,
+}
+
+impl Simulation {
+    pub fn new(slaves: Vec<SimSlave>) -> Self {
+        let slaves = slaves
+            .into_iter()
+            .map(|slave| Slave {
+                desc: slave.0,
+                al_state: AlState::PreOp,
+                requested: AlState::PreOp,
+                sm_data: BTreeMap::new(),
+                fmmus: vec![],
+            })
+            .collect();
+        Self {
+            bus: Bus::new(SimNetwork {
+                slaves,
+                answers: vec![],
+            }),
+        }
+    }
+
+    /// Create a backend for a `Master`, like opening `/dev/EtherCATn`.
+    pub fn backend(&self) -> SimBackend {
+        SimBackend(Connection::new(&self.bus))
+    }
+
+    pub fn al_state(&self, slave: SlavePos) -> Option<AlState> {
+        lock(&self.bus)
+            .network
+            .slaves
+            .get(usize::from(slave))
+            .map(|s| s.al_state)
+    }
+
+    /// Data of a sync manager: the outputs last written by the master or
+    /// the inputs set with `set_sm_data`.
+    pub fn sm_data(&self, slave: SlavePos, sm: SmIdx) -> Vec<u8> {
+        lock(&self.bus)
+            .network
+            .slaves
+            .get(usize::from(slave))
+            .and_then(|s| s.sm_data.get(&u8::from(sm)).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Set the data of a sync manager, e.g. the inputs read by the master.
+    pub fn set_sm_data(&self, slave: SlavePos, sm: SmIdx, data: &[u8]) {
+        if let Some(s) = lock(&self.bus).network.slaves.get_mut(usize::from(slave)) {
+            s.sm_data.insert(u8::from(sm), data.to_vec());
+        }
+    }
+
+    pub fn sdo(&self, slave: SlavePos, idx: SdoIdx) -> Option<Vec<u8>> {
+        lock(&self.bus)
+            .network
+            .slaves
+            .get(usize::from(slave))
+            .and_then(|s| s.desc.objects.get(&sdo_key(idx)).cloned())
+    }
+
+    pub fn set_sdo(&self, slave: SlavePos, idx: SdoIdx, value: &[u8]) {
+        if let Some(s) = lock(&self.bus).network.slaves.get_mut(usize::from(slave)) {
+            s.desc.objects.insert(sdo_key(idx), value.to_vec());
+        }
+    }
+}
+
+/// A connection of a `Master` to a `Simulation`.
+///
+/// Like a file descriptor of the kernel module, the connection which
+/// reserved the master is released when it is dropped.
+pub struct SimBackend(Connection<SimNetwork>);
+
+impl Backend for SimBackend {
+    unsafe fn ioctl(&self, request: ec::RawRequest, arg: c_ulong) -> io::Result<c_int> {
+        self.0.ioctl(request, arg)
+    }
+
+    fn map_process_data(&self, size: usize) -> io::Result<Box<dyn ProcessDataMemory>> {
+        self.0.map_process_data(size)
+    }
+
+    fn take_error(&self) -> Option<Error> {
+        self.0.take_error()
+    }
+}
+
+struct Slave {
+    desc: SlaveDesc,
+    al_state: AlState,
+    requested: AlState,
+    sm_data: BTreeMap<u8, Vec<u8>>,
+    /// Sync managers mapped to logical addresses: index, direction,
+    /// address and size.
+    fmmus: Vec<(u8, ec::ec_direction_t, usize, usize)>,
+}
+
+impl Slave {
+    fn step(&mut self) {
+        self.al_state = next_al_state(self.al_state, self.requested);
+    }
+
+    fn settle(&mut self) {
+        while self.al_state != self.requested {
+            self.step();
+        }
+    }
+
+    fn check_mailbox(&self) -> std::result::Result<(), SdoAbortCode> {
+        match self.al_state {
+            AlState::Init | AlState::Boot => Err(SdoAbortCode::DeviceState),
+            _ => Ok(()),
+        }
+    }
+
+    fn sdo(&self, index: u16, subindex: u8) -> std::result::Result<&[u8], SdoAbortCode> {
+        self.check_mailbox()?;
+        match self.desc.objects.get(&(index, subindex)) {
+            Some(value) => Ok(value),
+            None if self.desc.sdo_indices().contains(&index) => Err(SdoAbortCode::NoSubIndex),
+            None => Err(SdoAbortCode::NoObject),
+        }
+    }
+
+    fn download(
+        &mut self,
+        index: u16,
+        subindex: u8,
+        value: &[u8],
+    ) -> std::result::Result<(), SdoAbortCode> {
+        let current = self.sdo(index, subindex)?;
+        if current.len() != value.len() {
+            return Err(if value.len() > current.len() {
+                SdoAbortCode::LengthTooHigh
+            } else {
+                SdoAbortCode::LengthTooLow
+            });
+        }
+        self.desc.objects.insert((index, subindex), value.to_vec());
+        Ok(())
+    }
+}
+
+/// The slaves of a `Simulation`.
+struct SimNetwork {
+    slaves: Vec<Slave>,
+    answers: Vec<Answer>,
+}
+
+impl Network for SimNetwork {
+    fn slave_count(&self) -> usize {
+        self.slaves.len()
+    }
+
+    fn desc(&self, slave: usize) -> &SlaveDesc {
+        &self.slaves[slave].desc
+    }
+
+    fn al_state(&self, slave: usize) -> AlState {
+        self.slaves[slave].al_state
+    }
+
+    fn request_state(&mut self, slave: usize, state: AlState, wait: bool) -> io::Result<()> {
+        let slave = &mut self.slaves[slave];
+        slave.requested = state;
+        if wait {
+            slave.settle();
+        }
+        Ok(())
+    }
+
+    fn sdo_upload(&mut self, slave: usize, index: u16, subindex: u8) -> SdoResult<Vec<u8>> {
+        Ok(self.slaves[slave].sdo(index, subindex)?.to_vec())
+    }
+
+    fn sdo_download(
+        &mut self,
+        slave: usize,
+        index: u16,
+        subindex: u8,
+        complete_access: bool,
+        data: &[u8],
+    ) -> SdoResult<()> {
+        if complete_access {
+            return Err(SdoAbortCode::CompleteAccessNotSupported.into());
+        }
+        Ok(self.slaves[slave].download(index, subindex, data)?)
+    }
+
+    fn configure(&mut self, slave: usize, syncs: &[SyncLayout]) -> ConfigResult<()> {
+        self.slaves[slave].fmmus = syncs
+            .iter()
+            .flat_map(|s| {
+                s.addresses
+                    .iter()
+                    .map(move |a| (s.index, s.dir, *a, s.size))
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn send(&mut self, domain: usize, address: usize, data: &[u8]) -> io::Result<()> {
+        let range = address..address + data.len();
+        let mut answer = Answer {
+            domain,
+            working_counter: 0,
+            data: data.to_vec(),
+        };
+        for slave in &mut self.slaves {
+            let writes = slave.al_state == AlState::Op;
+            let reads = writes || slave.al_state == AlState::SafeOp;
+            let (mut wrote, mut read) = (false, false);
+            for &(sync, dir, start, size) in &slave.fmmus {
+                if !range.contains(&start) {
+                    continue;
+                }
+                let offset = start - address;
+                if dir == ec::EC_DIR_OUTPUT && writes {
+                    let output = data[offset..offset + size].to_vec();
+                    slave.sm_data.insert(sync, output);
+                    wrote = true;
+                } else if dir == ec::EC_DIR_INPUT && reads {
+                    let mut input = slave.sm_data.get(&sync).cloned().unwrap_or_default();
+                    input.resize(size, 0);
+                    answer.data[offset..offset + size].copy_from_slice(&input);
+                    read = true;
+                }
+            }
+            answer.working_counter += 2 * wrote as u16 + read as u16;
+        }
+        self.answers.push(answer);
+        Ok(())
+    }
+
+    /// Return the answers of the last sent frames and advance the AL
+    /// states.
+    fn receive(&mut self) -> io::Result<Vec<Answer>> {
+        for slave in &mut self.slaves {
+            slave.step();
+        }
+        Ok(self.answers.drain(..).collect())
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_slave() -> SimSlave {
+    let entry = |idx, bit_len| PdoEntryInfo {
+        pos: PdoEntryPos::from(0),
+        entry_idx: PdoEntryIdx::new(idx, 1),
+        bit_len,
+        name: String::new(),
+    };
+    SimSlave::new(SlaveId::new(0x2, 0x1234))
+        .with_name("Sim IO")
+        .with_sync(
+            SmCfg::output(SmIdx::from(2)),
+            vec![PdoCfg {
+                idx: PdoIdx::from(0x1600),
+                entries: vec![entry(0x7000, 8)],
+            }],
+        )
+        .with_sync(
+            SmCfg::input(SmIdx::from(3)),
+            vec![PdoCfg {
+                idx: PdoIdx::from(0x1A00),
+                entries: vec![entry(0x6000, 16)],
+            }],
+        )
+        .with_object(SdoIdx::new(0x8000, 1), &[0, 0])
+}
+
+#[test]
+fn test_simulation() {
+    use crate::Master;
+
+    let sim = Simulation::new(vec![test_slave(), test_slave()]);
+    let mut master = Master::with_backend(sim.backend())
+        .unwrap()
+        .reserve()
+        .unwrap();
+    assert!(Master::with_backend(sim.backend())
+        .unwrap()
+        .reserve()
+        .is_err());
+
+    let info = master.get_slave_info(SlavePos::from(1)).unwrap();
+    assert_eq!(info.name, "Sim IO");
+    assert_eq!(info.al_state, AlState::PreOp);
+    assert_eq!(info.sync_count, 4);
+    let pdo = master
+        .get_pdo_entry(
+            SlavePos::from(0),
+            SmIdx::from(3),
+            PdoPos::from(0),
+            PdoEntryPos::from(0),
+        )
+        .unwrap();
+    assert_eq!(pdo.entry_idx, PdoEntryIdx::new(0x6000, 1));
+
+    let sdo = SdoIdx::new(0x8000, 1);
+    master
+        .sdo_download(SlavePos::from(0), sdo, false, &0x0102_u16)
+        .unwrap();
+    let mut buf = [0; 4];
+    let value = master
+        .sdo_upload(SlavePos::from(0), sdo, false, &mut buf)
+        .unwrap();
+    assert_eq!(value, &[0x02, 0x01]);
+    assert!(matches!(
+        master.sdo_upload(SlavePos::from(0), SdoIdx::new(0x8001, 0), false, &mut buf),
+        Err(crate::Error::SdoAbort(SdoAbortCode::NoObject))
+    ));
+    master
+        .request_state(SlavePos::from(1), AlState::Init)
+        .unwrap();
+    assert_eq!(sim.al_state(SlavePos::from(1)), Some(AlState::Init));
+    master
+        .request_state(SlavePos::from(1), AlState::PreOp)
+        .unwrap();
+
+    let configs = (0..2)
+        .map(|pos| {
+            master
+                .configure_slave(SlaveAddr::ByPos(pos), SlaveId::new(0x2, 0x1234))
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let domain = master.create_domain().unwrap();
+    let mut offsets = vec![];
+    for config in &configs {
+        config.add_sdo(&master, sdo, &0x0506_u16).unwrap();
+        let output = config
+            .register_pdo_entry(&master, PdoEntryIdx::new(0x7000, 1), domain)
+            .unwrap();
+        let input = config
+            .register_pdo_entry(&master, PdoEntryIdx::new(0x6000, 1), domain)
+            .unwrap();
+        offsets.push((output.byte, input.byte));
+    }
+    assert_eq!(offsets, vec![(0, 1), (3, 4)]);
+    let config = master.get_config_info(1).unwrap();
+    assert_eq!(config.slave_position, Some(SlavePos::from(1)));
+    assert_eq!(config.syncs.len(), 2);
+
+    let mut master = master.activate().unwrap();
+    assert_eq!(sim.sdo(SlavePos::from(1), sdo), Some(vec![0x06, 0x05]));
+    sim.set_sm_data(SlavePos::from(1), SmIdx::from(3), &[0xCD, 0xAB]);
+    for cycle in 0..4 {
+        master.receive().unwrap();
+        master.domain(domain).process().unwrap();
+        let state = master.domain(domain).state().unwrap();
+        // Slaves reach SafeOp in the first and Op in the second cycle.
+        if cycle >= 2 {
+            assert!(matches!(state.wc_state, WcState::Complete));
+            assert_eq!(state.working_counter, 6);
+        } else {
+            assert!(!matches!(state.wc_state, WcState::Complete));
+        }
+        master.domain_data(domain).unwrap()[3] = 42;
+        master.domain(domain).queue().unwrap();
+        master.send().unwrap();
+    }
+    assert_eq!(master.state().unwrap().al_states, AlState::Op as u8);
+    assert_eq!(sim.sm_data(SlavePos::from(1), SmIdx::from(2)), vec![42]);
+    master.receive().unwrap();
+    assert_eq!(master.domain_data(domain).unwrap()[4..6], [0xCD, 0xAB]);
+
+    let mut master = master.deactivate().unwrap();
+    assert_eq!(sim.al_state(SlavePos::from(0)), Some(AlState::PreOp));
+    // The slave configurations are discarded, also when the index is
+    // reused by a new configuration.
+    let config = master
+        .configure_slave(SlaveAddr::ByPos(0), SlaveId::new(0x2, 0x1234))
+        .unwrap();
+    assert_eq!(config.index(), configs[0].index());
+    assert!(config.state(&master).is_ok());
+    assert!(matches!(
+        configs[0].state(&master),
+        Err(crate::Error::StaleHandle(..))
+    ));
+    assert!(master.create_domain().is_ok());
+
+    // Handles only work with the master which created them.
+    let other = Simulation::new(vec![test_slave()]);
+    let other = Master::with_backend(other.backend())
+        .unwrap()
+        .reserve()
+        .unwrap();
+    assert!(matches!(
+        config.state(&other),
+        Err(crate::Error::StaleHandle(..))
+    ));
+}
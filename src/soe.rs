@@ -0,0 +1,290 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Servo drive profile over EtherCAT (SoE)
+
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// A Sercos identification number (IDN).
+///
+/// IDNs are written as `S-0-0017` (standard data) or `P-1-0123`
+/// (product-specific data), consisting of the parameter set (0 - 7)
+/// and the data block number (0 - 4095).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Idn(u16);
+
+impl Idn {
+    const PRODUCT_SPECIFIC: u16 = 0x8000;
+    const MAX_PARAM_SET: u8 = 7;
+    const MAX_DATA_BLOCK: u16 = 0x0FFF;
+
+    /// Create an IDN from its components.
+    ///
+    /// Returns `None` if the parameter set or the data block number is
+    /// out of range.
+    pub fn new(product_specific: bool, param_set: u8, data_block: u16) -> Option<Self> {
+        if param_set > Self::MAX_PARAM_SET || data_block > Self::MAX_DATA_BLOCK {
+            return None;
+        }
+        let kind = if product_specific {
+            Self::PRODUCT_SPECIFIC
+        } else {
+            0
+        };
+        Some(Self(kind | (param_set as u16) << 12 | data_block))
+    }
+
+    pub const fn is_product_specific(self) -> bool {
+        self.0 & Self::PRODUCT_SPECIFIC != 0
+    }
+
+    pub const fn param_set(self) -> u8 {
+        ((self.0 >> 12) & 0x7) as u8
+    }
+
+    pub const fn data_block(self) -> u16 {
+        self.0 & Self::MAX_DATA_BLOCK
+    }
+}
+
+impl From<u16> for Idn {
+    fn from(idn: u16) -> Self {
+        Self(idn)
+    }
+}
+
+impl From<Idn> for u16 {
+    fn from(idn: Idn) -> Self {
+        idn.0
+    }
+}
+
+impl fmt::Display for Idn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{:04}",
+            if self.is_product_specific() { 'P' } else { 'S' },
+            self.param_set(),
+            self.data_block()
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid IDN '{0}'")]
+pub struct ParseIdnError(String);
+
+impl FromStr for Idn {
+    type Err = ParseIdnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseIdnError(s.to_string());
+        let mut parts = s.trim().splitn(3, '-');
+        let product_specific = match parts.next() {
+            Some("S") | Some("s") => false,
+            Some("P") | Some("p") => true,
+            _ => return Err(err()),
+        };
+        let param_set = parts
+            .next()
+            .filter(|p| p.len() == 1)
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(err)?;
+        let data_block = parts
+            .next()
+            .filter(|p| !p.is_empty() && p.len() <= 4 && p.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(err)?;
+        Self::new(product_specific, param_set, data_block).ok_or_else(err)
+    }
+}
+
+/// Error code returned by a drive for a failed SoE access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum SoeError {
+    #[error("No IDN")]
+    NoIdn,
+    #[error("Invalid access to element 1")]
+    InvalidAccessToElement1,
+    #[error("No name")]
+    NoName,
+    #[error("Name transmission too short")]
+    NameTooShort,
+    #[error("Name transmission too long")]
+    NameTooLong,
+    #[error("Name cannot be changed (read only)")]
+    NameReadOnly,
+    #[error("Name is write-protected at this time")]
+    NameWriteProtected,
+    #[error("Attribute transmission too short")]
+    AttributeTooShort,
+    #[error("Attribute transmission too long")]
+    AttributeTooLong,
+    #[error("Attribute cannot be changed (read only)")]
+    AttributeReadOnly,
+    #[error("Attribute is write-protected at this time")]
+    AttributeWriteProtected,
+    #[error("No units")]
+    NoUnits,
+    #[error("Unit transmission too short")]
+    UnitTooShort,
+    #[error("Unit transmission too long")]
+    UnitTooLong,
+    #[error("Unit cannot be changed (read only)")]
+    UnitReadOnly,
+    #[error("Unit is write-protected at this time")]
+    UnitWriteProtected,
+    #[error("No minimum input value")]
+    NoMinimum,
+    #[error("Minimum input value transmission too short")]
+    MinimumTooShort,
+    #[error("Minimum input value transmission too long")]
+    MinimumTooLong,
+    #[error("Minimum input value cannot be changed (read only)")]
+    MinimumReadOnly,
+    #[error("Minimum input value is write-protected at this time")]
+    MinimumWriteProtected,
+    #[error("No maximum input value")]
+    NoMaximum,
+    #[error("Maximum input value transmission too short")]
+    MaximumTooShort,
+    #[error("Maximum input value transmission too long")]
+    MaximumTooLong,
+    #[error("Maximum input value cannot be changed (read only)")]
+    MaximumReadOnly,
+    #[error("Maximum input value is write-protected at this time")]
+    MaximumWriteProtected,
+    #[error("Operation data transmission too short")]
+    DataTooShort,
+    #[error("Operation data transmission too long")]
+    DataTooLong,
+    #[error("Operation data cannot be changed (read only)")]
+    DataReadOnly,
+    #[error("Operation data is write-protected at this time")]
+    DataWriteProtected,
+    #[error("Operation data is smaller than the minimum input value")]
+    DataBelowMinimum,
+    #[error("Operation data is greater than the maximum input value")]
+    DataAboveMaximum,
+    #[error("Invalid operation data: configured IDN will not be supported")]
+    DataInvalid,
+    #[error("Operation data is write-protected by a password")]
+    DataPasswordProtected,
+    #[error("Operation data is write-protected, it is configured cyclically")]
+    DataCyclic,
+    #[error("Invalid indirect addressing")]
+    InvalidIndirectAddressing,
+    #[error("Operation data is write-protected due to other settings")]
+    DataOtherSettings,
+    #[error("Procedure command already active")]
+    CommandActive,
+    #[error("Procedure command not interruptible")]
+    CommandNotInterruptible,
+    #[error("Procedure command is not executable at this time")]
+    CommandNotExecutableNow,
+    #[error("Procedure command not executable (invalid or false parameters)")]
+    CommandNotExecutable,
+    #[error("No data state")]
+    NoDataState,
+    #[error("No default value")]
+    NoDefault,
+    #[error("Default value transmission too long")]
+    DefaultTooLong,
+    #[error("Default value cannot be changed (read only)")]
+    DefaultReadOnly,
+    #[error("Invalid drive number")]
+    InvalidDriveNumber,
+    #[error("General error")]
+    General,
+    #[error("No element addressed")]
+    NoElement,
+    #[error("Unknown SoE error 0x{0:04X}")]
+    Other(u16),
+}
+
+impl From<u16> for SoeError {
+    fn from(code: u16) -> Self {
+        use SoeError::*;
+        match code {
+            0x1001 => NoIdn,
+            0x1009 => InvalidAccessToElement1,
+            0x2001 => NoName,
+            0x2002 => NameTooShort,
+            0x2003 => NameTooLong,
+            0x2004 => NameReadOnly,
+            0x2005 => NameWriteProtected,
+            0x3002 => AttributeTooShort,
+            0x3003 => AttributeTooLong,
+            0x3004 => AttributeReadOnly,
+            0x3005 => AttributeWriteProtected,
+            0x4001 => NoUnits,
+            0x4002 => UnitTooShort,
+            0x4003 => UnitTooLong,
+            0x4004 => UnitReadOnly,
+            0x4005 => UnitWriteProtected,
+            0x5001 => NoMinimum,
+            0x5002 => MinimumTooShort,
+            0x5003 => MinimumTooLong,
+            0x5004 => MinimumReadOnly,
+            0x5005 => MinimumWriteProtected,
+            0x6001 => NoMaximum,
+            0x6002 => MaximumTooShort,
+            0x6003 => MaximumTooLong,
+            0x6004 => MaximumReadOnly,
+            0x6005 => MaximumWriteProtected,
+            0x7002 => DataTooShort,
+            0x7003 => DataTooLong,
+            0x7004 => DataReadOnly,
+            0x7005 => DataWriteProtected,
+            0x7006 => DataBelowMinimum,
+            0x7007 => DataAboveMaximum,
+            0x7008 => DataInvalid,
+            0x7009 => DataPasswordProtected,
+            0x700A => DataCyclic,
+            0x700B => InvalidIndirectAddressing,
+            0x700C => DataOtherSettings,
+            0x7010 => CommandActive,
+            0x7011 => CommandNotInterruptible,
+            0x7012 => CommandNotExecutableNow,
+            0x7013 => CommandNotExecutable,
+            0x7014 => NoDataState,
+            0x8001 => NoDefault,
+            0x8002 => DefaultTooLong,
+            0x8004 => DefaultReadOnly,
+            0x800A => InvalidDriveNumber,
+            0x800B => General,
+            0x800C => NoElement,
+            x => Other(x),
+        }
+    }
+}
+
+#[test]
+fn test_idn_parse_and_format() {
+    let idn: Idn = "S-0-0017".parse().unwrap();
+    assert_eq!(u16::from(idn), 17);
+    assert_eq!(idn.to_string(), "S-0-0017");
+
+    let idn: Idn = "P-1-0123".parse().unwrap();
+    assert!(idn.is_product_specific());
+    assert_eq!(idn.param_set(), 1);
+    assert_eq!(idn.data_block(), 123);
+    assert_eq!(u16::from(idn), 0x8000 | 0x1000 | 123);
+    assert_eq!(idn.to_string(), "P-1-0123");
+
+    assert_eq!(Idn::from(0xFFFF).to_string(), "P-7-4095");
+    assert!("S-8-0001".parse::<Idn>().is_err());
+    assert!("S-0-4096".parse::<Idn>().is_err());
+    assert!("X-0-0001".parse::<Idn>().is_err());
+    assert!("S-0-".parse::<Idn>().is_err());
+    assert!("S-0-+12".parse::<Idn>().is_err());
+}
+
+#[test]
+fn test_soe_error_from_code() {
+    assert_eq!(SoeError::from(0x1001), SoeError::NoIdn);
+    assert_eq!(SoeError::from(0x7007), SoeError::DataAboveMaximum);
+    assert_eq!(SoeError::from(0x1234), SoeError::Other(0x1234));
+}
@@ -0,0 +1,36 @@
+// Part of ethercat-rs. Copyright 2018-2020 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Fixtures shared by the tests of the different backends.
+
+use crate::{Master, PdoEntryIdx, Result, SdoIdx, SlaveAddr, SlaveId, SlavePos};
+
+/// Run an application against the first slave of `sim::test_slave`'s layout.
+///
+/// Accesses an SDO, exchanges three cycles of process data writing `output`,
+/// and returns the SDO value followed by the inputs of each cycle.
+pub(crate) fn run_cycles(mut master: Master, output: u8) -> Result<Vec<u8>> {
+    let mut inputs = vec![0; 4];
+    let sdo = SdoIdx::new(0x8000, 1);
+    master.sdo_download(SlavePos::from(0), sdo, false, &0x0102_u16)?;
+    let len = master
+        .sdo_upload(SlavePos::from(0), sdo, false, &mut inputs)?
+        .len();
+    inputs.truncate(len);
+    let mut master = master.reserve()?;
+    let domain = master.create_domain()?;
+    let config = master.configure_slave(SlaveAddr::ByPos(0), SlaveId::new(0x2, 0x1234))?;
+    config.register_pdo_entry(&master, PdoEntryIdx::new(0x7000, 1), domain)?;
+    config.register_pdo_entry(&master, PdoEntryIdx::new(0x6000, 1), domain)?;
+    let mut master = master.activate()?;
+    for _ in 0..3 {
+        master.receive()?;
+        master.domain(domain).process()?;
+        inputs.extend_from_slice(&master.domain_data(domain)?[1..3]);
+        master.domain_data(domain)?[0] = output;
+        master.domain(domain).queue()?;
+        master.send()?;
+    }
+    master.deactivate()?;
+    Ok(inputs)
+}
@@ -1,9 +1,16 @@
 // Part of ethercat-rs. Copyright 2018-2020 by the authors.
 // This work is dual-licensed under Apache 2.0 and MIT terms.
 
-use crate::ec;
+use crate::{
+    ec,
+    foe::FoeError,
+    sdo::SdoAbortCode,
+    sii::{SiiError, SiiMailbox},
+    soe::{Idn, SoeError},
+};
+use bitflags::bitflags;
 use derive_new::new;
-use std::io;
+use std::{convert::TryFrom, fmt, io, net::Ipv4Addr};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -18,14 +25,133 @@ pub enum Error {
     KernelModule(u32, u32),
     #[error("Domain is not available")]
     NoDomain,
-    #[error("Master is not activated")]
-    NotActivated,
+    #[error("Timeout while waiting for the bus scan")]
+    ScanTimeout,
     #[error("Invalid AL state 0x{0:X}")]
     InvalidAlState(u8),
+    #[error("Invalid master phase {0}")]
+    InvalidMasterPhase(u8),
+    #[error("Invalid DC range {0}")]
+    InvalidDcRange(u32),
+    #[error("Invalid port type {0}")]
+    InvalidPortType(u32),
+    #[error("Invalid working counter state {0}")]
+    InvalidWcState(u32),
+    #[error("Invalid sync manager direction {0}")]
+    InvalidSyncDirection(u32),
+    #[error("Invalid watchdog mode {0}")]
+    InvalidWatchdogMode(u32),
+    #[error("Slave {} does not support distributed clocks", u16::from(*.0))]
+    NoDcSupport(SlavePos),
+    #[error("Invalid request state {0}")]
+    InvalidRequestState(u32),
+    #[error("Handle of {0} belongs to another master or to a discarded configuration")]
+    StaleHandle(ErrorTarget),
+    #[error("Request data size {0} exceeds the reserved size {1}")]
+    RequestSize(usize, usize),
+    #[error("EoE host name is too long")]
+    EoeHostnameTooLong,
+    #[error("EoE request failed with result 0x{0:04X}")]
+    EoeResult(u16),
+    #[error("SDO transfer aborted: {0}")]
+    SdoAbort(#[from] SdoAbortCode),
+    #[error(
+        "SDO 0x{:04X}:{} of slave {} aborted while configuring: {code}",
+        u16::from(.idx.idx),
+        u8::from(.idx.sub_idx),
+        u16::from(*.slave)
+    )]
+    ConfigSdoAbort {
+        slave: SlavePos,
+        idx: SdoIdx,
+        code: SdoAbortCode,
+    },
+    #[error(transparent)]
+    Foe(#[from] FoeError),
+    #[error("SoE error: {0}")]
+    Soe(#[from] SoeError),
+    #[error(transparent)]
+    Sii(#[from] SiiError),
+    #[error("{op} on {target}: master is in use by another process")]
+    Busy {
+        op: &'static str,
+        target: ErrorTarget,
+        source: io::Error,
+    },
+    #[error("{op} on {target}: timeout")]
+    Timeout {
+        op: &'static str,
+        target: ErrorTarget,
+        source: io::Error,
+    },
+    #[error("{op} on {target}: not found")]
+    NotFound {
+        op: &'static str,
+        target: ErrorTarget,
+        source: io::Error,
+    },
+    #[error("{op} on {target}: not allowed in the current phase")]
+    WrongPhase {
+        op: &'static str,
+        target: ErrorTarget,
+        source: io::Error,
+    },
+    #[error("{op} on {target}: {source}")]
+    Ioctl {
+        op: &'static str,
+        target: ErrorTarget,
+        source: io::Error,
+    },
     #[error(transparent)]
     Io(#[from] io::Error),
 }
 
+impl Error {
+    /// Classify the errno of a failed ioctl.
+    pub(crate) fn from_ioctl(op: &'static str, target: ErrorTarget, source: io::Error) -> Self {
+        match source.raw_os_error() {
+            Some(libc::EBUSY) => Error::Busy { op, target, source },
+            Some(libc::ETIMEDOUT) | Some(libc::EIO) => Error::Timeout { op, target, source },
+            Some(libc::EINVAL) | Some(libc::ENOENT) => Error::NotFound { op, target, source },
+            Some(libc::EPERM) => Error::WrongPhase { op, target, source },
+            _ => Error::Ioctl { op, target, source },
+        }
+    }
+
+    /// The OS error code, if the error was caused by a failed system call.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Busy { source, .. }
+            | Error::Timeout { source, .. }
+            | Error::NotFound { source, .. }
+            | Error::WrongPhase { source, .. }
+            | Error::Ioctl { source, .. }
+            | Error::Io(source) => source.raw_os_error(),
+            _ => None,
+        }
+    }
+}
+
+/// The object a failed operation was applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorTarget {
+    Master,
+    Slave(SlavePos),
+    Config(SlaveConfigIdx),
+    Domain(DomainIdx),
+}
+
+impl fmt::Display for ErrorTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorTarget::Master => write!(f, "master"),
+            ErrorTarget::Slave(pos) => write!(f, "slave {}", u16::from(*pos)),
+            ErrorTarget::Config(idx) => write!(f, "slave config {}", idx),
+            ErrorTarget::Domain(idx) => write!(f, "domain {}", usize::from(*idx)),
+        }
+    }
+}
+
 impl From<Error> for io::Error {
     fn from(e: Error) -> Self {
         io::Error::new(io::ErrorKind::Other, e)
@@ -46,14 +172,14 @@ pub(crate) struct DomainDataPlacement {
 pub type SlaveConfigIdx = u32;
 
 /// An EtherCAT slave identification, consisting of vendor ID and product code.
-#[derive(Debug, Clone, Copy, new)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
 pub struct SlaveId {
     pub vendor_id: u32,
     pub product_code: u32,
 }
 
 /// An EtherCAT slave revision identification.
-#[derive(Debug, Clone, Copy, new)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
 pub struct SlaveRev {
     pub revision_number: u32,
     pub serial_number: u32,
@@ -79,9 +205,130 @@ impl SlaveAddr {
 #[derive(Debug, Clone)]
 pub struct MasterInfo {
     pub slave_count: u32,
+    /// Link state of the main device.
     pub link_up: bool,
     pub scan_busy: bool,
     pub app_time: u64,
+    /// Position of the slave acting as DC reference clock, if any.
+    pub ref_clock: Option<SlavePos>,
+    pub phase: MasterPhase,
+    pub active: bool,
+    pub config_count: u32,
+    pub domain_count: u32,
+    pub eoe_handler_count: u32,
+    /// The main device followed by the backup devices.
+    pub devices: Vec<MasterDeviceInfo>,
+    /// Statistics summed up over all devices.
+    pub stats: FrameStats,
+    /// Rates of lost frames in frames per second.
+    pub loss_rates: Rates,
+    /// Application time of the last DC reference clock sync.
+    pub dc_ref_time: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MasterPhase {
+    /// No network device attached.
+    Orphaned,
+    /// Network device attached, master is not in use by an application.
+    Idle,
+    /// Master is in use by an application.
+    Operation,
+}
+
+impl TryFrom<u8> for MasterPhase {
+    type Error = ();
+
+    fn try_from(phase: u8) -> std::result::Result<Self, ()> {
+        match phase {
+            0 => Ok(MasterPhase::Orphaned),
+            1 => Ok(MasterPhase::Idle),
+            2 => Ok(MasterPhase::Operation),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An Ethernet device used by the master.
+#[derive(Debug, Clone)]
+pub struct MasterDeviceInfo {
+    pub mac_address: [u8; 6],
+    pub attached: bool,
+    pub link_up: bool,
+    pub tx_errors: u64,
+    pub stats: FrameStats,
+}
+
+/// Frame statistics of the master or a single device.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrameStats {
+    pub tx_count: u64,
+    pub rx_count: u64,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    /// Frames per second
+    pub tx_frame_rates: Rates,
+    /// Frames per second
+    pub rx_frame_rates: Rates,
+    /// Bytes per second
+    pub tx_byte_rates: Rates,
+    /// Bytes per second
+    pub rx_byte_rates: Rates,
+}
+
+/// Rates averaged over 1, 10 and 60 seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rates {
+    pub avg_1s: f64,
+    pub avg_10s: f64,
+    pub avg_60s: f64,
+}
+
+impl Rates {
+    /// Convert frame rates, which the master reports in frames per 1000 s.
+    pub(crate) fn from_frame_rates(rates: [i32; 3]) -> Self {
+        Self {
+            avg_1s: f64::from(rates[0]) / 1000.0,
+            avg_10s: f64::from(rates[1]) / 1000.0,
+            avg_60s: f64::from(rates[2]) / 1000.0,
+        }
+    }
+
+    pub(crate) fn from_byte_rates(rates: [i32; 3]) -> Self {
+        Self {
+            avg_1s: f64::from(rates[0]),
+            avg_10s: f64::from(rates[1]),
+            avg_60s: f64::from(rates[2]),
+        }
+    }
+}
+
+/// Statistics of an EoE handler.
+///
+/// Rates are given in bytes per second.
+#[derive(Debug, Clone)]
+pub struct EoeHandlerInfo {
+    /// Name of the network interface.
+    pub name: String,
+    pub slave_pos: SlavePos,
+    pub open: bool,
+    pub rx_bytes: u32,
+    pub rx_rate: u32,
+    pub tx_bytes: u32,
+    pub tx_rate: u32,
+    pub tx_queued_frames: u32,
+    pub tx_queue_size: u32,
+}
+
+/// IP parameters of a slave that are set via EoE.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EoeIpParams {
+    pub mac_address: Option<[u8; 6]>,
+    pub ip_address: Option<Ipv4Addr>,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns: Option<Ipv4Addr>,
+    pub hostname: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -99,8 +346,56 @@ pub struct ConfigInfo {
     pub slave_position: Option<SlavePos>,
     pub sdo_count: u32,
     pub idn_count: u32,
-    // TODO: more attributes are returned:
-    // syncs[*], watchdog_*, dc_*
+    /// Configured sync managers.
+    pub syncs: Vec<SmConfigInfo>,
+    pub watchdog_divider: u16,
+    pub watchdog_intervals: u16,
+    pub dc_assign_activate: u16,
+    /// Configuration of the SYNC0 and SYNC1 signals.
+    pub dc_sync: [DcSyncSignal; 2],
+}
+
+/// A PDO assigned to a sync manager of a slave config, with its mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigPdo {
+    pub info: PdoInfo,
+    pub entries: Vec<PdoEntryInfo>,
+}
+
+/// A startup SDO queued in a slave config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigSdo {
+    pub idx: SdoIdx,
+    pub complete_access: bool,
+    pub data: Vec<u8>,
+}
+
+/// An IDN queued in a slave config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIdn {
+    pub drive_no: u8,
+    pub idn: Idn,
+    /// The AL state in which the IDN is written.
+    pub al_state: AlState,
+    pub data: Vec<u8>,
+}
+
+/// Sync manager configuration as applied to a slave config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmConfigInfo {
+    pub idx: SmIdx,
+    pub direction: SyncDirection,
+    pub watchdog_mode: WatchdogMode,
+    pub pdo_count: u32,
+}
+
+/// Cycle and shift time of a DC sync signal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DcSyncSignal {
+    /// Cycle time in ns.
+    pub cycle_time: u32,
+    /// Shift time in ns.
+    pub shift_time: i32,
 }
 
 #[derive(Debug, Clone)]
@@ -115,9 +410,90 @@ pub struct SlaveInfo {
     pub error_flag: u8,
     pub sync_count: u8,
     pub sdo_count: u16,
+    pub boot_rx_mailbox: SiiMailbox,
+    pub boot_tx_mailbox: SiiMailbox,
+    pub std_rx_mailbox: SiiMailbox,
+    pub std_tx_mailbox: SiiMailbox,
+    pub mailbox_protocols: MailboxProtocols,
+    /// CoE details from the SII General category, if present.
+    pub coe_details: Option<CoeDetails>,
+    /// Flags from the SII General category, if present.
+    pub general_flags: Option<GeneralFlags>,
+    pub dc_supported: bool,
+    pub dc_range: DcRange,
+    pub has_dc_system_time: bool,
+    /// Propagation delay from the reference clock in ns.
+    pub transmission_delay: u32,
+    /// Size of the SII contents in words.
+    pub sii_nwords: u32,
+    pub group: String,
+    pub image: String,
+    pub order: String,
     pub ports: [SlavePortInfo; ec::EC_MAX_PORTS as usize],
 }
 
+bitflags! {
+    /// Mailbox protocols supported by a slave.
+    #[derive(Default)]
+    pub struct MailboxProtocols: u16 {
+        /// ADS over EtherCAT
+        const AOE = 0x01;
+        /// Ethernet over EtherCAT
+        const EOE = 0x02;
+        /// CANopen over EtherCAT
+        const COE = 0x04;
+        /// File access over EtherCAT
+        const FOE = 0x08;
+        /// Servo drive profile over EtherCAT
+        const SOE = 0x10;
+        /// Vendor specific protocol over EtherCAT
+        const VOE = 0x20;
+    }
+}
+
+bitflags! {
+    /// CoE features supported by a slave.
+    #[derive(Default)]
+    pub struct CoeDetails: u8 {
+        const SDO = 0x01;
+        const SDO_INFO = 0x02;
+        const PDO_ASSIGN = 0x04;
+        const PDO_CONFIGURATION = 0x08;
+        const UPLOAD_AT_STARTUP = 0x10;
+        const SDO_COMPLETE_ACCESS = 0x20;
+    }
+}
+
+bitflags! {
+    /// General flags from the SII General category.
+    #[derive(Default)]
+    pub struct GeneralFlags: u8 {
+        /// The slave supports the SAFEOP state.
+        const ENABLE_SAFEOP = 0x01;
+        /// The slave does not support LRW datagrams.
+        const ENABLE_NOT_LRW = 0x02;
+    }
+}
+
+/// Width of the slave's DC system time register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DcRange {
+    Bits32,
+    Bits64,
+}
+
+impl TryFrom<u32> for DcRange {
+    type Error = ();
+
+    fn try_from(range: u32) -> std::result::Result<Self, ()> {
+        match range {
+            ec::EC_DC_32 => Ok(DcRange::Bits32),
+            ec::EC_DC_64 => Ok(DcRange::Bits64),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum SlavePortType {
     NotImplemented,
@@ -126,6 +502,20 @@ pub enum SlavePortType {
     MII,
 }
 
+impl TryFrom<u32> for SlavePortType {
+    type Error = ();
+
+    fn try_from(desc: u32) -> std::result::Result<Self, ()> {
+        match desc {
+            ec::EC_PORT_NOT_IMPLEMENTED => Ok(SlavePortType::NotImplemented),
+            ec::EC_PORT_NOT_CONFIGURED => Ok(SlavePortType::NotConfigured),
+            ec::EC_PORT_EBUS => Ok(SlavePortType::EBus),
+            ec::EC_PORT_MII => Ok(SlavePortType::MII),
+            _ => Err(()),
+        }
+    }
+}
+
 impl Default for SlavePortType {
     fn default() -> Self {
         SlavePortType::NotImplemented
@@ -155,20 +545,66 @@ pub struct SlaveConfigState {
     pub al_state: AlState,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// State of a non-blocking request (SDO, register or VoE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestState {
+    /// Not requested.
+    Unused,
+    /// Request is being processed.
+    Busy,
+    /// Request was processed successfully.
+    Success,
+    /// Request processing failed.
+    Error,
+}
+
+/// Header of a vendor-specific mailbox (VoE) message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct VoeHeader {
+    pub vendor_id: u32,
+    pub vendor_type: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SyncDirection {
     Invalid,
     Output,
     Input,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl TryFrom<ec::ec_direction_t> for SyncDirection {
+    type Error = ();
+
+    fn try_from(dir: ec::ec_direction_t) -> std::result::Result<Self, ()> {
+        match dir {
+            ec::EC_DIR_INVALID => Ok(SyncDirection::Invalid),
+            ec::EC_DIR_OUTPUT => Ok(SyncDirection::Output),
+            ec::EC_DIR_INPUT => Ok(SyncDirection::Input),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WatchdogMode {
     Default,
     Enable,
     Disable,
 }
 
+impl TryFrom<ec::ec_watchdog_mode_t> for WatchdogMode {
+    type Error = ();
+
+    fn try_from(mode: ec::ec_watchdog_mode_t) -> std::result::Result<Self, ()> {
+        match mode {
+            ec::EC_WD_DEFAULT => Ok(WatchdogMode::Default),
+            ec::EC_WD_ENABLE => Ok(WatchdogMode::Enable),
+            ec::EC_WD_DISABLE => Ok(WatchdogMode::Disable),
+            _ => Err(()),
+        }
+    }
+}
+
 /// Sync Manager Info
 #[derive(Debug, Copy, Clone)]
 pub struct SmInfo {
@@ -255,6 +691,27 @@ pub struct DomainState {
     pub redundancy_active: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct DomainInfo {
+    pub logical_base_address: u32,
+    pub data_size: usize,
+    /// Working counters of the main device followed by the backup devices.
+    pub working_counters: Vec<u16>,
+    pub expected_working_counter: u16,
+    pub fmmus: Vec<DomainFmmuInfo>,
+}
+
+/// An FMMU configuration mapping a sync manager into a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DomainFmmuInfo {
+    pub slave_config_alias: u16,
+    pub slave_config_position: u16,
+    pub sync_index: SmIdx,
+    pub direction: SyncDirection,
+    pub logical_address: u32,
+    pub data_size: usize,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum WcState {
     Zero = 0,
@@ -279,13 +736,28 @@ fn access(read: u8, write: u8) -> Access {
     }
 }
 
-impl From<u32> for WcState {
-    fn from(st: u32) -> Self {
-        match st {
-            0 => WcState::Zero,
-            1 => WcState::Incomplete,
-            2 => WcState::Complete,
-            x => panic!("invalid state {}", x),
+impl TryFrom<u32> for WcState {
+    type Error = ();
+
+    fn try_from(state: u32) -> std::result::Result<Self, ()> {
+        match state {
+            ec::EC_WC_ZERO => Ok(WcState::Zero),
+            ec::EC_WC_INCOMPLETE => Ok(WcState::Incomplete),
+            ec::EC_WC_COMPLETE => Ok(WcState::Complete),
+            _ => Err(()),
         }
     }
 }
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn prop_kernel_enum_conversions(value: u32) {
+        proptest::prop_assert_eq!(WcState::try_from(value).is_ok(), value <= 2);
+        proptest::prop_assert_eq!(SlavePortType::try_from(value).is_ok(), value <= 3);
+        proptest::prop_assert_eq!(DcRange::try_from(value).is_ok(), value <= 1);
+        proptest::prop_assert_eq!(MasterPhase::try_from(value as u8).is_ok(), value as u8 <= 2);
+        proptest::prop_assert_eq!(SyncDirection::try_from(value).is_ok(), value <= 2);
+        proptest::prop_assert_eq!(WatchdogMode::try_from(value).is_ok(), value <= 2);
+    }
+}